@@ -0,0 +1,81 @@
+//! Typed calls to the server, built from the endpoints of `library::api`
+
+use library::api::{Endpoint, Method};
+use library::{ApiError, ApiErrorCode};
+
+// The endpoints, so calls read `api::call::<api::GetRoom>`
+pub use library::api::*;
+
+/// Where the server listens during development
+const SERVER_URL: &str = "http://127.0.0.1:3000";
+const SERVER_SOCKET_URL: &str = "ws://127.0.0.1:3000";
+
+/// Address of a path on the server, such as `library::api::STEAM_LOGIN_PATH`
+pub fn url(path: &str) -> String {
+    format!("{}{}", SERVER_URL, path)
+}
+
+pub fn socket_url(path: &str) -> String {
+    format!("{}{}", SERVER_SOCKET_URL, path)
+}
+
+/// Calls the endpoint `E` with the session cookie. The request is built right away, so the
+/// returned future borrows nothing and can be spawned.
+pub fn call<E: Endpoint>(
+    client: &reqwest::Client,
+    params: &E::Params,
+    request: &E::Request,
+) -> impl Future<Output = Result<E::Response, ApiError>> + use<E> {
+    let url = url(&E::path(params));
+    let builder = match E::METHOD {
+        Method::Get => client.get(url),
+        Method::Post => client.post(url),
+        Method::Delete => client.delete(url),
+    };
+    let builder = if E::METHOD.has_body() {
+        builder.json(request)
+    } else {
+        builder
+    };
+    let builder = builder.fetch_credentials_include();
+
+    async move {
+        let response = builder.send().await.map_err(request_failed)?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        response
+            .json::<E::Response>()
+            .await
+            .map_err(unreadable_response)
+    }
+}
+
+/// The `ApiError` a failed response carries, or the closest one when it has none, such as
+/// when a proxy answered instead of the server
+async fn api_error(response: reqwest::Response) -> ApiError {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    serde_json::from_str(&body).unwrap_or_else(|_| {
+        let message = if body.is_empty() {
+            status.to_string()
+        } else {
+            body
+        };
+        ApiError::new(ApiErrorCode::from_status(status.as_u16()), message)
+    })
+}
+
+fn request_failed(error: reqwest::Error) -> ApiError {
+    let code = error.status().map_or(ApiErrorCode::Internal, |status| {
+        ApiErrorCode::from_status(status.as_u16())
+    });
+    ApiError::new(code, format!("Request failed: {}", error))
+}
+
+fn unreadable_response(error: reqwest::Error) -> ApiError {
+    ApiError::new(
+        ApiErrorCode::Internal,
+        format!("Failed to parse response: {}", error),
+    )
+}
@@ -1,25 +1,57 @@
 use egui::{ColorImage, Image, TextureHandle};
 use image::load_from_memory;
-use library::{Consultant, CounterResponse, Customer, NewCustomerResponse, Room};
+use library::{
+    ApiError, ApiErrorCode, AppOwnership, ClientMessage, CompatibilityBadge, Consultant,
+    ContentDescriptor, ContentLimits, CounterResponse, CreateRoomRequest, Customer,
+    CustomerPreferences, FreeToPlayCandidate, FreeToPlayRequest, GetCustomerLibraryRequest,
+    JoinRoomRequest, LeaveRoomRequest, LocalLibraryUpload, NewCustomerResponse, PhaseTimers,
+    Platform, Recommendation, RecommendationRequest, RecommendationSort, RefreshOwnershipRequest,
+    ReviewSummary, Role, Room, RoomPhase, RoomRef, RoomResponse, ServerMessage,
+    SetCustomerPreferencesRequest, SetGameInstalledRequest, SetRoomContentLimitsRequest, StaleData,
+};
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
 use std::thread::spawn;
 
+use crate::api;
+use crate::room_socket::RoomSocket;
+
+/// Number of top recommendations whose ownership is re-checked
+const SHORTLIST_SIZE: usize = 10;
+
+/// Seconds between two attempts to (re)connect the room WebSocket
+const ROOM_RECONNECT_DELAY: f64 = 3.0;
+
+/// Chat messages shown in the room section
+const VISIBLE_CHAT_MESSAGES: usize = 20;
+
 #[derive(Clone)]
 enum RequestState {
     Idle,
     Loading,
     Success(u64),
-    Error(String),
+    Error(ApiError),
 }
 
 #[derive(Clone, Serialize, Deserialize)] //
-struct ClientState {
+pub(crate) struct ClientState {
     pub current_room: Option<Room>,
     pub server_counter: Option<u64>,
 
     pub steam_id_str: String,
     pub current_customer: Option<Customer>,
+    pub recommendations: Vec<Recommendation>,
+    pub free_to_play: Vec<FreeToPlayCandidate>,
+    pub shortlist_ownership: Vec<AppOwnership>,
+    /// Last message refused by the room, such as voting before joining
+    #[serde(skip)]
+    pub room_error: Option<String>,
+    /// SteamID64 of the account signed in through Steam
+    #[serde(skip)]
+    pub signed_in: Option<u64>,
+    /// When the server will close the current room for being unused, once it warned us
+    #[serde(skip)]
+    pub room_expires_at: Option<u64>,
 
     #[serde(skip)]
     test_texture: Option<egui::TextureHandle>,
@@ -45,9 +77,50 @@ pub struct SteamDilemmaUi {
 
     #[serde(skip)] // Don't serialize shared client state for async operations
     shared_client_state: Arc<Mutex<ClientState>>,
+
+    #[serde(skip)] // Local Steam files dropped onto the window, waiting to be uploaded
+    local_upload: LocalLibraryUpload,
+
+    installed_by_everyone: bool,
+    include_non_games: bool,
+    recommendation_sort: RecommendationSort,
+    /// Comma separated languages being edited, saved into the customer's preferences
+    languages_text: String,
+    /// Comma separated genres being edited, saved into the customer's preferences
+    genres_text: String,
+    content_limits: ContentLimits,
+    /// Phase timers of the next room created
+    phase_timers: PhaseTimers,
+    /// SteamID64 used to join the room
+    join_steam_id: String,
+
+    #[serde(skip)] // Live connection to the current room
+    room_socket: Option<RoomSocket>,
+
+    #[serde(skip)] // Time of the last connection attempt, to retry at a steady pace
+    last_connect_attempt: Option<f64>,
+
+    #[serde(skip)]
+    chat_text: String,
+
+    #[serde(skip)] // Whether we asked the server who is signed in yet
+    session_checked: bool,
+
+    #[serde(skip)]
+    room_code_text: String,
+
+    #[serde(skip)] // Password given when joining a protected room
+    join_password: String,
+
+    #[serde(skip)] // Password the host is about to set
+    room_password_text: String,
+
+    #[serde(skip)] // Opened through a spectator link, so the room is only shown
+    spectating: bool,
 }
 
-fn parse_room_id_from_url() -> Option<u64> {
+/// The room in the page's link: `?room=<code>` from invite links, or `?room_id=<id>`
+fn parse_room_id_from_url() -> Option<RoomRef> {
     #[cfg(target_arch = "wasm32")]
     {
         let window = web_sys::window()?;
@@ -55,14 +128,52 @@ fn parse_room_id_from_url() -> Option<u64> {
         let url = url::Url::parse(&location_href).ok()?;
 
         for (key, value) in url.query_pairs() {
-            if key == "room_id" {
-                return value.parse().ok();
+            if key == "room" || key == "room_id" {
+                return RoomRef::parse(&value);
             }
         }
     }
     None
 }
 
+/// Whether the page's link is a spectator link, see `Room::spectator_link`
+fn is_spectator_link() -> bool {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let href = web_sys::window().and_then(|window| window.location().href().ok());
+        if let Some(url) = href.and_then(|href| url::Url::parse(&href).ok()) {
+            return url.query_pairs().any(|(key, _)| key == "spectate");
+        }
+    }
+    false
+}
+
+/// Seconds since the Unix epoch, to count down phase timers
+fn unix_now() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        (web_sys::js_sys::Date::now() / 1000.0) as u64
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
+
+/// The page's address without its query, which invite links are built on
+fn page_url() -> String {
+    #[cfg(target_arch = "wasm32")]
+    {
+        if let Some(window) = web_sys::window() {
+            let location = window.location();
+            if let (Ok(origin), Ok(path)) = (location.origin(), location.pathname()) {
+                return format!("{}{}", origin, path);
+            }
+        }
+    }
+    "http://127.0.0.1:3000/".to_owned()
+}
+
 async fn download_image(url: &str) -> Result<image::DynamicImage, Box<dyn std::error::Error>> {
     let resp = reqwest::get(url).await?;
     let bytes = resp.bytes().await?;
@@ -81,146 +192,435 @@ fn image_to_color_image(img: &image::DynamicImage) -> egui::ColorImage {
 }
 async fn send_get_customer_library_request(
     client: reqwest::Client,
+    steam_id: String,
     request_state: Arc<Mutex<RequestState>>,
     shared_client_state: Arc<Mutex<ClientState>>,
     ctx: egui::Context,
 ) {
-    let response_result = client
-        .post("http://127.0.0.1:3000/api/get_customer_library")
-        .json("76561199101214612")
-        .send()
-        .await;
+    let request = GetCustomerLibraryRequest { steam_id };
+    let result = api::call::<api::GetCustomerLibrary>(&client, &(), &request).await;
 
-    handle_get_customer_library_response(response_result, request_state, shared_client_state, ctx)
-        .await;
+    handle_get_customer_library_response(result, request_state, shared_client_state, ctx).await;
 }
 
 async fn handle_get_customer_library_response(
-    response_result: Result<reqwest::Response, reqwest::Error>,
+    result: Result<NewCustomerResponse, ApiError>,
     request_state: Arc<Mutex<RequestState>>,
     shared_client_state: Arc<Mutex<ClientState>>,
     ctx: egui::Context,
 ) {
-    match response_result {
-        Ok(response) => {
-            handle_successful_custom_library_response(
-                response,
-                request_state,
-                shared_client_state,
-                ctx,
-            )
-            .await;
+    match result {
+        Ok(customer_response) => {
+            handle_successful_custom_library_response(customer_response, shared_client_state, ctx)
+                .await;
         }
-        Err(e) => {
-            update_request_state_error(&request_state, format!("Request failed: {}", e));
+        Err(error) => {
+            update_request_state_error(&request_state, error);
             ctx.request_repaint();
         }
     }
 }
 
-async fn send_increment_request(
+async fn send_upload_local_library_request(
     client: reqwest::Client,
+    upload: LocalLibraryUpload,
     request_state: Arc<Mutex<RequestState>>,
     shared_client_state: Arc<Mutex<ClientState>>,
     ctx: egui::Context,
 ) {
-    let response_result = client
-        .post("http://127.0.0.1:3000/api/increment")
-        .send()
-        .await;
+    let result = api::call::<api::UploadLocalLibrary>(&client, &(), &upload).await;
 
-    handle_increment_response(response_result, request_state, shared_client_state, ctx).await;
+    handle_get_customer_library_response(result, request_state, shared_client_state, ctx).await;
 }
 
-async fn handle_increment_response(
-    response_result: Result<reqwest::Response, reqwest::Error>,
+/// Sorts a file dropped onto the window into the matching slot of the upload, based on
+/// the names the Steam client gives them.
+fn add_local_steam_file(
+    upload: &mut LocalLibraryUpload,
+    file_name: &str,
+    contents: String,
+) -> bool {
+    let file_name = file_name.to_lowercase();
+
+    if file_name.ends_with("libraryfolders.vdf") {
+        upload.library_folders = Some(contents);
+    } else if file_name.ends_with("localconfig.vdf") {
+        upload.local_config = Some(contents);
+    } else if file_name.starts_with("appmanifest_") && file_name.ends_with(".acf") {
+        upload.app_manifests.push(contents);
+    } else {
+        return false;
+    }
+    true
+}
+
+async fn send_set_game_installed_request(
+    client: reqwest::Client,
+    request: SetGameInstalledRequest,
+    request_state: Arc<Mutex<RequestState>>,
+    ctx: egui::Context,
+) {
+    if let Err(error) = api::call::<api::SetGameInstalled>(&client, &(), &request).await {
+        update_request_state_error(&request_state, error);
+    }
+    ctx.request_repaint();
+}
+
+async fn send_set_customer_preferences_request(
+    client: reqwest::Client,
+    request: SetCustomerPreferencesRequest,
+    request_state: Arc<Mutex<RequestState>>,
+    ctx: egui::Context,
+) {
+    if let Err(error) = api::call::<api::SetCustomerPreferences>(&client, &(), &request).await {
+        update_request_state_error(&request_state, error);
+    }
+    ctx.request_repaint();
+}
+
+async fn send_free_to_play_request(
+    client: reqwest::Client,
+    request: FreeToPlayRequest,
     request_state: Arc<Mutex<RequestState>>,
     shared_client_state: Arc<Mutex<ClientState>>,
     ctx: egui::Context,
 ) {
-    match response_result {
-        Ok(response) => {
-            handle_successful_response(response, request_state, shared_client_state, ctx).await;
-        }
-        Err(e) => {
-            update_request_state_error(&request_state, format!("Request failed: {}", e));
-            ctx.request_repaint();
+    match api::call::<api::GetFreeToPlay>(&client, &(), &request).await {
+        Ok(free_to_play_response) => {
+            if let Ok(mut client_state) = shared_client_state.lock() {
+                client_state.free_to_play = free_to_play_response.candidates;
+            }
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Idle;
+            }
         }
+        Err(error) => update_request_state_error(&request_state, error),
     }
+    ctx.request_repaint();
 }
 
-async fn handle_successful_custom_library_response(
-    response: reqwest::Response,
+async fn send_refresh_ownership_request(
+    client: reqwest::Client,
+    request: RefreshOwnershipRequest,
     request_state: Arc<Mutex<RequestState>>,
     shared_client_state: Arc<Mutex<ClientState>>,
     ctx: egui::Context,
 ) {
-    match response.json::<NewCustomerResponse>().await {
-        Ok(customer_response) => {
-            // update_request_state_success(&request_state, counter_response.counter_value);
-            // update_client_state_counter(&shared_client_state, counter_response.counter_value);
-
+    match api::call::<api::RefreshOwnership>(&client, &(), &request).await {
+        Ok(ownership_response) => {
             if let Ok(mut client_state) = shared_client_state.lock() {
-                log::debug!("{:?}", customer_response.customer);
-                client_state.current_customer = Some(customer_response.customer);
+                client_state.shortlist_ownership = ownership_response.ownership;
             }
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Idle;
+            }
+        }
+        Err(error) => update_request_state_error(&request_state, error),
+    }
+    ctx.request_repaint();
+}
 
-            let proxy_url = format!(
-                "https://corsproxy.io/?{}",
-                urlencoding::encode("https://media.steampowered.com/steamcommunity/public/images/apps/920210/568a07f03f8dba0b74c4a02f6ebb43ce5e09075c.jpg")
-            );
+/// Sends any room request answered with a `RoomResponse` (create, get, join, leave) and keeps
+/// the returned room as the current one
+async fn send_room_request(
+    request: impl Future<Output = Result<RoomResponse, ApiError>>,
+    request_state: Arc<Mutex<RequestState>>,
+    shared_client_state: Arc<Mutex<ClientState>>,
+    ctx: egui::Context,
+) {
+    match request.await {
+        Ok(room_response) => {
+            if let Ok(mut client_state) = shared_client_state.lock() {
+                client_state.current_room = Some(room_response.room);
+            }
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Idle;
+            }
+        }
+        Err(error) => update_request_state_error(&request_state, error),
+    }
+    ctx.request_repaint();
+}
 
-            log::debug!("proxy_url: {}", proxy_url);
+/// Asks who is signed in through Steam, if anyone
+async fn send_session_request(
+    client: reqwest::Client,
+    shared_client_state: Arc<Mutex<ClientState>>,
+    ctx: egui::Context,
+) {
+    let signed_in = api::call::<api::GetSession>(&client, &(), &())
+        .await
+        .ok()
+        .map(|session| session.steam_id);
+    if let Ok(mut client_state) = shared_client_state.lock() {
+        client_state.signed_in = signed_in;
+    }
+    ctx.request_repaint();
+}
 
-            let mut new_test_texture: Option<egui::TextureHandle> = None;
+async fn send_logout_request(
+    client: reqwest::Client,
+    shared_client_state: Arc<Mutex<ClientState>>,
+    ctx: egui::Context,
+) {
+    match api::call::<api::Logout>(&client, &(), &()).await {
+        Ok(()) => {
+            if let Ok(mut client_state) = shared_client_state.lock() {
+                client_state.signed_in = None;
+            }
+        }
+        Err(error) => log::warn!("Sign out failed: {}", error),
+    }
+    ctx.request_repaint();
+}
 
-            let url = proxy_url;//"https://media.steampowered.com/steamcommunity/public/images/apps/920210/568a07f03f8dba0b74c4a02f6ebb43ce5e09075c.jpg";
-            if let Ok(image) = download_image(url.as_str()).await {
-                let color_image = image_to_color_image(&image);
-                new_test_texture = Some(ctx.load_texture("my_image", color_image, egui::TextureOptions::default()));
+async fn send_close_room_request(
+    client: reqwest::Client,
+    room_id: u64,
+    request_state: Arc<Mutex<RequestState>>,
+    ctx: egui::Context,
+) {
+    match api::call::<api::CloseRoom>(&client, &room_id, &()).await {
+        Ok(()) => {
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Idle;
             }
+        }
+        Err(error) => update_request_state_error(&request_state, error),
+    }
+    ctx.request_repaint();
+}
+
+async fn send_set_room_content_limits_request(
+    client: reqwest::Client,
+    request: SetRoomContentLimitsRequest,
+    request_state: Arc<Mutex<RequestState>>,
+    ctx: egui::Context,
+) {
+    if let Err(error) = api::call::<api::SetRoomContentLimits>(&client, &(), &request).await {
+        update_request_state_error(&request_state, error);
+    }
+    ctx.request_repaint();
+}
 
+async fn send_recommendations_request(
+    client: reqwest::Client,
+    request: RecommendationRequest,
+    request_state: Arc<Mutex<RequestState>>,
+    shared_client_state: Arc<Mutex<ClientState>>,
+    ctx: egui::Context,
+) {
+    match api::call::<api::GetRecommendations>(&client, &(), &request).await {
+        Ok(recommendations_response) => {
             if let Ok(mut client_state) = shared_client_state.lock() {
-                client_state.test_texture = new_test_texture;
+                client_state.recommendations = recommendations_response.recommendations;
+            }
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Idle;
             }
-
-            ctx.request_repaint();
-        }
-        Err(e) => {
-            update_request_state_error(&request_state, format!("Failed to parse response: {}", e));
-            ctx.request_repaint();
         }
+        Err(error) => update_request_state_error(&request_state, error),
+    }
+    ctx.request_repaint();
+}
+
+fn split_comma_list(text: &str) -> Vec<String> {
+    text.split(',')
+        .map(|item| item.trim().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn platform_label(platform: Platform) -> &'static str {
+    match platform {
+        Platform::Windows => "Windows",
+        Platform::Mac => "macOS",
+        Platform::Linux => "Linux",
+        Platform::SteamDeck => "Steam Deck",
+    }
+}
+
+fn content_descriptor_label(descriptor: ContentDescriptor) -> &'static str {
+    match descriptor {
+        ContentDescriptor::SomeNudityOrSexualContent => "Some nudity or sexual content",
+        ContentDescriptor::FrequentViolenceOrGore => "Frequent violence or gore",
+        ContentDescriptor::AdultOnlySexualContent => "Adult only sexual content",
+        ContentDescriptor::FrequentNudityOrSexualContent => "Frequent nudity or sexual content",
+        ContentDescriptor::GeneralMatureContent => "General mature content",
+    }
+}
+
+fn render_review_summary(ui: &mut egui::Ui, reviews: Option<&ReviewSummary>) {
+    let Some(reviews) = reviews else {
+        return;
+    };
+    if reviews.total_reviews == 0 {
+        return;
+    }
+
+    let color = match reviews.review_score {
+        7..=9 => egui::Color32::LIGHT_BLUE,
+        5..=6 => egui::Color32::YELLOW,
+        _ => egui::Color32::LIGHT_RED,
+    };
+    ui.colored_label(
+        color,
+        format!(
+            "{} ({:.0}% of {})",
+            reviews.description,
+            reviews.positive_ratio * 100.0,
+            reviews.total_reviews
+        ),
+    );
+}
+
+/// Warns that Steam couldn't be reached and shows how old the cached data is
+fn render_stale_notice(ui: &mut egui::Ui, stale: Option<StaleData>) {
+    if let Some(stale) = stale {
+        let minutes = stale.age_secs / 60;
+        let age = if minutes < 60 {
+            format!("{} min", minutes)
+        } else if minutes < 24 * 60 {
+            format!("{} h", minutes / 60)
+        } else {
+            format!("{} days", minutes / (24 * 60))
+        };
+        ui.colored_label(
+            egui::Color32::GRAY,
+            format!("Steam unavailable, showing data from {} ago", age),
+        );
+    }
+}
+
+fn render_compatibility_badge(ui: &mut egui::Ui, steam_name: &str, badge: CompatibilityBadge) {
+    let (color, text) = match badge {
+        CompatibilityBadge::Native => (egui::Color32::GREEN, "native"),
+        CompatibilityBadge::Verified => (egui::Color32::GREEN, "verified"),
+        CompatibilityBadge::Playable => (egui::Color32::YELLOW, "playable"),
+        CompatibilityBadge::Unsupported => (egui::Color32::RED, "unsupported"),
+        CompatibilityBadge::Unknown => (egui::Color32::GRAY, "unknown"),
+    };
+    ui.colored_label(color, format!("{}: {}", steam_name, text));
+}
+
+/// Formats a unix timestamp as `YYYY-MM-DD` (UTC), using Howard Hinnant's civil_from_days
+fn format_date(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+
+    let bytes = bytes as f64;
+    if bytes >= GB {
+        format!("{:.1} GB", bytes / GB)
+    } else {
+        format!("{:.0} MB", bytes / MB)
     }
 }
 
-async fn handle_successful_response(
-    response: reqwest::Response,
+async fn send_increment_request(
+    client: reqwest::Client,
+    request_state: Arc<Mutex<RequestState>>,
+    shared_client_state: Arc<Mutex<ClientState>>,
+    ctx: egui::Context,
+) {
+    let result = api::call::<api::Increment>(&client, &(), &()).await;
+
+    handle_increment_response(result, request_state, shared_client_state, ctx).await;
+}
+
+async fn handle_increment_response(
+    result: Result<CounterResponse, ApiError>,
     request_state: Arc<Mutex<RequestState>>,
     shared_client_state: Arc<Mutex<ClientState>>,
     ctx: egui::Context,
 ) {
-    match response.json::<CounterResponse>().await {
+    match result {
         Ok(counter_response) => {
-            update_request_state_success(&request_state, counter_response.counter_value);
-            update_client_state_counter(&shared_client_state, counter_response.counter_value);
-            ctx.request_repaint();
+            handle_successful_response(counter_response, request_state, shared_client_state, ctx);
         }
-        Err(e) => {
-            update_request_state_error(&request_state, format!("Failed to parse response: {}", e));
+        Err(error) => {
+            update_request_state_error(&request_state, error);
             ctx.request_repaint();
         }
     }
 }
 
+async fn handle_successful_custom_library_response(
+    customer_response: NewCustomerResponse,
+    shared_client_state: Arc<Mutex<ClientState>>,
+    ctx: egui::Context,
+) {
+    // update_request_state_success(&request_state, counter_response.counter_value);
+    // update_client_state_counter(&shared_client_state, counter_response.counter_value);
+
+    if let Ok(mut client_state) = shared_client_state.lock() {
+        log::debug!("{:?}", customer_response.customer);
+        client_state.current_customer = Some(customer_response.customer);
+    }
+
+    let proxy_url = format!(
+        "https://corsproxy.io/?{}",
+        urlencoding::encode(
+            "https://media.steampowered.com/steamcommunity/public/images/apps/920210/568a07f03f8dba0b74c4a02f6ebb43ce5e09075c.jpg"
+        )
+    );
+
+    log::debug!("proxy_url: {}", proxy_url);
+
+    let mut new_test_texture: Option<egui::TextureHandle> = None;
+
+    let url = proxy_url; //"https://media.steampowered.com/steamcommunity/public/images/apps/920210/568a07f03f8dba0b74c4a02f6ebb43ce5e09075c.jpg";
+    if let Ok(image) = download_image(url.as_str()).await {
+        let color_image = image_to_color_image(&image);
+        new_test_texture =
+            Some(ctx.load_texture("my_image", color_image, egui::TextureOptions::default()));
+    }
+
+    if let Ok(mut client_state) = shared_client_state.lock() {
+        client_state.test_texture = new_test_texture;
+    }
+
+    ctx.request_repaint();
+}
+
+fn handle_successful_response(
+    counter_response: CounterResponse,
+    request_state: Arc<Mutex<RequestState>>,
+    shared_client_state: Arc<Mutex<ClientState>>,
+    ctx: egui::Context,
+) {
+    update_request_state_success(&request_state, counter_response.counter_value);
+    update_client_state_counter(&shared_client_state, counter_response.counter_value);
+    ctx.request_repaint();
+}
+
 fn update_request_state_success(request_state: &Arc<Mutex<RequestState>>, counter_value: u64) {
     if let Ok(mut state) = request_state.lock() {
         *state = RequestState::Success(counter_value);
     }
 }
 
-fn update_request_state_error(request_state: &Arc<Mutex<RequestState>>, error_message: String) {
+fn update_request_state_error(request_state: &Arc<Mutex<RequestState>>, error: ApiError) {
     if let Ok(mut state) = request_state.lock() {
-        *state = RequestState::Error(error_message);
+        *state = RequestState::Error(error);
     }
 }
 
@@ -252,60 +652,495 @@ fn render_room_info(ui: &mut egui::Ui, label: &mut String) {
     ui.add_space(10.0);
 }
 
-fn render_server_counter_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamDilemmaUi) {
+fn render_room_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamDilemmaUi) {
     ui.separator();
-    ui.heading("Server Counter");
+    ui.heading("Room");
 
-    let can_send_request = get_can_send_request_status(&app.request_state);
+    render_sign_in(ui, ctx, app);
 
-    ui.add_enabled_ui(can_send_request, |ui| {
-        if ui.button("Increment Server").clicked() {
-            app.increment_server_counter(ctx);
-        }
-    });
+    let can_send_request = get_can_send_request_status(&app.request_state);
 
-    render_request_status(ui, &app.request_state);
-    render_client_state_counter(ui, app.client_state.server_counter);
-}
+    let Some(room_id) = app.room_id else {
+        let signed_in = app.client_state.signed_in.is_some();
+        ui.add_enabled_ui(can_send_request && signed_in, |ui| {
+            if ui
+                .button("Create room")
+                .on_disabled_hover_text("Sign in through Steam to host a room")
+                .clicked()
+            {
+                app.create_room(ctx);
+            }
+        });
+        render_phase_timers(ui, &mut app.phase_timers);
+        ui.horizontal(|ui| {
+            ui.label("Room code:");
+            ui.text_edit_singleline(&mut app.room_code_text);
+            let room = RoomRef::parse(&app.room_code_text);
+            if ui
+                .add_enabled(
+                    can_send_request && room.is_some(),
+                    egui::Button::new("Open"),
+                )
+                .clicked()
+                && let Some(room) = room
+            {
+                app.open_room(ctx, room);
+            }
+        });
+        return;
+    };
 
-fn get_can_send_request_status(request_state: &Arc<Mutex<RequestState>>) -> bool {
-    if let Ok(state) = request_state.lock() {
-        !matches!(*state, RequestState::Loading)
+    if let Some(room) = &app.client_state.current_room
+        && room.id == room_id
+        && !room.code.is_empty()
+    {
+        let invite_link = room.invite_link(&page_url());
+        ui.horizontal(|ui| {
+            ui.label("Room code:");
+            ui.heading(&room.code);
+            if ui
+                .button("Copy invite link")
+                .on_hover_text(&invite_link)
+                .clicked()
+            {
+                ctx.copy_text(invite_link);
+            }
+            let spectator_link = room.spectator_link(&page_url());
+            if ui
+                .button("Copy spectator link")
+                .on_hover_text("Opens the room read-only, such as for a stream's chat")
+                .clicked()
+            {
+                ctx.copy_text(spectator_link);
+            }
+        });
     } else {
-        false
+        ui.label(format!("Invite friends with ?room_id={}", room_id));
     }
-}
 
-fn render_request_status(ui: &mut egui::Ui, request_state: &Arc<Mutex<RequestState>>) {
-    if let Ok(state) = request_state.lock() {
-        match &*state {
-            RequestState::Idle => {
-                ui.colored_label(egui::Color32::GRAY, "Ready to increment server counter");
+    let mut moderation = None;
+    match &app.client_state.current_room {
+        Some(room) if room.id == room_id => {
+            let is_host = room.is_host(app.client_state.signed_in);
+            if room.customers.is_empty() {
+                ui.label("Nobody joined yet");
             }
-            RequestState::Loading => {
-                ui.colored_label(egui::Color32::YELLOW, "Sending request...");
+            for member in &room.customers {
+                ui.horizontal(|ui| {
+                    ui.label(&member.steam_name);
+                    if member.steam_id.is_some() && member.steam_id == room.host {
+                        ui.strong("host");
+                    }
+                    if member.verified {
+                        ui.colored_label(egui::Color32::GREEN, "✔")
+                            .on_hover_text("Signed in through Steam");
+                    }
+                    ui.weak(format!("{} games", member.games.len()));
+                    render_stale_notice(ui, member.stale);
+                    if is_host && member.steam_id != room.host {
+                        if let Some(steam_id) = member.steam_id
+                            && ui.small_button("Make host").clicked()
+                        {
+                            moderation = Some(ClientMessage::TransferHost { steam_id });
+                        }
+                        render_kick_buttons(ui, member, &mut moderation);
+                    }
+                });
             }
-            RequestState::Success(counter_value) => {
+            if !room.spectators.is_empty() {
+                ui.label("Watching:");
+            }
+            for spectator in &room.spectators {
                 ui.horizontal(|ui| {
-                    ui.label("Last response:");
-                    ui.colored_label(egui::Color32::GREEN, format!("{}", counter_value));
+                    ui.label(&spectator.steam_name);
+                    if is_host {
+                        if ui.small_button("Promote").clicked() {
+                            moderation = Some(ClientMessage::Promote {
+                                steam_id: spectator.steam_id,
+                                steam_name: spectator.steam_name.clone(),
+                            });
+                        }
+                        render_kick_buttons(ui, spectator, &mut moderation);
+                    }
                 });
             }
-            RequestState::Error(error) => {
-                ui.colored_label(egui::Color32::RED, format!("Error: {}", error));
+            if is_host {
+                ui.horizontal(|ui| {
+                    let mut locked = room.locked;
+                    if ui
+                        .checkbox(&mut locked, "Locked")
+                        .on_hover_text("Nobody new can join")
+                        .changed()
+                    {
+                        moderation = Some(ClientMessage::Lock { locked });
+                    }
+                    ui.label("Password:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut app.room_password_text)
+                            .password(true)
+                            .desired_width(120.0),
+                    );
+                    if ui.button("Set").clicked() && !app.room_password_text.is_empty() {
+                        moderation = Some(ClientMessage::SetPassword {
+                            password: Some(std::mem::take(&mut app.room_password_text)),
+                        });
+                    }
+                    if room.password_protected && ui.button("Remove").clicked() {
+                        moderation = Some(ClientMessage::SetPassword { password: None });
+                    }
+                });
+            } else if room.locked {
+                ui.weak("The host locked the room");
             }
         }
+        _ => {
+            ui.label("Room not loaded");
+        }
+    }
+    if let Some(message) = moderation {
+        app.send_room_message(&message);
     }
-}
 
-fn render_client_state_counter(ui: &mut egui::Ui, server_counter: Option<u64>) {
-    if let Some(counter) = server_counter {
+    render_room_live_section(ui, app);
+
+    ui.horizontal(|ui| {
+        ui.label("Steam ID:");
+        ui.text_edit_singleline(&mut app.join_steam_id);
+    });
+    if app
+        .client_state
+        .current_room
+        .as_ref()
+        .is_some_and(|room| room.password_protected)
+    {
         ui.horizontal(|ui| {
-            ui.label("Client state counter:");
-            ui.colored_label(egui::Color32::BLUE, format!("{}", counter));
+            ui.label("Room password:");
+            ui.add(egui::TextEdit::singleline(&mut app.join_password).password(true));
         });
     }
-}
+
+    ui.add_enabled_ui(can_send_request, |ui| {
+        ui.horizontal(|ui| {
+            let accepts_members = app
+                .client_state
+                .current_room
+                .as_ref()
+                .is_none_or(|room| room.phase.accepts_members());
+            if ui
+                .add_enabled(accepts_members, egui::Button::new("Join with this library"))
+                .clicked()
+                && !app.join_steam_id.is_empty()
+            {
+                app.join_room(ctx, room_id, false);
+                // Reconnect as the new member
+                app.room_socket = None;
+            }
+            if ui.button("Watch").clicked() && !app.join_steam_id.is_empty() {
+                app.join_room(ctx, room_id, true);
+            }
+            if ui.button("Leave").clicked() {
+                app.leave_room(ctx, room_id);
+            }
+            if ui.button("Refresh").clicked() {
+                app.get_room(ctx, room_id);
+            }
+            if ui.button("Close room").clicked() {
+                app.close_room(ctx, room_id);
+            }
+        });
+    });
+}
+
+fn render_kick_buttons(
+    ui: &mut egui::Ui,
+    customer: &Customer,
+    moderation: &mut Option<ClientMessage>,
+) {
+    let kick = |ban| ClientMessage::Kick {
+        steam_id: customer.steam_id,
+        steam_name: customer.steam_name.clone(),
+        ban,
+    };
+    if ui.small_button("Kick").clicked() {
+        *moderation = Some(kick(false));
+    }
+    if customer.steam_id.is_some()
+        && ui
+            .small_button("Ban")
+            .on_hover_text("Kick and keep them out of this room")
+            .clicked()
+    {
+        *moderation = Some(kick(true));
+    }
+}
+
+/// Optional timers for the phases of the room about to be created
+fn render_phase_timers(ui: &mut egui::Ui, timers: &mut PhaseTimers) {
+    let phases = [
+        ("Loading libraries", &mut timers.loading_libraries_secs),
+        ("Nominating", &mut timers.nominating_secs),
+        ("Voting", &mut timers.voting_secs),
+    ];
+    ui.horizontal(|ui| {
+        ui.label("Phase timers:");
+        for (name, secs) in phases {
+            let mut timed = secs.is_some();
+            ui.checkbox(&mut timed, name);
+            if timed {
+                let mut minutes = secs.map_or(5, |secs| secs / 60);
+                ui.add(
+                    egui::DragValue::new(&mut minutes)
+                        .range(1..=120)
+                        .suffix(" min"),
+                );
+                *secs = Some(minutes * 60);
+            } else {
+                *secs = None;
+            }
+        }
+    });
+}
+
+fn render_sign_in(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamDilemmaUi) {
+    ui.horizontal(|ui| match app.client_state.signed_in {
+        Some(steam_id) => {
+            ui.label(format!("Signed in through Steam as {}", steam_id));
+            if ui.button("Sign out").clicked() {
+                app.logout(ctx);
+            }
+        }
+        None => {
+            ui.hyperlink_to("Sign in through Steam", api::url(api::STEAM_LOGIN_PATH));
+            ui.weak("to host a room or change your preferences");
+        }
+    });
+}
+
+/// Nominations, votes, decision and chat, shared live over the room WebSocket
+fn render_room_live_section(ui: &mut egui::Ui, app: &mut SteamDilemmaUi) {
+    let connected = app.room_socket.as_ref().is_some_and(RoomSocket::is_open);
+    if !connected {
+        ui.weak("Connecting to the room...");
+    }
+    if let Some(error) = &app.client_state.room_error {
+        ui.colored_label(egui::Color32::RED, error);
+    }
+    if let Some(expires_at) = app.client_state.room_expires_at {
+        let left = expires_at.saturating_sub(unix_now());
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!(
+                "This room closes in {}:{:02} unless something happens in it",
+                left / 60,
+                left % 60
+            ),
+        );
+        ui.ctx()
+            .request_repaint_after(std::time::Duration::from_secs(1));
+    }
+    let Some(room) = &app.client_state.current_room else {
+        return;
+    };
+
+    let mut to_send = None;
+    let only_watching = app.only_watching();
+    let is_host = !only_watching && room.is_host(app.client_state.signed_in);
+
+    ui.horizontal(|ui| {
+        ui.strong(format!("Phase: {}", room.phase.label()));
+        if let Some(deadline) = room.phase_deadline {
+            let left = deadline.saturating_sub(unix_now());
+            ui.label(format!("{}:{:02} left", left / 60, left % 60));
+            ui.ctx()
+                .request_repaint_after(std::time::Duration::from_secs(1));
+        }
+    });
+    ui.weak(match room.phase {
+        RoomPhase::Lobby => "Waiting for everyone to join",
+        RoomPhase::LoadingLibraries => "Reading everyone's libraries, recommendations are coming",
+        RoomPhase::Nominating => "Nominate games from the recommendations",
+        RoomPhase::Voting => "Vote for one of the nominated games",
+        RoomPhase::Decided | RoomPhase::Closed => "",
+    });
+
+    if is_host {
+        ui.add_enabled_ui(connected, |ui| {
+            ui.horizontal(|ui| {
+                if let Some(next) = room
+                    .phase
+                    .next()
+                    .filter(|next| !matches!(next, RoomPhase::Decided | RoomPhase::Closed))
+                    && ui.button(format!("Move on to {}", next.label())).clicked()
+                {
+                    to_send = Some(ClientMessage::SetPhase { phase: next });
+                }
+                ui.menu_button("Go to phase", |ui| {
+                    for phase in [
+                        RoomPhase::Lobby,
+                        RoomPhase::LoadingLibraries,
+                        RoomPhase::Nominating,
+                        RoomPhase::Voting,
+                    ] {
+                        if phase != room.phase && ui.button(phase.label()).clicked() {
+                            to_send = Some(ClientMessage::SetPhase { phase });
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+        });
+    }
+
+    if let Some(decision) = room.decision {
+        let name = room
+            .nominations
+            .iter()
+            .find(|n| n.app_id == decision)
+            .map_or_else(|| decision.to_string(), |n| n.name.clone());
+        ui.colored_label(egui::Color32::GREEN, format!("Tonight we play {}", name));
+    }
+
+    for nomination in &room.nominations {
+        ui.horizontal(|ui| {
+            ui.label(&nomination.name);
+            ui.weak(format!(
+                "nominated by {}",
+                nomination.nominated_by.steam_name
+            ));
+            ui.label(format!("{} votes", nomination.votes.len()));
+            if room.phase == RoomPhase::Voting && !only_watching {
+                ui.add_enabled_ui(connected, |ui| {
+                    if ui.button("Vote").clicked() {
+                        to_send = Some(ClientMessage::Vote {
+                            app_id: nomination.app_id,
+                        });
+                    }
+                    if is_host && ui.button("Decide").clicked() {
+                        to_send = Some(ClientMessage::Decide {
+                            app_id: nomination.app_id,
+                        });
+                    }
+                });
+            }
+        });
+    }
+
+    let skipped = room.chat.len().saturating_sub(VISIBLE_CHAT_MESSAGES);
+    for message in &room.chat[skipped..] {
+        ui.label(format!("{}: {}", message.from, message.text));
+    }
+    if only_watching {
+        ui.weak("You're watching, so you can't nominate, vote or chat");
+    } else {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut app.chat_text);
+            if ui
+                .add_enabled(connected, egui::Button::new("Send"))
+                .clicked()
+                && !app.chat_text.trim().is_empty()
+            {
+                to_send = Some(ClientMessage::Chat {
+                    text: std::mem::take(&mut app.chat_text),
+                });
+            }
+        });
+    }
+
+    if let Some(message) = to_send {
+        app.send_room_message(&message);
+    }
+}
+
+/// What spectator links show: the room's public view, who is in it and its phase, nothing to act on
+fn render_spectator_layout(ui: &mut egui::Ui, app: &mut SteamDilemmaUi) {
+    ui.separator();
+    match &app.client_state.current_room {
+        Some(room) => {
+            ui.horizontal(|ui| {
+                ui.heading(format!("Room {}", room.code));
+                ui.weak("spectating");
+            });
+            let members: Vec<&str> = room
+                .customers
+                .iter()
+                .map(|member| member.steam_name.as_str())
+                .collect();
+            if members.is_empty() {
+                ui.label("Nobody joined yet");
+            } else {
+                ui.label(format!("Playing: {}", members.join(", ")));
+            }
+        }
+        None => {
+            ui.label("Room not loaded");
+        }
+    }
+    render_room_live_section(ui, app);
+}
+
+fn render_server_counter_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamDilemmaUi) {
+    ui.separator();
+    ui.heading("Server Counter");
+
+    let can_send_request = get_can_send_request_status(&app.request_state);
+
+    ui.add_enabled_ui(can_send_request, |ui| {
+        if ui.button("Increment Server").clicked() {
+            app.increment_server_counter(ctx);
+        }
+    });
+
+    render_request_status(ui, &app.request_state);
+    render_client_state_counter(ui, app.client_state.server_counter);
+}
+
+fn get_can_send_request_status(request_state: &Arc<Mutex<RequestState>>) -> bool {
+    if let Ok(state) = request_state.lock() {
+        !matches!(*state, RequestState::Loading)
+    } else {
+        false
+    }
+}
+
+fn render_request_status(ui: &mut egui::Ui, request_state: &Arc<Mutex<RequestState>>) {
+    if let Ok(state) = request_state.lock() {
+        match &*state {
+            RequestState::Idle => {
+                ui.colored_label(egui::Color32::GRAY, "Ready to increment server counter");
+            }
+            RequestState::Loading => {
+                ui.colored_label(egui::Color32::YELLOW, "Sending request...");
+            }
+            RequestState::Success(counter_value) => {
+                ui.horizontal(|ui| {
+                    ui.label("Last response:");
+                    ui.colored_label(egui::Color32::GREEN, format!("{}", counter_value));
+                });
+            }
+            RequestState::Error(error) => {
+                let details: Vec<String> = error
+                    .details
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                ui.colored_label(egui::Color32::RED, format!("Error: {}", error.message))
+                    .on_hover_text(format!("{:?} {}", error.code, details.join(", ")));
+                if error.code == ApiErrorCode::Unauthorized {
+                    ui.weak("Sign in through Steam at the top of the room section");
+                }
+            }
+        }
+    }
+}
+
+fn render_client_state_counter(ui: &mut egui::Ui, server_counter: Option<u64>) {
+    if let Some(counter) = server_counter {
+        ui.horizontal(|ui| {
+            ui.label("Client state counter:");
+            ui.colored_label(egui::Color32::BLUE, format!("{}", counter));
+        });
+    }
+}
 
 fn render_steam_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamDilemmaUi) {
     ui.add_space(10.0);
@@ -313,7 +1148,14 @@ fn render_steam_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamD
 
     ui.horizontal(|ui| {
         // ui.text_edit_singleline(&mut app.shared_client_state.lock().unwrap().steam_id_str);
-        if ui.button("Load Library").clicked() {
+        if ui
+            .add_enabled(
+                !app.join_steam_id.trim().is_empty(),
+                egui::Button::new("Load Library"),
+            )
+            .on_disabled_hover_text("Enter your Steam ID in the room section first")
+            .clicked()
+        {
             app.get_customer_game_library(ctx);
         }
     });
@@ -322,8 +1164,55 @@ fn render_steam_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamD
         ui.vertical(|ui| {
             ui.label("Customer Data:");
             ui.label(&current_customer.steam_name);
+            render_stale_notice(ui, current_customer.stale);
+        });
+
+        let mut preferences = current_customer.preferences.clone();
+        ui.horizontal(|ui| {
+            ui.label("I play on:");
+            egui::ComboBox::from_id_salt("platform")
+                .selected_text(platform_label(preferences.platform))
+                .show_ui(ui, |ui| {
+                    for platform in [
+                        Platform::Windows,
+                        Platform::Mac,
+                        Platform::Linux,
+                        Platform::SteamDeck,
+                    ] {
+                        ui.selectable_value(
+                            &mut preferences.platform,
+                            platform,
+                            platform_label(platform),
+                        );
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Languages I play in:");
+            ui.add(
+                egui::TextEdit::singleline(&mut app.languages_text)
+                    .hint_text(preferences.languages.join(", ")),
+            );
+            if ui.button("Save").clicked() {
+                preferences.languages = split_comma_list(&app.languages_text);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Genres I like:");
+            ui.add(
+                egui::TextEdit::singleline(&mut app.genres_text)
+                    .hint_text(preferences.genres.join(", ")),
+            );
+            if ui.button("Save").clicked() {
+                preferences.genres = split_comma_list(&app.genres_text);
+            }
         });
+        if preferences != current_customer.preferences {
+            app.set_customer_preferences(ctx, preferences);
+            return;
+        }
 
+        let mut toggled = None;
         ui.vertical(|ui| {
             for game in &current_customer.games {
                 ui.horizontal(|ui| {
@@ -332,22 +1221,322 @@ fn render_steam_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamD
                         ui.add(Image::new(texture));
                     }
                     ui.label(&game.name);
+
+                    let mut installed = game.installed;
+                    if ui.checkbox(&mut installed, "Installed").changed() {
+                        toggled = Some((game.app_id, installed));
+                    }
+                    if let Some(size) = game.size_on_disk {
+                        ui.weak(format_bytes(size));
+                    }
+                    if !game.dlc.is_empty() {
+                        ui.weak(format!("+{} DLC", game.dlc.len()));
+                    }
                 });
             }
         });
+
+        if let Some((app_id, installed)) = toggled {
+            app.set_game_installed(ctx, app_id, installed);
+        }
+    }
+}
+
+fn render_recommendations_section(
+    ui: &mut egui::Ui,
+    ctx: &egui::Context,
+    app: &mut SteamDilemmaUi,
+) {
+    let Some(room_id) = app.room_id else {
+        return;
+    };
+
+    ui.add_space(10.0);
+    ui.separator();
+    ui.heading("What should we play?");
+
+    ui.collapsing("Content limits", |ui| {
+        let mut age_limited = app.content_limits.max_required_age.is_some();
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut age_limited, "Maximum age rating");
+            let mut max_age = app.content_limits.max_required_age.unwrap_or(12);
+            ui.add_enabled(
+                age_limited,
+                egui::DragValue::new(&mut max_age).range(0..=21),
+            );
+            app.content_limits.max_required_age = age_limited.then_some(max_age);
+        });
+
+        for descriptor in ContentDescriptor::ALL {
+            let blocked = &mut app.content_limits.blocked_descriptors;
+            let mut is_blocked = blocked.contains(&descriptor);
+            if ui
+                .checkbox(
+                    &mut is_blocked,
+                    format!("Block: {}", content_descriptor_label(descriptor)),
+                )
+                .changed()
+            {
+                blocked.retain(|d| *d != descriptor);
+                if is_blocked {
+                    blocked.push(descriptor);
+                }
+            }
+        }
+
+        if ui.button("Apply to room").clicked() {
+            app.set_room_content_limits(ctx, room_id);
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut app.installed_by_everyone, "Installed by everyone");
+        ui.checkbox(&mut app.include_non_games, "Include soundtracks and tools");
+        ui.radio_value(
+            &mut app.recommendation_sort,
+            RecommendationSort::Ready,
+            "Ready to play",
+        );
+        ui.radio_value(
+            &mut app.recommendation_sort,
+            RecommendationSort::SmallestDownload,
+            "Smallest download",
+        );
+
+        ui.add_enabled_ui(get_can_send_request_status(&app.request_state), |ui| {
+            if ui.button("Recommend").clicked() {
+                app.get_recommendations(ctx, room_id);
+            }
+            if !app.client_state.recommendations.is_empty()
+                && ui.button("Re-check who owns the top picks").clicked()
+            {
+                app.refresh_shortlist_ownership(ctx, room_id);
+            }
+        });
+    });
+
+    for entry in &app.client_state.shortlist_ownership {
+        let name = app
+            .client_state
+            .recommendations
+            .iter()
+            .find(|r| r.app_id == entry.app_id)
+            .map_or_else(|| entry.app_id.to_string(), |r| r.name.clone());
+        let mut line = format!("{}: owned by {}", name, entry.owners.join(", "));
+        if !entry.unknown.is_empty() {
+            line.push_str(&format!(" (unknown for {})", entry.unknown.join(", ")));
+        }
+        ui.label(line);
+    }
+
+    let can_nominate = app.room_socket.as_ref().is_some_and(RoomSocket::is_open)
+        && !app.only_watching()
+        && app
+            .client_state
+            .current_room
+            .as_ref()
+            .is_some_and(|room| room.phase == RoomPhase::Nominating);
+    let mut nominated = None;
+    for recommendation in &app.client_state.recommendations {
+        ui.horizontal(|ui| {
+            ui.label(&recommendation.name);
+            if ui
+                .add_enabled(can_nominate, egui::Button::new("Nominate"))
+                .clicked()
+            {
+                nominated = Some(recommendation.app_id);
+            }
+            render_review_summary(ui, recommendation.reviews.as_ref());
+            if recommendation.missing_install.is_empty() {
+                ui.colored_label(egui::Color32::GREEN, "installed by everyone");
+            } else {
+                let download = recommendation
+                    .download_bytes
+                    .map(format_bytes)
+                    .unwrap_or_else(|| "unknown size".to_owned());
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!(
+                        "{} to download for {}",
+                        download,
+                        recommendation.missing_install.join(", ")
+                    ),
+                );
+            }
+        });
+        ui.horizontal(|ui| {
+            for member in &recommendation.compatibility {
+                render_compatibility_badge(ui, &member.steam_name, member.badge);
+            }
+        });
+        ui.horizontal(|ui| {
+            if let Some(update) = &recommendation.latest_update {
+                ui.weak(format!("Last update {}", format_date(update.date)));
+            }
+            if let Some(news) = &recommendation.latest_news {
+                ui.weak(format_date(news.date));
+                ui.hyperlink_to(&news.title, &news.url);
+            }
+            render_stale_notice(ui, recommendation.stale);
+        });
+
+        if recommendation
+            .dlc
+            .iter()
+            .any(|member| !member.dlc.is_empty())
+        {
+            ui.collapsing(format!("DLC for {}", recommendation.name), |ui| {
+                for member in &recommendation.dlc {
+                    let names: Vec<&str> = member.dlc.iter().map(|d| d.name.as_str()).collect();
+                    let owned = if names.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        names.join(", ")
+                    };
+                    ui.label(format!("{}: {}", member.steam_name, owned));
+                }
+            });
+        }
+    }
+}
+
+fn render_free_to_play_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamDilemmaUi) {
+    let Some(room_id) = app.room_id else {
+        return;
+    };
+
+    ui.add_space(10.0);
+    ui.separator();
+    ui.heading("Free to play, nobody has to buy anything");
+
+    ui.add_enabled_ui(get_can_send_request_status(&app.request_state), |ui| {
+        if ui.button("Find free games").clicked() {
+            app.get_free_to_play(ctx, room_id);
+        }
+    });
+
+    for candidate in &app.client_state.free_to_play {
+        ui.horizontal(|ui| {
+            ui.label(format!("#{} {}", candidate.rank, candidate.name));
+            ui.weak(format!("{} players at peak", candidate.peak_players));
+            render_review_summary(ui, candidate.reviews.as_ref());
+            if !candidate.matching_genres.is_empty() {
+                ui.colored_label(egui::Color32::GREEN, candidate.matching_genres.join(", "));
+            }
+            if !candidate.owned_by.is_empty() {
+                ui.weak(format!(
+                    "already played by {}",
+                    candidate.owned_by.join(", ")
+                ));
+            }
+        });
+        ui.horizontal(|ui| {
+            for member in &candidate.compatibility {
+                render_compatibility_badge(ui, &member.steam_name, member.badge);
+            }
+        });
+    }
+}
+
+fn render_local_files_section(ui: &mut egui::Ui, ctx: &egui::Context, app: &mut SteamDilemmaUi) {
+    ui.add_space(10.0);
+    ui.separator();
+    ui.heading("Private profile? Upload your local Steam files");
+    ui.label("Drop libraryfolders.vdf, appmanifest_*.acf and localconfig.vdf onto this window.");
+
+    let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
+    for file in dropped_files {
+        let Some(bytes) = file.bytes else {
+            continue;
+        };
+        let contents = String::from_utf8_lossy(&bytes).into_owned();
+        if !add_local_steam_file(&mut app.local_upload, &file.name, contents) {
+            log::warn!("Ignoring dropped file {}", file.name);
+        }
     }
+
+    let upload = &mut app.local_upload;
+    ui.horizontal(|ui| {
+        ui.label("Steam name:");
+        ui.text_edit_singleline(&mut upload.steam_name);
+    });
+    ui.label(format!(
+        "libraryfolders.vdf: {}, localconfig.vdf: {}, app manifests: {}",
+        if upload.library_folders.is_some() {
+            "✔"
+        } else {
+            "✖"
+        },
+        if upload.local_config.is_some() {
+            "✔"
+        } else {
+            "✖"
+        },
+        upload.app_manifests.len()
+    ));
+
+    let can_upload = get_can_send_request_status(&app.request_state)
+        && !upload.steam_name.is_empty()
+        && (upload.library_folders.is_some()
+            || upload.local_config.is_some()
+            || !upload.app_manifests.is_empty());
+
+    ui.horizontal(|ui| {
+        ui.add_enabled_ui(can_upload, |ui| {
+            if ui.button("Upload local files").clicked() {
+                app.upload_local_library(ctx);
+            }
+        });
+        if ui.button("Clear").clicked() {
+            app.local_upload = LocalLibraryUpload::default();
+        }
+    });
 }
 
 fn render_central_panel(ctx: &egui::Context, app: &mut SteamDilemmaUi) {
     egui::CentralPanel::default().show(ctx, |ui| {
         ui.heading("Steam Dilemma");
 
+        if app.spectating && app.room_id.is_some() {
+            render_spectator_layout(ui, app);
+            return;
+        }
+
         render_room_info(ui, &mut app.label);
+        render_room_section(ui, ctx, app);
         render_server_counter_section(ui, ctx, app);
         render_steam_section(ui, ctx, app);
+        render_local_files_section(ui, ctx, app);
+        render_recommendations_section(ui, ctx, app);
+        render_free_to_play_section(ui, ctx, app);
     });
 }
 
+impl ClientState {
+    /// Keeps the current room in sync with the room WebSocket
+    pub(crate) fn apply_room_message(&mut self, message: ServerMessage) {
+        match message {
+            ServerMessage::Snapshot { room } => {
+                self.current_room = Some(room);
+                self.room_error = None;
+            }
+            ServerMessage::RoomClosed => {
+                self.current_room = None;
+                self.room_expires_at = None;
+            }
+            ServerMessage::Error { message } => self.room_error = Some(message),
+            ServerMessage::RoomExpiring { expires_at } => self.room_expires_at = Some(expires_at),
+            message => {
+                // Anything happening in the room keeps it open
+                self.room_expires_at = None;
+                if let Some(room) = &mut self.current_room {
+                    room.apply(&message);
+                }
+            }
+        }
+    }
+}
+
 impl Default for ClientState {
     fn default() -> Self {
         Self {
@@ -355,6 +1544,12 @@ impl Default for ClientState {
             server_counter: None,
             steam_id_str: "".to_owned(),
             current_customer: None,
+            recommendations: Vec::new(),
+            free_to_play: Vec::new(),
+            shortlist_ownership: Vec::new(),
+            room_error: None,
+            signed_in: None,
+            room_expires_at: None,
             test_texture: None,
         }
     }
@@ -371,6 +1566,23 @@ impl Default for SteamDilemmaUi {
             value: 2.1,
             http_client: None,
             request_state: Arc::new(Mutex::new(RequestState::Idle)),
+            local_upload: LocalLibraryUpload::default(),
+            installed_by_everyone: false,
+            include_non_games: false,
+            recommendation_sort: RecommendationSort::default(),
+            languages_text: String::new(),
+            genres_text: String::new(),
+            content_limits: ContentLimits::default(),
+            phase_timers: PhaseTimers::default(),
+            join_steam_id: String::new(),
+            room_socket: None,
+            last_connect_attempt: None,
+            chat_text: String::new(),
+            session_checked: false,
+            room_code_text: String::new(),
+            join_password: String::new(),
+            room_password_text: String::new(),
+            spectating: false,
         }
     }
 }
@@ -389,15 +1601,16 @@ impl SteamDilemmaUi {
             Default::default()
         };
 
-        app.room_id = parse_room_id_from_url();
-
-        if let Some(room_id) = app.room_id {
-            app.label = format!("Room ID: {}", room_id);
-        }
+        app.room_id = None;
 
         // Initialize HTTP client
         app.http_client = Some(reqwest::Client::new());
 
+        if let Some(room) = parse_room_id_from_url() {
+            app.spectating = is_spectator_link();
+            app.open_room(&cc.egui_ctx, room);
+        }
+
         app
     }
 
@@ -406,6 +1619,201 @@ impl SteamDilemmaUi {
         if let Ok(shared_state) = self.shared_client_state.lock() {
             self.client_state = shared_state.clone();
         }
+
+        // A room we just created
+        if self.room_id.is_none()
+            && let Some(room) = &self.client_state.current_room
+        {
+            self.room_id = Some(room.id);
+            self.label = format!("Room ID: {}", room.id);
+        }
+    }
+
+    /// Spawns a request answered with a `RoomResponse`, see `send_room_request`
+    fn spawn_room_request<F>(
+        &mut self,
+        ctx: &egui::Context,
+        request: impl FnOnce(&reqwest::Client) -> F,
+    ) where
+        F: Future<Output = Result<RoomResponse, ApiError>> + 'static,
+    {
+        if let Some(client) = &self.http_client {
+            let request = request(client);
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+            let shared_client_state = self.shared_client_state.clone();
+
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Loading;
+            }
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_room_request(request, request_state, shared_client_state, ctx).await;
+            });
+        }
+    }
+
+    fn create_room(&mut self, ctx: &egui::Context) {
+        let request = CreateRoomRequest {
+            content_limits: self.content_limits.clone(),
+            phase_timers: self.phase_timers,
+        };
+        self.spawn_room_request(ctx, |client| {
+            api::call::<api::CreateRoom>(client, &(), &request)
+        });
+    }
+
+    /// Loads a room given by ID or code. A room loaded by code becomes the current room once
+    /// the server answers, see `sync_client_state`.
+    fn open_room(&mut self, ctx: &egui::Context, room: RoomRef) {
+        match room {
+            RoomRef::Id(room_id) => {
+                self.room_id = Some(room_id);
+                self.label = format!("Room ID: {}", room_id);
+                self.get_room(ctx, room_id);
+            }
+            RoomRef::Code(code) => {
+                self.spawn_room_request(ctx, |client| {
+                    api::call::<api::GetRoomByCode>(client, &code, &())
+                });
+            }
+        }
+    }
+
+    fn get_room(&mut self, ctx: &egui::Context, room_id: u64) {
+        self.spawn_room_request(ctx, |client| {
+            api::call::<api::GetRoom>(client, &room_id, &())
+        });
+    }
+
+    /// Joins as a member bringing their library, or as a spectator who only watches
+    fn join_room(&mut self, ctx: &egui::Context, room_id: u64, spectator: bool) {
+        let password = self.join_password.trim();
+        let request = JoinRoomRequest {
+            steam_id: self.join_steam_id.trim().to_owned(),
+            password: (!password.is_empty()).then(|| password.to_owned()),
+            spectator,
+        };
+        self.spawn_room_request(ctx, |client| {
+            api::call::<api::JoinRoom>(client, &room_id, &request)
+        });
+    }
+
+    fn leave_room(&mut self, ctx: &egui::Context, room_id: u64) {
+        let steam_id = self.join_steam_id.trim();
+        let request = LeaveRoomRequest {
+            steam_id: steam_id.parse().ok(),
+            steam_name: steam_id.to_owned(),
+        };
+        self.spawn_room_request(ctx, |client| {
+            api::call::<api::LeaveRoom>(client, &room_id, &request)
+        });
+    }
+
+    /// Asks the server once who is signed in. Signing in reloads the page, so once is enough.
+    fn check_session(&mut self, ctx: &egui::Context) {
+        if self.session_checked {
+            return;
+        }
+        self.session_checked = true;
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let shared_client_state = self.shared_client_state.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_session_request(client, shared_client_state, ctx).await;
+            });
+        }
+    }
+
+    fn logout(&mut self, ctx: &egui::Context) {
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let shared_client_state = self.shared_client_state.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_logout_request(client, shared_client_state, ctx).await;
+            });
+        }
+    }
+
+    /// Keeps a WebSocket open to the current room, reconnecting after drops
+    fn maintain_room_socket(&mut self, ctx: &egui::Context) {
+        let Some(room_id) = self.room_id else {
+            self.room_socket = None;
+            return;
+        };
+        let connected = self
+            .room_socket
+            .as_ref()
+            .is_some_and(|socket| socket.room_id() == room_id && !socket.is_closed());
+        if connected {
+            return;
+        }
+
+        let now = ctx.input(|i| i.time);
+        if self
+            .last_connect_attempt
+            .is_some_and(|last| now - last < ROOM_RECONNECT_DELAY)
+        {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(ROOM_RECONNECT_DELAY));
+            return;
+        }
+        self.last_connect_attempt = Some(now);
+        let member = if self.spectating {
+            ""
+        } else {
+            self.join_steam_id.trim()
+        };
+        self.room_socket = RoomSocket::connect(
+            room_id,
+            member,
+            self.join_password.trim(),
+            self.shared_client_state.clone(),
+            ctx.clone(),
+        );
+    }
+
+    /// Whether the room is only shown to us: we came through a spectator link or joined as a
+    /// spectator, so we can't nominate, vote or chat
+    fn only_watching(&self) -> bool {
+        self.spectating
+            || self.client_state.current_room.as_ref().is_some_and(|room| {
+                self.client_state
+                    .signed_in
+                    .and_then(|steam_id| room.role_of(steam_id))
+                    == Some(Role::Spectator)
+            })
+    }
+
+    fn send_room_message(&self, message: &ClientMessage) {
+        if let Some(socket) = &self.room_socket {
+            socket.send(message);
+        }
+    }
+
+    fn close_room(&mut self, ctx: &egui::Context, room_id: u64) {
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Loading;
+            }
+            // Forget the room right away, or it would be picked up again as just created
+            self.room_id = None;
+            self.room_socket = None;
+            if let Ok(mut client_state) = self.shared_client_state.lock() {
+                client_state.current_room = None;
+            }
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_close_room_request(client, room_id, request_state, ctx).await;
+            });
+        }
     }
 
     fn increment_server_counter(&mut self, ctx: &egui::Context) {
@@ -426,29 +1834,231 @@ impl SteamDilemmaUi {
         }
     }
 
+    /// Loads the library of the Steam ID given in the room section
     fn get_customer_game_library(&mut self, ctx: &egui::Context) {
+        let steam_id = self.join_steam_id.trim().to_owned();
+        if steam_id.is_empty() {
+            return;
+        }
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+            let shared_client_state = self.shared_client_state.clone();
+
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Loading;
+            }
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_get_customer_library_request(
+                    client,
+                    steam_id,
+                    request_state,
+                    shared_client_state,
+                    ctx,
+                )
+                .await;
+            });
+        }
+    }
+
+    fn set_game_installed(&mut self, ctx: &egui::Context, app_id: u64, installed: bool) {
+        let Some(customer) = &self.client_state.current_customer else {
+            return;
+        };
+        let request = SetGameInstalledRequest {
+            steam_name: customer.steam_name.clone(),
+            steam_id: customer.steam_id,
+            app_id,
+            installed,
+            size_on_disk: None,
+        };
+
+        // Update the local copy right away, the server only confirms the toggle
+        if let Ok(mut client_state) = self.shared_client_state.lock() {
+            let games = client_state
+                .current_customer
+                .iter_mut()
+                .flat_map(|c| c.games.iter_mut());
+            for game in games.filter(|g| g.app_id == app_id) {
+                game.installed = installed;
+            }
+        }
+
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_set_game_installed_request(client, request, request_state, ctx).await;
+            });
+        }
+    }
+
+    fn set_customer_preferences(&mut self, ctx: &egui::Context, preferences: CustomerPreferences) {
+        let request = {
+            let Ok(mut client_state) = self.shared_client_state.lock() else {
+                return;
+            };
+            let Some(customer) = client_state.current_customer.as_mut() else {
+                return;
+            };
+            customer.preferences = preferences.clone();
+
+            SetCustomerPreferencesRequest {
+                steam_name: customer.steam_name.clone(),
+                steam_id: customer.steam_id,
+                preferences,
+            }
+        };
+
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_set_customer_preferences_request(client, request, request_state, ctx).await;
+            });
+        }
+    }
+
+    fn get_free_to_play(&mut self, ctx: &egui::Context, room_id: u64) {
         if let Some(client) = &self.http_client {
             let client = client.clone();
             let ctx = ctx.clone();
             let request_state = self.request_state.clone();
             let shared_client_state = self.shared_client_state.clone();
+            let request = FreeToPlayRequest {
+                room_id,
+                limit: None,
+            };
 
             if let Ok(mut state) = request_state.lock() {
                 *state = RequestState::Loading;
             }
 
             wasm_bindgen_futures::spawn_local(async move {
-                send_get_customer_library_request(client, request_state, shared_client_state, ctx)
+                send_free_to_play_request(client, request, request_state, shared_client_state, ctx)
                     .await;
             });
         }
     }
+
+    fn refresh_shortlist_ownership(&mut self, ctx: &egui::Context, room_id: u64) {
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+            let shared_client_state = self.shared_client_state.clone();
+            let request = RefreshOwnershipRequest {
+                room_id,
+                app_ids: self
+                    .client_state
+                    .recommendations
+                    .iter()
+                    .take(SHORTLIST_SIZE)
+                    .map(|r| r.app_id)
+                    .collect(),
+            };
+
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Loading;
+            }
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_refresh_ownership_request(
+                    client,
+                    request,
+                    request_state,
+                    shared_client_state,
+                    ctx,
+                )
+                .await;
+            });
+        }
+    }
+
+    fn set_room_content_limits(&mut self, ctx: &egui::Context, room_id: u64) {
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+            let request = SetRoomContentLimitsRequest {
+                room_id,
+                content_limits: self.content_limits.clone(),
+            };
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_set_room_content_limits_request(client, request, request_state, ctx).await;
+            });
+        }
+    }
+
+    fn get_recommendations(&mut self, ctx: &egui::Context, room_id: u64) {
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+            let shared_client_state = self.shared_client_state.clone();
+            let request = RecommendationRequest {
+                room_id,
+                installed_by_everyone: self.installed_by_everyone,
+                include_non_games: self.include_non_games,
+                sort: self.recommendation_sort,
+            };
+
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Loading;
+            }
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_recommendations_request(
+                    client,
+                    request,
+                    request_state,
+                    shared_client_state,
+                    ctx,
+                )
+                .await;
+            });
+        }
+    }
+
+    fn upload_local_library(&mut self, ctx: &egui::Context) {
+        if let Some(client) = &self.http_client {
+            let client = client.clone();
+            let ctx = ctx.clone();
+            let request_state = self.request_state.clone();
+            let shared_client_state = self.shared_client_state.clone();
+            let upload = self.local_upload.clone();
+
+            if let Ok(mut state) = request_state.lock() {
+                *state = RequestState::Loading;
+            }
+
+            wasm_bindgen_futures::spawn_local(async move {
+                send_upload_local_library_request(
+                    client,
+                    upload,
+                    request_state,
+                    shared_client_state,
+                    ctx,
+                )
+                .await;
+            });
+        }
+    }
 }
 
 impl eframe::App for SteamDilemmaUi {
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.sync_client_state();
+        self.check_session(ctx);
+        self.maintain_room_socket(ctx);
 
         render_top_panel(ctx);
         render_central_panel(ctx, self);
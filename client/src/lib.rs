@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+mod api;
 pub mod app_ui;
+mod room_socket;
 
 pub use app_ui::SteamDilemmaUi;
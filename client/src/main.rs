@@ -1,6 +1,8 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+mod api;
 mod app_ui;
+mod room_socket;
 
 pub use app_ui::SteamDilemmaUi;
 
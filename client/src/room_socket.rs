@@ -0,0 +1,100 @@
+//! Live connection to a room over `library::api::ROOM_SOCKET_PATH`.
+//!
+//! The server sends a snapshot of the room on connect, then every change as it happens. Each
+//! change is applied to the snapshot with `Room::apply`, the same way the server does.
+
+use std::sync::{Arc, Mutex};
+
+use library::{ClientMessage, ServerMessage};
+use wasm_bindgen::JsCast;
+use wasm_bindgen::closure::Closure;
+use web_sys::{MessageEvent, WebSocket};
+
+use crate::api;
+use crate::app_ui::ClientState;
+
+pub struct RoomSocket {
+    room_id: u64,
+    socket: WebSocket,
+    // Kept alive for as long as the socket is
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl RoomSocket {
+    /// Connects as `member` (a SteamID64, or a name for members who uploaded local files).
+    /// Without a member the connection can only watch, which takes the `password` of a
+    /// password protected room.
+    pub fn connect(
+        room_id: u64,
+        member: &str,
+        password: &str,
+        shared_client_state: Arc<Mutex<ClientState>>,
+        ctx: egui::Context,
+    ) -> Option<Self> {
+        let mut url = api::socket_url(&api::room_socket_path(room_id));
+        let query: Vec<String> = [("member", member), ("password", password)]
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| format!("{name}={}", urlencoding::encode(value)))
+            .collect();
+        if !query.is_empty() {
+            url.push_str(&format!("?{}", query.join("&")));
+        }
+        let socket = WebSocket::new(&url).ok()?;
+
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let Some(text) = event.data().as_string() else {
+                return;
+            };
+            match serde_json::from_str::<ServerMessage>(&text) {
+                Ok(message) => {
+                    if let Ok(mut client_state) = shared_client_state.lock() {
+                        client_state.apply_room_message(message);
+                    }
+                    ctx.request_repaint();
+                }
+                Err(e) => log::warn!("Unknown room message: {}", e),
+            }
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        Some(Self {
+            room_id,
+            socket,
+            _on_message: on_message,
+        })
+    }
+
+    pub fn room_id(&self) -> u64 {
+        self.room_id
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(
+            self.socket.ready_state(),
+            WebSocket::CLOSING | WebSocket::CLOSED
+        )
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.socket.ready_state() == WebSocket::OPEN
+    }
+
+    pub fn send(&self, message: &ClientMessage) {
+        match serde_json::to_string(message) {
+            Ok(json) => {
+                if let Err(e) = self.socket.send_with_str(&json) {
+                    log::warn!("Can't send room message: {:?}", e);
+                }
+            }
+            Err(e) => log::error!("Can't serialize room message: {}", e),
+        }
+    }
+}
+
+impl Drop for RoomSocket {
+    fn drop(&mut self) {
+        self.socket.set_onmessage(None);
+        let _ = self.socket.close();
+    }
+}
@@ -0,0 +1,185 @@
+//! The HTTP API, one type per endpoint.
+//!
+//! Each endpoint has its method, path, and request and response types here, and the server's
+//! routes and the client's calls are both built from them, so the two can't disagree on where
+//! an endpoint lives or what it takes. Failed calls are answered with an `ApiError`.
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    CounterResponse, CreateRoomRequest, FreeToPlayRequest, FreeToPlayResponse,
+    GetCustomerLibraryRequest, JoinRoomRequest, LeaveRoomRequest, LocalLibraryUpload,
+    NewCustomerResponse, RecommendationRequest, RecommendationsResponse, RefreshOwnershipRequest,
+    RefreshOwnershipResponse, RoomResponse, SessionResponse, SetCustomerPreferencesRequest,
+    SetGameInstalledRequest, SetRoomContentLimitsRequest, SteamUsageResponse,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Delete,
+}
+
+impl Method {
+    /// Only POST requests send their `Endpoint::Request` as a JSON body
+    pub fn has_body(self) -> bool {
+        self == Self::Post
+    }
+}
+
+pub trait Endpoint {
+    const METHOD: Method;
+    /// As the server routes it, with `{name}` standing for the path parameter
+    const PATH: &'static str;
+    /// The path parameter, `()` for paths without one
+    type Params;
+    /// `()` for endpoints that take nothing
+    type Request: Serialize + DeserializeOwned;
+    type Response: Serialize + DeserializeOwned;
+
+    /// `PATH` with its parameter filled in, percent-encoded
+    fn path(params: &Self::Params) -> String;
+}
+
+/// Percent-encodes everything but the characters RFC 3986 leaves unreserved, so a parameter
+/// stays a single path segment
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+macro_rules! endpoint {
+    ($(#[$doc:meta])* $name:ident, $method:ident $path:literal, $request:ty => $response:ty) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name;
+
+        impl Endpoint for $name {
+            const METHOD: Method = Method::$method;
+            const PATH: &'static str = $path;
+            type Params = ();
+            type Request = $request;
+            type Response = $response;
+
+            fn path(_: &()) -> String {
+                Self::PATH.to_owned()
+            }
+        }
+    };
+    ($(#[$doc:meta])* $name:ident, $method:ident $path:literal, $param:ident: $param_type:ty, $request:ty => $response:ty) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name;
+
+        impl Endpoint for $name {
+            const METHOD: Method = Method::$method;
+            const PATH: &'static str = $path;
+            type Params = $param_type;
+            type Request = $request;
+            type Response = $response;
+
+            fn path($param: &$param_type) -> String {
+                Self::PATH.replace(
+                    concat!("{", stringify!($param), "}"),
+                    &encode_path_segment(&$param.to_string()),
+                )
+            }
+        }
+    };
+}
+
+endpoint!(
+    /// The account signed in through Steam, or `Unauthorized`
+    GetSession, Get "/api/auth/session", () => SessionResponse
+);
+endpoint!(
+    /// Ends the session
+    Logout, Post "/api/auth/logout", () => ()
+);
+endpoint!(Increment, Post "/api/increment", () => CounterResponse);
+endpoint!(
+    /// Fetches a Steam account's profile and library
+    GetCustomerLibrary, Post "/api/get_customer_library", GetCustomerLibraryRequest => NewCustomerResponse
+);
+endpoint!(
+    UploadLocalLibrary, Post "/api/upload_local_library", LocalLibraryUpload => NewCustomerResponse
+);
+endpoint!(
+    SetGameInstalled, Post "/api/set_game_installed", SetGameInstalledRequest => NewCustomerResponse
+);
+endpoint!(
+    /// Signed in as the customer only
+    SetCustomerPreferences, Post "/api/set_customer_preferences", SetCustomerPreferencesRequest => NewCustomerResponse
+);
+endpoint!(
+    /// Host only
+    SetRoomContentLimits, Post "/api/set_room_content_limits", SetRoomContentLimitsRequest => RoomResponse
+);
+endpoint!(
+    GetRecommendations, Post "/api/recommendations", RecommendationRequest => RecommendationsResponse
+);
+endpoint!(GetFreeToPlay, Post "/api/free_to_play", FreeToPlayRequest => FreeToPlayResponse);
+endpoint!(
+    RefreshOwnership, Post "/api/refresh_ownership", RefreshOwnershipRequest => RefreshOwnershipResponse
+);
+endpoint!(GetSteamUsage, Get "/api/admin/steam_usage", () => SteamUsageResponse);
+endpoint!(
+    /// Signed in only, the account becomes the room's host
+    CreateRoom, Post "/api/rooms", CreateRoomRequest => RoomResponse
+);
+endpoint!(GetRoom, Get "/api/rooms/{room_id}", room_id: u64, () => RoomResponse);
+endpoint!(
+    /// Host only
+    CloseRoom, Delete "/api/rooms/{room_id}", room_id: u64, () => ()
+);
+endpoint!(
+    /// The open room with this code, given as `normalize_room_code` returns it
+    GetRoomByCode, Get "/api/rooms/code/{code}", code: String, () => RoomResponse
+);
+endpoint!(JoinRoom, Post "/api/rooms/{room_id}/join", room_id: u64, JoinRoomRequest => RoomResponse);
+endpoint!(
+    LeaveRoom, Post "/api/rooms/{room_id}/leave", room_id: u64, LeaveRoomRequest => RoomResponse
+);
+
+/// Where browsers go to sign in through Steam
+pub const STEAM_LOGIN_PATH: &str = "/api/auth/steam/login";
+
+/// The room WebSocket, which sends `ServerMessage`s and takes `ClientMessage`s
+pub const ROOM_SOCKET_PATH: &str = "/api/rooms/{room_id}/ws";
+
+pub fn room_socket_path(room_id: u64) -> String {
+    ROOM_SOCKET_PATH.replace("{room_id}", &encode_path_segment(&room_id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_fill_in_their_parameter() {
+        assert_eq!(GetRoom::path(&7), "/api/rooms/7");
+        assert_eq!(JoinRoom::path(&7), "/api/rooms/7/join");
+        assert_eq!(CreateRoom::path(&()), "/api/rooms");
+        assert_eq!(room_socket_path(7), "/api/rooms/7/ws");
+    }
+
+    #[test]
+    fn path_parameters_are_percent_encoded() {
+        assert_eq!(
+            GetRoomByCode::path(&"ABC234".to_owned()),
+            "/api/rooms/code/ABC234"
+        );
+        assert_eq!(
+            GetRoomByCode::path(&"ab c/../ü?#%".to_owned()),
+            "/api/rooms/code/ab%20c%2F..%2F%C3%BC%3F%23%25"
+        );
+    }
+}
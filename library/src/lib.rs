@@ -1,12 +1,185 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
+pub mod api;
 
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Customer {
     pub steam_name: String,
     pub steam_id: Option<u64>,
     pub games: Vec<Game>,
+    #[serde(default)]
+    pub preferences: CustomerPreferences,
+    /// Unix time of the last successful read of the profile and library, from Steam or from
+    /// uploaded local files
+    #[serde(default)]
+    pub fetched_at: Option<u64>,
+    /// Set when Steam couldn't be reached and this is the last copy the server had
+    #[serde(default)]
+    pub stale: Option<StaleData>,
+    /// Joined the room signed in through Steam as this account, rather than just giving
+    /// its Steam ID
+    #[serde(default)]
+    pub verified: bool,
+}
+
+/// Marks data served from cache because Steam couldn't refresh it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaleData {
+    /// How long ago the data was last fetched from Steam
+    pub age_secs: u64,
+}
+
+impl From<std::time::Duration> for StaleData {
+    fn from(age: std::time::Duration) -> Self {
+        Self {
+            age_secs: age.as_secs(),
+        }
+    }
+}
+
+/// What a customer tells us about themselves to narrow down recommendations
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CustomerPreferences {
+    pub platform: Platform,
+    /// Languages the customer can play in, named as on the Steam store ("English",
+    /// "French", ...). Empty means any language is fine.
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// Store genres the customer enjoys ("Action", "Strategy", ...)
+    #[serde(default)]
+    pub genres: Vec<String>,
+}
+
+/// The device a customer plays on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Platform {
+    #[default]
+    Windows,
+    Mac,
+    Linux,
+    SteamDeck,
+}
+
+/// Operating systems a game ships native builds for, as in Steam's appdetails `platforms`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlatformSupport {
+    #[serde(default)]
+    pub windows: bool,
+    #[serde(default)]
+    pub mac: bool,
+    #[serde(default)]
+    pub linux: bool,
+}
+
+/// Valve's Steam Deck compatibility rating
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeckCompatibility {
+    #[default]
+    Unknown,
+    Unsupported,
+    Playable,
+    Verified,
+}
+
+/// Whether a game will run for one member of a room, on the platform they declared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompatibilityBadge {
+    /// A native build exists for the member's platform
+    Native,
+    /// Steam Deck Verified
+    Verified,
+    /// Runs on the Deck or through Proton, possibly with some tweaks
+    Playable,
+    Unsupported,
+    /// We have no data about this game yet
+    Unknown,
+}
+
+/// Steam's content descriptors, as listed on a store page's mature content notice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContentDescriptor {
+    SomeNudityOrSexualContent,
+    FrequentViolenceOrGore,
+    AdultOnlySexualContent,
+    FrequentNudityOrSexualContent,
+    GeneralMatureContent,
+}
+
+impl ContentDescriptor {
+    pub const ALL: [ContentDescriptor; 5] = [
+        ContentDescriptor::SomeNudityOrSexualContent,
+        ContentDescriptor::FrequentViolenceOrGore,
+        ContentDescriptor::AdultOnlySexualContent,
+        ContentDescriptor::FrequentNudityOrSexualContent,
+        ContentDescriptor::GeneralMatureContent,
+    ];
+
+    /// Maps the numeric ids used by Steam's appdetails `content_descriptors`
+    pub fn from_steam_id(id: u64) -> Option<Self> {
+        match id {
+            1 => Some(ContentDescriptor::SomeNudityOrSexualContent),
+            2 => Some(ContentDescriptor::FrequentViolenceOrGore),
+            3 => Some(ContentDescriptor::AdultOnlySexualContent),
+            4 => Some(ContentDescriptor::FrequentNudityOrSexualContent),
+            5 => Some(ContentDescriptor::GeneralMatureContent),
+            _ => None,
+        }
+    }
+}
+
+/// What a room allows, for when we play with family
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentLimits {
+    /// Highest `required_age` a game may have
+    pub max_required_age: Option<u32>,
+    /// Games carrying any of these descriptors are left out
+    pub blocked_descriptors: Vec<ContentDescriptor>,
+}
+
+impl ContentLimits {
+    pub fn is_active(&self) -> bool {
+        self.max_required_age.is_some() || !self.blocked_descriptors.is_empty()
+    }
+}
+
+/// What kind of app a library entry is, from Steam's appdetails `type`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AppType {
+    /// Not classified yet
+    #[default]
+    Unknown,
+    Game,
+    Dlc,
+    Soundtrack,
+    Demo,
+    Tool,
+    Video,
+    Other,
+}
+
+impl AppType {
+    pub fn from_steam_type(steam_type: &str) -> Self {
+        match steam_type.to_ascii_lowercase().as_str() {
+            "game" => AppType::Game,
+            "dlc" => AppType::Dlc,
+            "music" => AppType::Soundtrack,
+            "demo" => AppType::Demo,
+            "application" | "tool" => AppType::Tool,
+            "video" | "series" | "episode" => AppType::Video,
+            "" => AppType::Unknown,
+            _ => AppType::Other,
+        }
+    }
+}
+
+/// Minutes played per platform, from GetOwnedGames
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlatformPlaytime {
+    pub windows: u64,
+    pub mac: u64,
+    pub linux: u64,
+    pub deck: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,6 +187,23 @@ pub struct Game {
     pub id: u64,
     pub app_id: u64,
     pub name: String,
+    /// Minutes played, as reported by Steam or the local `localconfig.vdf`
+    #[serde(default)]
+    pub playtime_minutes: u64,
+    /// Whether the game is fully installed on the customer's machine
+    #[serde(default)]
+    pub installed: bool,
+    /// Bytes on disk (or to download) for the game, when known
+    #[serde(default)]
+    pub size_on_disk: Option<u64>,
+    #[serde(default)]
+    pub platform_playtime: PlatformPlaytime,
+    #[serde(default)]
+    pub app_type: AppType,
+    /// DLC (and soundtracks) the customer owns for this game, grouped under it once the
+    /// library has been classified
+    #[serde(default)]
+    pub dlc: Vec<Game>,
     // pub tags: Vec<String>,
 }
 
@@ -28,6 +218,12 @@ pub struct SteamGameLibrary {
     pub api_key: String,
 }
 
+impl Default for SteamGameLibrary {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SteamGameLibrary {
     pub fn new() -> SteamGameLibrary {
         let steam_api_key = &std::env::var("STEAM_API_KEY").expect("Missing STEAM API key");
@@ -48,16 +244,1200 @@ pub struct CounterResponse {
     pub counter_value: u64,
 }
 
+/// Fetches a customer's profile and library from Steam
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GetCustomerLibraryRequest {
+    /// SteamID64 of the account
+    pub steam_id: String,
+}
+
 // API Response types for client-server communication
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NewCustomerResponse {
     pub customer: Customer,
 }
 
+/// Contents of the Steam client's local files, uploaded by users with a private profile.
+/// Every field holds the raw text of the file as found on disk.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LocalLibraryUpload {
+    pub steam_name: String,
+    pub steam_id: Option<u64>,
+    /// `steamapps/libraryfolders.vdf`
+    pub library_folders: Option<String>,
+    /// `steamapps/appmanifest_<appid>.acf`, one entry per file
+    pub app_manifests: Vec<String>,
+    /// `userdata/<id>/config/localconfig.vdf`
+    pub local_config: Option<String>,
+}
+
+/// Manual override of a game's install state, set from the client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetGameInstalledRequest {
+    pub steam_name: String,
+    pub steam_id: Option<u64>,
+    pub app_id: u64,
+    pub installed: bool,
+    pub size_on_disk: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetCustomerPreferencesRequest {
+    pub steam_name: String,
+    pub steam_id: Option<u64>,
+    pub preferences: CustomerPreferences,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetRoomContentLimitsRequest {
+    pub room_id: u64,
+    pub content_limits: ContentLimits,
+}
+
+// Recommendation types
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecommendationSort {
+    /// Games most of the room already has installed first, then by playtime
+    #[default]
+    Ready,
+    /// Games with the smallest total download for the room first
+    SmallestDownload,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecommendationRequest {
+    pub room_id: u64,
+    /// Keep soundtracks, tools, demos and other non-game apps
+    #[serde(default)]
+    pub include_non_games: bool,
+    /// Only keep games every member already has installed
+    #[serde(default)]
+    pub installed_by_everyone: bool,
+    #[serde(default)]
+    pub sort: RecommendationSort,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recommendation {
+    pub app_id: u64,
+    pub name: String,
+    /// Steam names of the members who have the game installed
+    pub installed_by: Vec<String>,
+    /// Steam names of the members who still need to download it
+    pub missing_install: Vec<String>,
+    /// Sum of what every member still has to download, `None` when no size is known
+    pub download_bytes: Option<u64>,
+    pub total_playtime_minutes: u64,
+    /// One badge per member, in the room's member order
+    pub compatibility: Vec<MemberCompatibility>,
+    /// The DLC each member owns for the game, in the room's member order
+    pub dlc: Vec<MemberDlc>,
+    /// Most recent news item from the game's Steam news feed
+    #[serde(default)]
+    pub latest_news: Option<NewsHeadline>,
+    /// Most recent news item tagged as patch notes
+    #[serde(default)]
+    pub latest_update: Option<NewsHeadline>,
+    #[serde(default)]
+    pub reviews: Option<ReviewSummary>,
+    /// Set when the reviews or news shown are out of date because Steam couldn't refresh them
+    #[serde(default)]
+    pub stale: Option<StaleData>,
+}
+
+/// Steam user reviews of a game, as summarized on its store page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReviewSummary {
+    pub total_reviews: u64,
+    /// Share of positive reviews, between 0 and 1
+    pub positive_ratio: f32,
+    /// Steam's 0-9 score bucket, 9 being "Overwhelmingly Positive"
+    pub review_score: u8,
+    /// "Very Positive", "Mixed", ...
+    pub description: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewsHeadline {
+    pub title: String,
+    pub url: String,
+    /// Unix timestamp, in seconds
+    pub date: u64,
+    pub is_patch_notes: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberDlc {
+    pub steam_name: String,
+    pub dlc: Vec<DlcInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DlcInfo {
+    pub app_id: u64,
+    pub name: String,
+    pub app_type: AppType,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberCompatibility {
+    pub steam_name: String,
+    pub platform: Platform,
+    pub badge: CompatibilityBadge,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecommendationsResponse {
+    pub recommendations: Vec<Recommendation>,
+}
+
+/// Re-checks who owns each game of a shortlist, without pulling whole libraries again
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RefreshOwnershipRequest {
+    pub room_id: u64,
+    pub app_ids: Vec<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppOwnership {
+    pub app_id: u64,
+    /// Steam names of the members who own the game
+    pub owners: Vec<String>,
+    /// Members whose library couldn't be checked (private profile or local files only)
+    pub unknown: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RefreshOwnershipResponse {
+    pub ownership: Vec<AppOwnership>,
+}
+
+/// Popular free multiplayer games for a room, kept apart from the owned-game recommendations
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FreeToPlayRequest {
+    pub room_id: u64,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreeToPlayCandidate {
+    pub app_id: u64,
+    pub name: String,
+    /// Position in Steam's most played chart
+    pub rank: u32,
+    pub peak_players: u64,
+    pub genres: Vec<String>,
+    /// Genres matching what the room's members like
+    pub matching_genres: Vec<String>,
+    /// Members who already have it in their library (it shows up once launched)
+    pub owned_by: Vec<String>,
+    pub compatibility: Vec<MemberCompatibility>,
+    #[serde(default)]
+    pub reviews: Option<ReviewSummary>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FreeToPlayResponse {
+    pub candidates: Vec<FreeToPlayCandidate>,
+}
+
+/// Today's Steam API usage, for the admin endpoint
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SteamUsageResponse {
+    /// Unix timestamp of the UTC midnight the count started at
+    pub day_start: u64,
+    pub total_calls: u64,
+    /// Past this, cached data is served even when out of date
+    pub soft_budget: u64,
+    /// Past this, only calls needed to read a user's library are made
+    pub hard_budget: u64,
+    pub endpoints: Vec<EndpointUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointUsage {
+    pub endpoint: String,
+    pub calls: u64,
+}
+
 // Room management types
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Room {
     pub id: u64,
     pub customers: Vec<Customer>,
     pub consultants: Vec<Consultant>,
+    #[serde(default)]
+    pub content_limits: ContentLimits,
+    /// Games put forward for tonight, in the order they were nominated
+    #[serde(default)]
+    pub nominations: Vec<Nomination>,
+    /// Most recent chat messages, oldest first
+    #[serde(default)]
+    pub chat: Vec<ChatMessage>,
+    /// The game the room settled on
+    #[serde(default)]
+    pub decision: Option<u64>,
+    /// SteamID64 of the signed in customer who created the room
+    #[serde(default)]
+    pub host: Option<u64>,
+    /// Short code to read out on voice chat, see `ROOM_CODE_ALPHABET`
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub phase: RoomPhase,
+    /// Unix time at which the current phase ends on its own, when it has a timer
+    #[serde(default)]
+    pub phase_deadline: Option<u64>,
+    #[serde(default)]
+    pub phase_timers: PhaseTimers,
+    /// Unix time the room was created
+    #[serde(default)]
+    pub created_at: u64,
+    /// Unix time of the last change to the room. Rooms idle for too long are closed.
+    #[serde(default)]
+    pub last_active_at: u64,
+    /// Watching the room without a library or a say in it
+    #[serde(default)]
+    pub spectators: Vec<Customer>,
+    /// Nobody new can join while locked
+    #[serde(default)]
+    pub locked: bool,
+    /// Joining takes the password the host set
+    #[serde(default)]
+    pub password_protected: bool,
+    /// SteamID64s the host kicked out for good
+    #[serde(default)]
+    pub banned: Vec<u64>,
+}
+
+/// What someone can do in a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    /// Steers the room: phases, decisions, locking, kicking and promoting
+    Host,
+    /// Brings their library, nominates, votes and chats
+    Member,
+    /// Only watches
+    Spectator,
+}
+
+/// Where a room is in the evening, in order. Rooms move on to the next phase when the host
+/// says so or the phase's timer runs out, and can be closed from any phase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RoomPhase {
+    /// Members gather
+    #[default]
+    Lobby,
+    /// Members' libraries are read and classified
+    LoadingLibraries,
+    /// Members put games forward
+    Nominating,
+    /// Members vote for a nominated game
+    Voting,
+    /// The room settled on a game
+    Decided,
+    Closed,
+}
+
+impl RoomPhase {
+    pub fn next(self) -> Option<Self> {
+        match self {
+            Self::Lobby => Some(Self::LoadingLibraries),
+            Self::LoadingLibraries => Some(Self::Nominating),
+            Self::Nominating => Some(Self::Voting),
+            Self::Voting => Some(Self::Decided),
+            Self::Decided => Some(Self::Closed),
+            Self::Closed => None,
+        }
+    }
+
+    /// Without overriding: on to the next phase, or closing
+    pub fn can_move_to(self, to: Self) -> bool {
+        self.next() == Some(to) || (to == Self::Closed && self != Self::Closed)
+    }
+
+    /// Members can still join up to voting
+    pub fn accepts_members(self) -> bool {
+        self < Self::Voting
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Lobby => "Lobby",
+            Self::LoadingLibraries => "Loading libraries",
+            Self::Nominating => "Nominating",
+            Self::Voting => "Voting",
+            Self::Decided => "Decided",
+            Self::Closed => "Closed",
+        }
+    }
+}
+
+/// How long phases last before the room moves on by itself. Phases without a timer wait for
+/// the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseTimers {
+    pub loading_libraries_secs: Option<u64>,
+    pub nominating_secs: Option<u64>,
+    pub voting_secs: Option<u64>,
+}
+
+impl PhaseTimers {
+    pub fn duration_secs(&self, phase: RoomPhase) -> Option<u64> {
+        match phase {
+            RoomPhase::LoadingLibraries => self.loading_libraries_secs,
+            RoomPhase::Nominating => self.nominating_secs,
+            RoomPhase::Voting => self.voting_secs,
+            _ => None,
+        }
+    }
+}
+
+/// Chat messages kept in a room, older ones are dropped
+pub const MAX_CHAT_HISTORY: usize = 100;
+
+impl Room {
+    pub fn new(id: u64, content_limits: ContentLimits) -> Self {
+        Self {
+            id,
+            customers: Vec::new(),
+            consultants: Vec::new(),
+            content_limits,
+            nominations: Vec::new(),
+            chat: Vec::new(),
+            decision: None,
+            host: None,
+            code: String::new(),
+            phase: RoomPhase::Lobby,
+            phase_deadline: None,
+            phase_timers: PhaseTimers::default(),
+            created_at: 0,
+            last_active_at: 0,
+            spectators: Vec::new(),
+            locked: false,
+            password_protected: false,
+            banned: Vec::new(),
+        }
+    }
+
+    /// The role of an account in the room, if it's in it. Hosts don't have to be members.
+    pub fn role_of(&self, steam_id: u64) -> Option<Role> {
+        if self.host == Some(steam_id) {
+            Some(Role::Host)
+        } else if self.customers.iter().any(|c| c.steam_id == Some(steam_id)) {
+            Some(Role::Member)
+        } else if self.spectators.iter().any(|c| c.steam_id == Some(steam_id)) {
+            Some(Role::Spectator)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the signed in account can steer the room. Rooms saved before they had a host
+    /// have nobody steering them.
+    pub fn is_host(&self, signed_in: Option<u64>) -> bool {
+        signed_in.is_some() && self.host == signed_in
+    }
+
+    /// Takes a member or spectator out of the room, along with their votes
+    fn remove(&mut self, steam_id: Option<u64>, steam_name: &str) {
+        self.customers
+            .retain(|c| !is_same_member(c, steam_id, steam_name));
+        self.spectators
+            .retain(|c| !is_same_member(c, steam_id, steam_name));
+        for nomination in &mut self.nominations {
+            nomination
+                .votes
+                .retain(|voter| !voter.is(steam_id, steam_name));
+        }
+    }
+
+    /// Link that opens the client at `base_url` straight into this room
+    pub fn invite_link(&self, base_url: &str) -> String {
+        let base_url = base_url.split(['?', '#']).next().unwrap_or_default();
+        if self.code.is_empty() {
+            format!("{base_url}?room_id={}", self.id)
+        } else {
+            format!("{base_url}?room={}", self.code)
+        }
+    }
+
+    /// Link that opens the room read-only, for people who only watch, such as a stream's chat
+    pub fn spectator_link(&self, base_url: &str) -> String {
+        format!("{}&spectate", self.invite_link(base_url))
+    }
+
+    /// Applies a room event. The server applies each event to its copy before broadcasting
+    /// it, and clients apply it to the snapshot they got on connect, so both stay in sync.
+    pub fn apply(&mut self, message: &ServerMessage) {
+        match message {
+            ServerMessage::Snapshot { room } => *self = room.clone(),
+            ServerMessage::MemberJoined { member } | ServerMessage::LibraryLoaded { member } => {
+                match self
+                    .customers
+                    .iter_mut()
+                    .find(|c| is_same_member(c, member.steam_id, &member.steam_name))
+                {
+                    Some(existing) => *existing = member.clone(),
+                    None if matches!(message, ServerMessage::MemberJoined { .. }) => {
+                        self.customers.push(member.clone())
+                    }
+                    None => {}
+                }
+            }
+            ServerMessage::LibraryClassified {
+                steam_id,
+                steam_name,
+                games,
+            } => {
+                if let Some(member) = self
+                    .customers
+                    .iter_mut()
+                    .find(|c| is_same_member(c, *steam_id, steam_name))
+                {
+                    member.games = games.clone();
+                }
+            }
+            ServerMessage::MemberLeft {
+                steam_id,
+                steam_name,
+            } => self.remove(*steam_id, steam_name),
+            ServerMessage::SpectatorJoined { spectator } => {
+                if !self
+                    .spectators
+                    .iter()
+                    .any(|c| is_same_member(c, spectator.steam_id, &spectator.steam_name))
+                {
+                    self.spectators.push(spectator.clone());
+                }
+            }
+            ServerMessage::Kicked {
+                steam_id,
+                steam_name,
+                banned,
+            } => {
+                self.remove(*steam_id, steam_name);
+                if let Some(steam_id) = steam_id
+                    && *banned
+                    && !self.banned.contains(steam_id)
+                {
+                    self.banned.push(*steam_id);
+                }
+            }
+            ServerMessage::Promoted { member } => {
+                self.spectators
+                    .retain(|c| !is_same_member(c, member.steam_id, &member.steam_name));
+                self.customers.push(member.clone());
+            }
+            ServerMessage::HostChanged { host } => self.host = Some(*host),
+            ServerMessage::AccessChanged {
+                locked,
+                password_protected,
+            } => {
+                self.locked = *locked;
+                self.password_protected = *password_protected;
+            }
+            ServerMessage::Nominated { nomination } => {
+                if !self
+                    .nominations
+                    .iter()
+                    .any(|n| n.app_id == nomination.app_id)
+                {
+                    self.nominations.push(nomination.clone());
+                }
+            }
+            ServerMessage::Voted { app_id, voter } => {
+                for nomination in &mut self.nominations {
+                    nomination
+                        .votes
+                        .retain(|v| !v.is(voter.steam_id, &voter.steam_name));
+                    if nomination.app_id == *app_id {
+                        nomination.votes.push(voter.clone());
+                    }
+                }
+            }
+            ServerMessage::Chat { message } => {
+                self.chat.push(message.clone());
+                let overflow = self.chat.len().saturating_sub(MAX_CHAT_HISTORY);
+                self.chat.drain(..overflow);
+            }
+            ServerMessage::Decided { app_id } => self.decision = Some(*app_id),
+            ServerMessage::PhaseChanged { phase, deadline } => {
+                self.phase = *phase;
+                self.phase_deadline = *deadline;
+                // The host took the room back to pick again
+                if *phase < RoomPhase::Decided {
+                    self.decision = None;
+                }
+            }
+            ServerMessage::RoomClosed
+            | ServerMessage::RoomExpiring { .. }
+            | ServerMessage::Error { .. } => {}
+        }
+    }
+}
+
+/// Characters of room codes: digits and capitals that can't be mistaken for one another, so
+/// no 0/O or 1/I/l
+pub const ROOM_CODE_ALPHABET: &str = "23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+pub const ROOM_CODE_LENGTH: usize = 6;
+
+/// A room as given by a user or a link: by its code, or by its numeric ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomRef {
+    Id(u64),
+    Code(String),
+}
+
+impl RoomRef {
+    /// Room codes always hold a letter, so anything all digits is an ID
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        match text.parse() {
+            Ok(id) => Some(Self::Id(id)),
+            Err(_) => normalize_room_code(text).map(Self::Code),
+        }
+    }
+}
+
+/// The code as the server stores it: upper case, without the spaces or dashes people add when
+/// typing it. None if it can't be a room code.
+pub fn normalize_room_code(code: &str) -> Option<String> {
+    let code: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let valid = code.len() == ROOM_CODE_LENGTH
+        && code.chars().all(|c| ROOM_CODE_ALPHABET.contains(c))
+        && !code.chars().all(|c| c.is_ascii_digit());
+    valid.then_some(code)
+}
+
+/// Same rule as the server uses: by Steam ID when known, by name otherwise
+fn is_same_member(customer: &Customer, steam_id: Option<u64>, steam_name: &str) -> bool {
+    match steam_id {
+        Some(steam_id) => customer.steam_id == Some(steam_id),
+        None => customer.steam_id.is_none() && customer.steam_name == steam_name,
+    }
+}
+
+/// A member as nominations and votes name them. Members are told apart by SteamID64, so
+/// renaming keeps their votes, and by name only when they don't have one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberRef {
+    pub steam_id: Option<u64>,
+    pub steam_name: String,
+}
+
+impl MemberRef {
+    pub fn of(customer: &Customer) -> Self {
+        Self {
+            steam_id: customer.steam_id,
+            steam_name: customer.steam_name.clone(),
+        }
+    }
+
+    pub fn is(&self, steam_id: Option<u64>, steam_name: &str) -> bool {
+        match steam_id {
+            Some(steam_id) => self.steam_id == Some(steam_id),
+            None => self.steam_id.is_none() && self.steam_name == steam_name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Nomination {
+    pub app_id: u64,
+    pub name: String,
+    pub nominated_by: MemberRef,
+    /// The members voting for it. Each member has a single vote.
+    #[serde(default)]
+    pub votes: Vec<MemberRef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub from: String,
+    pub text: String,
+    /// Unix timestamp
+    pub sent_at: u64,
+}
+
+/// Sent by a room member over the room WebSocket (`/api/rooms/{id}/ws`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    Nominate {
+        app_id: u64,
+    },
+    /// Moves the member's vote to a nominated game
+    Vote {
+        app_id: u64,
+    },
+    Chat {
+        text: String,
+    },
+    /// Settles on a nominated game, the host's call
+    Decide {
+        app_id: u64,
+    },
+    /// Moves the room to another phase. The host can skip phases or go back.
+    SetPhase {
+        phase: RoomPhase,
+    },
+    // The host's moderation
+    /// Stops (or lets) anyone new join
+    Lock {
+        locked: bool,
+    },
+    /// Sets the password joining takes, or removes it with `None`
+    SetPassword {
+        password: Option<String>,
+    },
+    /// Takes a member or spectator out of the room. Banned accounts can't come back.
+    Kick {
+        steam_id: Option<u64>,
+        steam_name: String,
+        ban: bool,
+    },
+    /// Hands the room over to a member
+    TransferHost {
+        steam_id: u64,
+    },
+    /// Makes a spectator a member, bringing their library into the room
+    Promote {
+        steam_id: Option<u64>,
+        steam_name: String,
+    },
+}
+
+/// Sent by the server over the room WebSocket. A `Snapshot` comes first on every
+/// (re)connect, then every change to the room as it happens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    Snapshot {
+        room: Room,
+    },
+    MemberJoined {
+        member: Customer,
+    },
+    MemberLeft {
+        steam_id: Option<u64>,
+        steam_name: String,
+    },
+    /// A member's library was fetched again
+    LibraryLoaded {
+        member: Customer,
+    },
+    /// More of a member's apps were classified: their library with app types, store names
+    /// and DLC grouped under base games
+    LibraryClassified {
+        steam_id: Option<u64>,
+        steam_name: String,
+        games: Vec<Game>,
+    },
+    Nominated {
+        nomination: Nomination,
+    },
+    Voted {
+        app_id: u64,
+        voter: MemberRef,
+    },
+    Chat {
+        message: ChatMessage,
+    },
+    Decided {
+        app_id: u64,
+    },
+    /// Sent on every phase change, including a timer being cleared
+    PhaseChanged {
+        phase: RoomPhase,
+        deadline: Option<u64>,
+    },
+    /// Someone joined to watch
+    SpectatorJoined {
+        spectator: Customer,
+    },
+    Kicked {
+        steam_id: Option<u64>,
+        steam_name: String,
+        banned: bool,
+    },
+    /// A spectator became a member
+    Promoted {
+        member: Customer,
+    },
+    HostChanged {
+        host: u64,
+    },
+    AccessChanged {
+        locked: bool,
+        password_protected: bool,
+    },
+    /// The room will be closed at `expires_at` (Unix time) unless something happens in it
+    RoomExpiring {
+        expires_at: u64,
+    },
+    RoomClosed,
+    /// Only sent to the client whose message was refused
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CreateRoomRequest {
+    #[serde(default)]
+    pub content_limits: ContentLimits,
+    #[serde(default)]
+    pub phase_timers: PhaseTimers,
+}
+
+/// Joins a room with the library of a Steam account, fetched by the server
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JoinRoomRequest {
+    /// SteamID64 of the member's account
+    pub steam_id: String,
+    /// Needed when the room is password protected
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Joins to watch, without bringing a library
+    #[serde(default)]
+    pub spectator: bool,
+}
+
+/// Identifies a member the same way the server does: by Steam ID, or by name for members
+/// who only uploaded local files
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LeaveRoomRequest {
+    pub steam_id: Option<u64>,
+    pub steam_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomResponse {
+    pub room: Room,
+}
+
+/// The account signed in through Steam
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub steam_id: u64,
+}
+
+/// Body of every failed API response, sent with the HTTP status of its `code`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiError {
+    pub code: ApiErrorCode,
+    /// Explains the error to the user
+    pub message: String,
+    /// What the error is about, such as the `room_id` that doesn't exist
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<String, String>,
+}
+
+impl ApiError {
+    pub fn new(code: ApiErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_detail(mut self, key: &str, value: impl ToString) -> Self {
+        self.details.insert(key.to_owned(), value.to_string());
+        self
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// What went wrong, for clients to act on without reading messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiErrorCode {
+    /// The request couldn't be understood
+    BadRequest,
+    /// Sign in through Steam first
+    Unauthorized,
+    /// Signed in as someone who isn't allowed to do this
+    Forbidden,
+    NotFound,
+    /// The room's state doesn't allow it, such as joining once voting started
+    Conflict,
+    /// Uploaded Steam files that can't be read
+    InvalidLibrary,
+    /// The Steam profile is private, or has no games
+    SteamPrivate,
+    /// Steam answered with an error
+    SteamFailed,
+    /// Steam is down, or today's Steam API budget is spent
+    SteamUnavailable,
+    Internal,
+}
+
+impl ApiErrorCode {
+    /// The HTTP status responses with this code have
+    pub fn status(self) -> u16 {
+        match self {
+            Self::BadRequest => 400,
+            Self::Unauthorized => 401,
+            Self::Forbidden | Self::SteamPrivate => 403,
+            Self::NotFound => 404,
+            Self::Conflict => 409,
+            Self::InvalidLibrary => 422,
+            Self::Internal => 500,
+            Self::SteamFailed => 502,
+            Self::SteamUnavailable => 503,
+        }
+    }
+
+    /// The closest code for a response that came without an `ApiError`, such as from a proxy
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 => Self::Unauthorized,
+            403 => Self::Forbidden,
+            404 => Self::NotFound,
+            409 => Self::Conflict,
+            422 => Self::InvalidLibrary,
+            502 => Self::SteamFailed,
+            503 => Self::SteamUnavailable,
+            400..500 => Self::BadRequest,
+            _ => Self::Internal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room() -> Room {
+        Room {
+            id: 1,
+            customers: Vec::new(),
+            consultants: Vec::new(),
+            content_limits: ContentLimits::default(),
+            nominations: Vec::new(),
+            chat: Vec::new(),
+            decision: None,
+            host: Some(1),
+            code: "ABC234".to_owned(),
+            phase: RoomPhase::Lobby,
+            phase_deadline: None,
+            phase_timers: PhaseTimers::default(),
+            created_at: 0,
+            last_active_at: 0,
+            spectators: Vec::new(),
+            locked: false,
+            password_protected: false,
+            banned: Vec::new(),
+        }
+    }
+
+    fn customer(steam_id: u64, steam_name: &str) -> Customer {
+        Customer {
+            steam_name: steam_name.to_owned(),
+            steam_id: Some(steam_id),
+            ..Default::default()
+        }
+    }
+
+    fn game(app_id: u64, name: &str) -> Game {
+        Game {
+            id: 0,
+            app_id,
+            name: name.to_owned(),
+            playtime_minutes: 0,
+            installed: false,
+            size_on_disk: None,
+            platform_playtime: PlatformPlaytime::default(),
+            app_type: AppType::default(),
+            dlc: Vec::new(),
+        }
+    }
+
+    fn names(customers: &[Customer]) -> Vec<&str> {
+        customers.iter().map(|c| c.steam_name.as_str()).collect()
+    }
+
+    fn apply_all(room: &mut Room, messages: &[ServerMessage]) {
+        for message in messages {
+            room.apply(message);
+        }
+    }
+
+    #[test]
+    fn reconnecting_clients_catch_up_from_the_snapshot() {
+        let mut server = room();
+        let mut client = room();
+        let missed = [
+            ServerMessage::MemberJoined {
+                member: customer(2, "Bob"),
+            },
+            ServerMessage::Chat {
+                message: ChatMessage {
+                    from: "Bob".to_owned(),
+                    text: "Portal 2?".to_owned(),
+                    sent_at: 1,
+                },
+            },
+        ];
+        apply_all(&mut server, &missed);
+        client.apply(&ServerMessage::MemberJoined {
+            member: customer(3, "Carol"),
+        });
+
+        client.apply(&ServerMessage::Snapshot {
+            room: server.clone(),
+        });
+
+        assert_eq!(names(&client.customers), ["Bob"]);
+        assert_eq!(client.chat, server.chat);
+    }
+
+    #[test]
+    fn members_join_leave_and_reload_their_library() {
+        let mut room = room();
+        let joined = ServerMessage::MemberJoined {
+            member: customer(2, "Bob"),
+        };
+        apply_all(&mut room, &[joined.clone(), joined]);
+        assert_eq!(names(&room.customers), ["Bob"]);
+
+        let mut reloaded = customer(2, "Bobby");
+        reloaded.games = vec![game(620, "App 620"), game(621, "App 621")];
+        room.apply(&ServerMessage::LibraryLoaded { member: reloaded });
+        // Not in the room, so not added
+        room.apply(&ServerMessage::LibraryLoaded {
+            member: customer(3, "Carol"),
+        });
+        assert_eq!(names(&room.customers), ["Bobby"]);
+        assert_eq!(room.customers[0].games.len(), 2);
+
+        let mut portal = game(620, "Portal 2");
+        portal.app_type = AppType::Game;
+        portal.dlc = vec![game(621, "Portal 2 Soundtrack")];
+        room.apply(&ServerMessage::LibraryClassified {
+            steam_id: Some(2),
+            steam_name: "Bobby".to_owned(),
+            games: vec![portal],
+        });
+        let games = &room.customers[0].games;
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].name, "Portal 2");
+        assert_eq!(games[0].dlc[0].app_id, 621);
+
+        room.apply(&ServerMessage::MemberLeft {
+            steam_id: Some(2),
+            steam_name: "Bobby".to_owned(),
+        });
+        assert!(room.customers.is_empty());
+    }
+
+    #[test]
+    fn leaving_takes_the_votes_along() {
+        let mut room = room();
+        apply_all(
+            &mut room,
+            &[
+                ServerMessage::MemberJoined {
+                    member: customer(2, "Bob"),
+                },
+                ServerMessage::Nominated {
+                    nomination: Nomination {
+                        app_id: 620,
+                        name: "Portal 2".to_owned(),
+                        nominated_by: MemberRef::of(&customer(2, "Bob")),
+                        votes: Vec::new(),
+                    },
+                },
+                ServerMessage::Voted {
+                    app_id: 620,
+                    voter: MemberRef::of(&customer(2, "Bob")),
+                },
+                ServerMessage::MemberLeft {
+                    steam_id: Some(2),
+                    steam_name: "Bob".to_owned(),
+                },
+            ],
+        );
+
+        assert_eq!(room.nominations.len(), 1);
+        assert!(room.nominations[0].votes.is_empty());
+    }
+
+    #[test]
+    fn nominations_and_votes() {
+        let mut room = room();
+        let bob = MemberRef::of(&customer(2, "Bob"));
+        let nominate = |app_id: u64| ServerMessage::Nominated {
+            nomination: Nomination {
+                app_id,
+                name: format!("App {app_id}"),
+                nominated_by: bob.clone(),
+                votes: Vec::new(),
+            },
+        };
+        let vote = |app_id: u64, voter: &MemberRef| ServerMessage::Voted {
+            app_id,
+            voter: voter.clone(),
+        };
+
+        apply_all(&mut room, &[nominate(620), nominate(620), nominate(730)]);
+        assert_eq!(room.nominations.len(), 2);
+
+        // A member has a single vote, voting again moves it
+        apply_all(&mut room, &[vote(620, &bob), vote(730, &bob)]);
+        assert!(room.nominations[0].votes.is_empty());
+        assert_eq!(room.nominations[1].votes, [bob]);
+
+        // Votes follow the SteamID64: a namesake has their own, a rename keeps Bob's
+        let namesake = MemberRef::of(&customer(3, "Bob"));
+        let renamed = MemberRef::of(&customer(2, "Robert"));
+        apply_all(&mut room, &[vote(620, &namesake), vote(620, &renamed)]);
+        assert_eq!(room.nominations[0].votes, [namesake, renamed]);
+        assert!(room.nominations[1].votes.is_empty());
+    }
+
+    #[test]
+    fn chat_keeps_the_latest_messages() {
+        let mut room = room();
+        for sent_at in 0..MAX_CHAT_HISTORY as u64 + 5 {
+            room.apply(&ServerMessage::Chat {
+                message: ChatMessage {
+                    from: "Bob".to_owned(),
+                    text: sent_at.to_string(),
+                    sent_at,
+                },
+            });
+        }
+
+        assert_eq!(room.chat.len(), MAX_CHAT_HISTORY);
+        assert_eq!(room.chat[0].sent_at, 5);
+    }
+
+    #[test]
+    fn decisions_and_phases() {
+        let mut room = room();
+        apply_all(
+            &mut room,
+            &[
+                ServerMessage::Decided { app_id: 620 },
+                ServerMessage::PhaseChanged {
+                    phase: RoomPhase::Decided,
+                    deadline: Some(60),
+                },
+            ],
+        );
+        assert_eq!(room.decision, Some(620));
+        assert_eq!(room.phase, RoomPhase::Decided);
+        assert_eq!(room.phase_deadline, Some(60));
+
+        room.apply(&ServerMessage::PhaseChanged {
+            phase: RoomPhase::Voting,
+            deadline: None,
+        });
+        assert_eq!(room.decision, None);
+        assert_eq!(room.phase_deadline, None);
+    }
+
+    #[test]
+    fn spectators_kicks_and_promotions() {
+        let mut room = room();
+        let watching = ServerMessage::SpectatorJoined {
+            spectator: customer(3, "Carol"),
+        };
+        apply_all(
+            &mut room,
+            &[
+                watching.clone(),
+                watching,
+                ServerMessage::SpectatorJoined {
+                    spectator: customer(4, "Dan"),
+                },
+                ServerMessage::MemberJoined {
+                    member: customer(2, "Bob"),
+                },
+            ],
+        );
+        assert_eq!(names(&room.spectators), ["Carol", "Dan"]);
+
+        room.apply(&ServerMessage::Promoted {
+            member: customer(3, "Carol"),
+        });
+        assert_eq!(names(&room.customers), ["Bob", "Carol"]);
+        assert_eq!(names(&room.spectators), ["Dan"]);
+
+        let kick = |steam_id: u64, steam_name: &str, banned: bool| ServerMessage::Kicked {
+            steam_id: Some(steam_id),
+            steam_name: steam_name.to_owned(),
+            banned,
+        };
+        apply_all(&mut room, &[kick(2, "Bob", false), kick(4, "Dan", true)]);
+        assert_eq!(names(&room.customers), ["Carol"]);
+        assert!(room.spectators.is_empty());
+        assert_eq!(room.banned, [4]);
+    }
+
+    #[test]
+    fn host_and_access_changes() {
+        let mut room = room();
+        apply_all(
+            &mut room,
+            &[
+                ServerMessage::HostChanged { host: 2 },
+                ServerMessage::AccessChanged {
+                    locked: true,
+                    password_protected: true,
+                },
+            ],
+        );
+
+        assert_eq!(room.host, Some(2));
+        assert!(room.locked);
+        assert!(room.password_protected);
+    }
+
+    #[test]
+    fn notices_leave_the_room_as_it_is() {
+        let mut room = room();
+        room.apply(&ServerMessage::MemberJoined {
+            member: customer(2, "Bob"),
+        });
+
+        apply_all(
+            &mut room,
+            &[
+                ServerMessage::RoomExpiring { expires_at: 60 },
+                ServerMessage::Error {
+                    message: "Only the host can do that".to_owned(),
+                },
+                ServerMessage::RoomClosed,
+            ],
+        );
+
+        assert_eq!(names(&room.customers), ["Bob"]);
+        assert_eq!(room.phase, RoomPhase::Lobby);
+    }
+
+    #[test]
+    fn typed_codes_and_ids_are_told_apart() {
+        assert_eq!(RoomRef::parse("42"), Some(RoomRef::Id(42)));
+        assert_eq!(
+            RoomRef::parse(" abc-def "),
+            Some(RoomRef::Code("ABCDEF".to_owned()))
+        );
+        assert_eq!(RoomRef::parse("ABC0EF"), None);
+        assert_eq!(RoomRef::parse("ABCDE"), None);
+    }
 }
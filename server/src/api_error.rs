@@ -0,0 +1,105 @@
+//! Failed API requests are answered with a `library::ApiError` body and the HTTP status of its
+//! code, including requests axum refuses before they reach a handler: `ApiJson` and `ApiPath`
+//! stand in for axum's `Json` and `Path` extractors and report what they couldn't read the
+//! same way.
+
+use axum::{
+    Json,
+    extract::{FromRequest, FromRequestParts, Path, Request},
+    http::{StatusCode, request::Parts},
+    response::{IntoResponse, Response},
+};
+use library::{ApiError, ApiErrorCode};
+use serde::de::DeserializeOwned;
+
+use crate::steam::steam_client::SteamError;
+
+/// An `ApiError` as a response
+#[derive(Debug)]
+pub struct ErrorResponse(pub ApiError);
+
+impl ErrorResponse {
+    pub fn new(code: ApiErrorCode, message: impl Into<String>) -> Self {
+        Self(ApiError::new(code, message))
+    }
+
+    pub fn with_detail(self, key: &str, value: impl ToString) -> Self {
+        Self(self.0.with_detail(key, value))
+    }
+}
+
+impl From<ApiError> for ErrorResponse {
+    fn from(error: ApiError) -> Self {
+        Self(error)
+    }
+}
+
+impl From<SteamError> for ErrorResponse {
+    fn from(error: SteamError) -> Self {
+        let code = match error {
+            SteamError::FailedRequest(_) => ApiErrorCode::SteamFailed,
+            SteamError::NoData => ApiErrorCode::SteamPrivate,
+            SteamError::MalformedFile(_) => ApiErrorCode::InvalidLibrary,
+            SteamError::QuotaExceeded | SteamError::Unavailable => ApiErrorCode::SteamUnavailable,
+        };
+        Self::new(code, error.to_string())
+    }
+}
+
+impl IntoResponse for ErrorResponse {
+    fn into_response(self) -> Response {
+        let status =
+            StatusCode::from_u16(self.0.code.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (status, Json(self.0)).into_response()
+    }
+}
+
+/// `Json`, refusing bodies it can't read with an `ApiError`
+pub struct ApiJson<T>(pub T);
+
+impl<S: Send + Sync, T: DeserializeOwned> FromRequest<S> for ApiJson<T> {
+    type Rejection = ErrorResponse;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(request, state).await {
+            Ok(Json(value)) => Ok(Self(value)),
+            Err(rejection) => Err(ErrorResponse::new(
+                ApiErrorCode::BadRequest,
+                rejection.body_text(),
+            )),
+        }
+    }
+}
+
+/// `Path`, refusing paths it can't read with an `ApiError`
+pub struct ApiPath<T>(pub T);
+
+impl<S: Send + Sync, T: DeserializeOwned + Send> FromRequestParts<S> for ApiPath<T> {
+    type Rejection = ErrorResponse;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Path::<T>::from_request_parts(parts, state).await {
+            Ok(Path(value)) => Ok(Self(value)),
+            Err(rejection) => Err(ErrorResponse::new(
+                ApiErrorCode::BadRequest,
+                rejection.body_text(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steam_errors_keep_their_meaning() {
+        let response = ErrorResponse::from(SteamError::QuotaExceeded).into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let ErrorResponse(error) =
+            ErrorResponse::from(SteamError::NoData).with_detail("steam_id", 76561197960287930u64);
+        assert_eq!(error.code, ApiErrorCode::SteamPrivate);
+        assert_eq!(error.details["steam_id"], "76561197960287930");
+    }
+}
@@ -0,0 +1,313 @@
+//! Who is making a request, proven by signing in through Steam.
+//!
+//! After `steam_openid` verifies the sign-in, the Steam ID is kept in a session cookie signed
+//! with HMAC-SHA256, so the server needs no session table. Handlers take a `Session` to
+//! require a signed in user, or an `Option<Session>` when signing in is optional. Admin
+//! endpoints take an `Admin`, a session of one of the configured `admin_steam_ids`.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts, Query, State},
+    http::{
+        HeaderMap, HeaderName, HeaderValue,
+        header::{COOKIE, SET_COOKIE},
+        request::Parts,
+    },
+    response::{IntoResponse, Json as ResponseJson, Redirect},
+};
+use hmac::{Hmac, Mac};
+use library::{ApiErrorCode, SessionResponse};
+use sha2::Sha256;
+
+use crate::api_error::ErrorResponse;
+use crate::config::Config;
+use crate::steam::steam_openid::SteamOpenId;
+use crate::{AppState, unix_now};
+
+pub const SESSION_COOKIE: &str = "steam_dilemma_session";
+
+const SESSION_LIFETIME_SECS: u64 = 30 * 24 * 60 * 60;
+
+pub const CALLBACK_PATH: &str = "/api/auth/steam/callback";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A signed in Steam account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Session {
+    pub steam_id: u64,
+    /// Unix time after which the cookie is refused
+    pub expires_at: u64,
+}
+
+pub struct Auth {
+    openid: SteamOpenId,
+    secret: Vec<u8>,
+    /// Where the server is reached from browsers, without a trailing slash
+    public_url: String,
+    admin_steam_ids: Vec<u64>,
+}
+
+impl Auth {
+    /// Without a configured secret a random one is used, and sessions end on restart
+    pub fn new(config: &Config) -> Self {
+        let secret = if config.session_secret.is_empty() {
+            log::warn!("No session secret configured, sessions won't survive a restart");
+            let mut secret = vec![0; 32];
+            getrandom::fill(&mut secret).expect("the OS provides random numbers");
+            secret
+        } else {
+            config.session_secret.as_bytes().to_vec()
+        };
+        Self {
+            admin_steam_ids: config.admin_steam_ids.clone(),
+            ..Self::with_secret(
+                SteamOpenId::new(config.steam.openid_provider.clone()),
+                secret,
+                config.public_url(),
+            )
+        }
+    }
+
+    pub fn with_secret(openid: SteamOpenId, secret: Vec<u8>, public_url: String) -> Self {
+        Self {
+            openid,
+            secret,
+            public_url: public_url.trim_end_matches('/').to_owned(),
+            admin_steam_ids: Vec::new(),
+        }
+    }
+
+    pub fn is_admin(&self, session: &Session) -> bool {
+        self.admin_steam_ids.contains(&session.steam_id)
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC takes any key size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    /// The cookie value: `<steam id>.<expiry>.<hex signature>`
+    pub fn sign(&self, session: &Session) -> String {
+        let payload = format!("{}.{}", session.steam_id, session.expires_at);
+        let signature = hex::encode(self.mac(&payload).finalize().into_bytes());
+        format!("{payload}.{signature}")
+    }
+
+    /// The session in a cookie value, if it was signed by us and hasn't expired
+    pub fn verify(&self, value: &str) -> Option<Session> {
+        let (payload, signature) = value.rsplit_once('.')?;
+        self.mac(payload)
+            .verify_slice(&hex::decode(signature).ok()?)
+            .ok()?;
+
+        let (steam_id, expires_at) = payload.split_once('.')?;
+        let session = Session {
+            steam_id: steam_id.parse().ok()?,
+            expires_at: expires_at.parse().ok()?,
+        };
+        (session.expires_at > unix_now()).then_some(session)
+    }
+
+    fn callback_url(&self) -> String {
+        format!("{}{CALLBACK_PATH}", self.public_url)
+    }
+
+    fn session_cookie(&self, value: &str, max_age_secs: u64) -> HeaderValue {
+        let secure = if self.public_url.starts_with("https://") {
+            "; Secure"
+        } else {
+            ""
+        };
+        let cookie = format!(
+            "{SESSION_COOKIE}={value}; Path=/; Max-Age={max_age_secs}; HttpOnly; SameSite=Lax{secure}"
+        );
+        HeaderValue::from_str(&cookie).expect("session cookies are plain ASCII")
+    }
+
+    fn session_from_headers(&self, headers: &HeaderMap) -> Option<Session> {
+        headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(|header| header.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(name, _)| *name == SESSION_COOKIE)
+            .and_then(|(_, value)| self.verify(value))
+    }
+}
+
+impl FromRequestParts<AppState> for Session {
+    type Rejection = ErrorResponse;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        state
+            .auth
+            .session_from_headers(&parts.headers)
+            .ok_or_else(|| {
+                ErrorResponse::new(ApiErrorCode::Unauthorized, "Sign in through Steam first")
+            })
+    }
+}
+
+impl OptionalFromRequestParts<AppState> for Session {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(state.auth.session_from_headers(&parts.headers))
+    }
+}
+
+/// A signed in Steam account listed in `admin_steam_ids`
+#[derive(Debug, Clone, Copy)]
+pub struct Admin(pub Session);
+
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = ErrorResponse;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let session =
+            <Session as FromRequestParts<AppState>>::from_request_parts(parts, state).await?;
+        if state.auth.is_admin(&session) {
+            Ok(Self(session))
+        } else {
+            Err(ErrorResponse::new(
+                ApiErrorCode::Forbidden,
+                "Only admins can do that",
+            ))
+        }
+    }
+}
+
+/// Sends the browser to Steam to sign in
+pub async fn steam_login(State(state): State<AppState>) -> Redirect {
+    let auth = &state.auth;
+    Redirect::to(
+        &auth
+            .openid
+            .login_url(&auth.callback_url(), &auth.public_url),
+    )
+}
+
+/// Where Steam sends the browser back. A verified sign-in starts a session and returns to the
+/// client.
+pub async fn steam_callback(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    let auth = &state.auth;
+    let steam_id = auth
+        .openid
+        .verify(&params, &auth.callback_url())
+        .await
+        .map_err(|error| {
+            log::warn!("Refused Steam sign-in: {error}");
+            ErrorResponse::new(ApiErrorCode::Unauthorized, error.to_string())
+        })?;
+    log::info!("{steam_id} signed in through Steam");
+
+    let session = Session {
+        steam_id,
+        expires_at: unix_now() + SESSION_LIFETIME_SECS,
+    };
+    let cookie = auth.session_cookie(&auth.sign(&session), SESSION_LIFETIME_SECS);
+
+    Ok((
+        [(SET_COOKIE, cookie)],
+        Redirect::to(&format!("{}/", auth.public_url)),
+    ))
+}
+
+pub async fn get_session(session: Session) -> ResponseJson<SessionResponse> {
+    ResponseJson(SessionResponse {
+        steam_id: session.steam_id,
+    })
+}
+
+pub async fn logout(
+    State(state): State<AppState>,
+) -> ([(HeaderName, HeaderValue); 1], ResponseJson<()>) {
+    (
+        [(SET_COOKIE, state.auth.session_cookie("", 0))],
+        ResponseJson(()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steam::steam_openid::STEAM_OPENID_PROVIDER;
+
+    fn auth(secret: &str) -> Auth {
+        Auth::with_secret(
+            SteamOpenId::new(STEAM_OPENID_PROVIDER.to_owned()),
+            secret.as_bytes().to_vec(),
+            "http://127.0.0.1:3000/".to_owned(),
+        )
+    }
+
+    #[test]
+    fn sessions_are_only_accepted_as_signed() {
+        let auth = auth("secret");
+        let session = Session {
+            steam_id: 76561197960287930,
+            expires_at: unix_now() + 60,
+        };
+        let cookie = auth.sign(&session);
+
+        assert_eq!(auth.verify(&cookie), Some(session));
+        assert_eq!(
+            auth.verify(&cookie.replacen("76561197960287930", "76561197960287931", 1)),
+            None
+        );
+        assert_eq!(self::auth("other secret").verify(&cookie), None);
+        assert!(!auth.is_admin(&session));
+        let admins = Auth {
+            admin_steam_ids: vec![76561197960287930],
+            ..self::auth("secret")
+        };
+        assert!(admins.is_admin(&session));
+
+        let expired = auth.sign(&Session {
+            expires_at: unix_now() - 1,
+            ..session
+        });
+        assert_eq!(auth.verify(&expired), None);
+    }
+
+    #[test]
+    fn the_session_is_read_among_other_cookies() {
+        let auth = auth("secret");
+        let session = Session {
+            steam_id: 42,
+            expires_at: unix_now() + 60,
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            COOKIE,
+            HeaderValue::from_str(&format!(
+                "theme=dark; {SESSION_COOKIE}={}",
+                auth.sign(&session)
+            ))
+            .unwrap(),
+        );
+
+        assert_eq!(auth.session_from_headers(&headers), Some(session));
+        assert_eq!(
+            auth.callback_url(),
+            "http://127.0.0.1:3000/api/auth/steam/callback"
+        );
+    }
+}
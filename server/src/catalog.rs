@@ -0,0 +1,112 @@
+//! Local copy of Steam's most played chart, used to suggest games nobody in a room owns yet.
+//! Names and everything else about the charted games come from their store pages, see
+//! `metadata`.
+
+use std::time::{Duration, Instant};
+
+use tokio::sync::RwLock;
+
+use crate::AppModel;
+use crate::steam::steam_apps::MostPlayedGame;
+use crate::steam::steam_client::SteamClient;
+
+/// The most played chart is rolled up daily, an hourly refresh is plenty
+pub const DEFAULT_MOST_PLAYED_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct AppCatalog {
+    most_played: Vec<MostPlayedGame>,
+    most_played_fetched_at: Option<Instant>,
+    most_played_ttl: Duration,
+}
+
+impl Default for AppCatalog {
+    fn default() -> Self {
+        Self::with_ttl(DEFAULT_MOST_PLAYED_TTL)
+    }
+}
+
+impl AppCatalog {
+    pub fn with_ttl(most_played_ttl: Duration) -> Self {
+        Self {
+            most_played: Vec::new(),
+            most_played_fetched_at: None,
+            most_played_ttl,
+        }
+    }
+
+    /// Most played games, best ranked first
+    pub fn most_played(&self) -> &[MostPlayedGame] {
+        &self.most_played
+    }
+
+    fn set_most_played(&mut self, mut most_played: Vec<MostPlayedGame>) {
+        most_played.sort_by_key(|game| game.rank);
+        self.most_played = most_played;
+        self.most_played_fetched_at = Some(Instant::now());
+    }
+
+    /// Past the soft API budget an out of date copy is kept
+    fn needs_most_played(&self, prefers_cache: bool) -> bool {
+        self.most_played_fetched_at
+            .is_none_or(|fetched_at| !prefers_cache && fetched_at.elapsed() > self.most_played_ttl)
+    }
+}
+
+/// Refreshes the most played chart when it's out of date. Failures keep the previous copy.
+pub async fn refresh_catalog(app_model: &RwLock<AppModel>, steam_client: &SteamClient) {
+    let prefers_cache = steam_client.quota().prefers_cache();
+    if !app_model
+        .read()
+        .await
+        .catalog
+        .needs_most_played(prefers_cache)
+    {
+        return;
+    }
+
+    match steam_client.get_most_played_games().await {
+        Ok(most_played) => app_model.write().await.catalog.set_most_played(most_played),
+        Err(error) => log::warn!("Can't refresh the most played games: {error}"),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub fn charted(app_ids: &[u64]) -> AppCatalog {
+        let mut catalog = AppCatalog::default();
+        catalog.set_most_played(
+            app_ids
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(i, &app_id)| MostPlayedGame {
+                    rank: i as u32 + 1,
+                    app_id,
+                    peak_in_game: 1000,
+                })
+                .collect(),
+        );
+        catalog
+    }
+
+    #[test]
+    fn keeps_the_chart_in_rank_order_until_it_expires() {
+        let catalog = charted(&[730, 570, 440]);
+        let ranked: Vec<u64> = catalog.most_played().iter().map(|g| g.app_id).collect();
+        assert_eq!(ranked, [730, 570, 440]);
+        assert!(!catalog.needs_most_played(false));
+        assert!(AppCatalog::default().needs_most_played(true));
+
+        let expired = AppCatalog {
+            most_played_ttl: Duration::ZERO,
+            ..catalog
+        };
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(expired.needs_most_played(false));
+        // Past the soft budget the old chart is good enough
+        assert!(!expired.needs_most_played(true));
+    }
+}
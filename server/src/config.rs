@@ -0,0 +1,473 @@
+//! Server configuration, layered from lowest to highest precedence: built-in defaults, a TOML
+//! file, environment variables and command line flags.
+//!
+//! The file is `--config` (or `STEAM_DILEMMA_CONFIG`), else `steam_dilemma.toml` when it
+//! exists. Every setting may be left out of it:
+//!
+//! ```toml
+//! port = 8080
+//! cors_origins = ["https://dilemma.example"]
+//!
+//! [rooms]
+//! idle_timeout_secs = 3600
+//!
+//! [steam]
+//! cache = "sqlite"
+//! cache_ttl_secs = 600
+//! ```
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use axum::http::HeaderValue;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::DEFAULT_MOST_PLAYED_TTL;
+use crate::metadata::DEFAULT_REVIEWS_TTL;
+use crate::news::DEFAULT_NEWS_TTL;
+use crate::room_access::DEFAULT_MAX_SPECTATORS;
+use crate::room_expiry::{DEFAULT_EXPIRY_WARNING, DEFAULT_IDLE_TIMEOUT, DEFAULT_MAX_LIFETIME};
+use crate::steam::steam_cache::{CacheKind, DEFAULT_CACHE_TTL};
+use crate::steam::steam_openid::STEAM_OPENID_PROVIDER;
+use crate::steam::steam_quota::{DEFAULT_HARD_BUDGET, DEFAULT_SOFT_BUDGET};
+use crate::steam::steam_transport::TransportKind;
+
+const DEFAULT_CONFIG_FILE: &str = "steam_dilemma.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Can't read the config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid config file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Missing Steam API key, set steam.api_key, STEAM_API_KEY or --steam-api-key")]
+    MissingApiKey,
+    #[error("Invalid CORS origin {0}")]
+    InvalidCorsOrigin(String),
+    #[error("The soft daily budget ({soft}) is above the hard one ({hard})")]
+    InvalidBudget { soft: u64, hard: u64 },
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    /// The compiled client, served at `/`
+    pub static_dir: PathBuf,
+    /// Origins allowed to call the API from a browser. Empty allows any origin.
+    pub cors_origins: Vec<String>,
+    /// SQLite file keeping customers and rooms, see `storage`
+    pub database: PathBuf,
+    /// `tracing` filter, such as `server=debug,tower_http=debug`
+    pub log_filter: String,
+    /// Where browsers reach the server, such as `https://dilemma.example`. Steam sends users
+    /// back there after signing in. Empty means `http://<bind_address>:<port>`.
+    pub public_url: String,
+    /// Key signing session cookies. Empty picks a random one, which signs everyone out on
+    /// restart.
+    pub session_secret: String,
+    /// SteamID64s of the accounts that may use the admin endpoints, such as
+    /// `/api/admin/steam_usage`. Empty leaves them closed to everyone.
+    pub admin_steam_ids: Vec<u64>,
+    pub rooms: RoomsConfig,
+    pub steam: SteamConfig,
+}
+
+/// When rooms are closed for good, see `room_expiry`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomsConfig {
+    /// Rooms with no activity for this long are closed
+    pub idle_timeout_secs: u64,
+    /// Rooms are closed this long after being created, however busy
+    pub max_lifetime_secs: u64,
+    /// How long before closing a room its members are warned
+    pub expiry_warning_secs: u64,
+    /// How many spectators a room takes
+    pub max_spectators: usize,
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SteamConfig {
+    pub api_key: String,
+    /// Where the day's API calls are counted, see `steam_quota`
+    pub usage_file: PathBuf,
+    pub soft_daily_budget: u64,
+    pub hard_daily_budget: u64,
+    pub transport: TransportKind,
+    pub recordings_file: PathBuf,
+    /// Cache of raw Steam responses, see `steam_cache`
+    pub cache: CacheKind,
+    pub cache_file: PathBuf,
+    pub cache_ttl_secs: u64,
+    pub reviews_ttl_secs: u64,
+    pub news_ttl_secs: u64,
+    pub most_played_ttl_secs: u64,
+    /// OpenID endpoint used to sign in through Steam
+    pub openid_provider: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 3000,
+            static_dir: PathBuf::from("client/dist"),
+            cors_origins: Vec::new(),
+            database: PathBuf::from("steam_dilemma.sqlite"),
+            log_filter: format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")),
+            public_url: String::new(),
+            session_secret: String::new(),
+            admin_steam_ids: Vec::new(),
+            rooms: RoomsConfig::default(),
+            steam: SteamConfig::default(),
+        }
+    }
+}
+
+impl Default for RoomsConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout_secs: DEFAULT_IDLE_TIMEOUT.as_secs(),
+            max_lifetime_secs: DEFAULT_MAX_LIFETIME.as_secs(),
+            expiry_warning_secs: DEFAULT_EXPIRY_WARNING.as_secs(),
+            max_spectators: DEFAULT_MAX_SPECTATORS,
+        }
+    }
+}
+
+impl Default for SteamConfig {
+    fn default() -> Self {
+        Self {
+            api_key: String::new(),
+            usage_file: PathBuf::from("steam_usage.json"),
+            soft_daily_budget: DEFAULT_SOFT_BUDGET,
+            hard_daily_budget: DEFAULT_HARD_BUDGET,
+            transport: TransportKind::Http,
+            recordings_file: PathBuf::from("steam_recordings.json"),
+            cache: CacheKind::Memory,
+            cache_file: PathBuf::from("steam_cache.sqlite"),
+            cache_ttl_secs: DEFAULT_CACHE_TTL.as_secs(),
+            reviews_ttl_secs: DEFAULT_REVIEWS_TTL.as_secs(),
+            news_ttl_secs: DEFAULT_NEWS_TTL.as_secs(),
+            most_played_ttl_secs: DEFAULT_MOST_PLAYED_TTL.as_secs(),
+            openid_provider: STEAM_OPENID_PROVIDER.to_owned(),
+        }
+    }
+}
+
+// Keeps the session secret out of logs
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("bind_address", &self.bind_address)
+            .field("port", &self.port)
+            .field("static_dir", &self.static_dir)
+            .field("cors_origins", &self.cors_origins)
+            .field("database", &self.database)
+            .field("log_filter", &self.log_filter)
+            .field("public_url", &self.public_url)
+            .field("session_secret", &"<hidden>")
+            .field("admin_steam_ids", &self.admin_steam_ids)
+            .field("rooms", &self.rooms)
+            .field("steam", &self.steam)
+            .finish()
+    }
+}
+
+// Keeps the API key out of logs
+impl fmt::Debug for SteamConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SteamConfig")
+            .field("api_key", &"<hidden>")
+            .field("usage_file", &self.usage_file)
+            .field("soft_daily_budget", &self.soft_daily_budget)
+            .field("hard_daily_budget", &self.hard_daily_budget)
+            .field("transport", &self.transport)
+            .field("recordings_file", &self.recordings_file)
+            .field("cache", &self.cache)
+            .field("cache_file", &self.cache_file)
+            .field("cache_ttl_secs", &self.cache_ttl_secs)
+            .field("reviews_ttl_secs", &self.reviews_ttl_secs)
+            .field("news_ttl_secs", &self.news_ttl_secs)
+            .field("most_played_ttl_secs", &self.most_played_ttl_secs)
+            .field("openid_provider", &self.openid_provider)
+            .finish()
+    }
+}
+
+/// Command line flags, each of which can also be set from the environment variable it names
+#[derive(Debug, Default, Parser)]
+#[command(about = "Serves the Steam Dilemma client and API")]
+pub struct Cli {
+    /// TOML configuration file
+    #[arg(long, env = "STEAM_DILEMMA_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "STEAM_DILEMMA_BIND_ADDRESS")]
+    pub bind_address: Option<IpAddr>,
+    #[arg(long, env = "STEAM_DILEMMA_PORT")]
+    pub port: Option<u16>,
+    #[arg(long, env = "STEAM_DILEMMA_STATIC_DIR")]
+    pub static_dir: Option<PathBuf>,
+    /// Allowed CORS origin, repeat the flag or separate them with commas
+    #[arg(
+        long = "cors-origin",
+        env = "STEAM_DILEMMA_CORS_ORIGINS",
+        value_delimiter = ','
+    )]
+    pub cors_origins: Option<Vec<String>>,
+    #[arg(long, env = "STEAM_DILEMMA_DATABASE")]
+    pub database: Option<PathBuf>,
+    #[arg(long, env = "RUST_LOG")]
+    pub log_filter: Option<String>,
+    #[arg(long, env = "STEAM_DILEMMA_PUBLIC_URL")]
+    pub public_url: Option<String>,
+    #[arg(long, env = "STEAM_DILEMMA_SESSION_SECRET", hide_env_values = true)]
+    pub session_secret: Option<String>,
+    /// Admin SteamID64, repeat the flag or separate them with commas
+    #[arg(
+        long = "admin-steam-id",
+        env = "STEAM_DILEMMA_ADMIN_STEAM_IDS",
+        value_delimiter = ','
+    )]
+    pub admin_steam_ids: Option<Vec<u64>>,
+    #[arg(long, env = "STEAM_DILEMMA_ROOM_IDLE_TIMEOUT_SECS")]
+    pub room_idle_timeout_secs: Option<u64>,
+    #[arg(long, env = "STEAM_DILEMMA_ROOM_MAX_LIFETIME_SECS")]
+    pub room_max_lifetime_secs: Option<u64>,
+    #[arg(long, env = "STEAM_DILEMMA_ROOM_EXPIRY_WARNING_SECS")]
+    pub room_expiry_warning_secs: Option<u64>,
+    #[arg(long, env = "STEAM_DILEMMA_ROOM_MAX_SPECTATORS")]
+    pub room_max_spectators: Option<usize>,
+    #[arg(long, env = "STEAM_API_KEY", hide_env_values = true)]
+    pub steam_api_key: Option<String>,
+    #[arg(long, env = "STEAM_USAGE_FILE")]
+    pub steam_usage_file: Option<PathBuf>,
+    #[arg(long, env = "STEAM_SOFT_DAILY_BUDGET")]
+    pub steam_soft_daily_budget: Option<u64>,
+    #[arg(long, env = "STEAM_HARD_DAILY_BUDGET")]
+    pub steam_hard_daily_budget: Option<u64>,
+    /// `http`, `record` or `replay`
+    #[arg(long, env = "STEAM_TRANSPORT")]
+    pub steam_transport: Option<TransportKind>,
+    #[arg(long, env = "STEAM_RECORDINGS_FILE")]
+    pub steam_recordings_file: Option<PathBuf>,
+    /// `memory`, `sqlite` or `none`
+    #[arg(long, env = "STEAM_CACHE")]
+    pub steam_cache: Option<CacheKind>,
+    #[arg(long, env = "STEAM_CACHE_FILE")]
+    pub steam_cache_file: Option<PathBuf>,
+    #[arg(long, env = "STEAM_CACHE_TTL_SECS")]
+    pub steam_cache_ttl_secs: Option<u64>,
+    #[arg(long, env = "STEAM_REVIEWS_TTL_SECS")]
+    pub steam_reviews_ttl_secs: Option<u64>,
+    #[arg(long, env = "STEAM_NEWS_TTL_SECS")]
+    pub steam_news_ttl_secs: Option<u64>,
+    #[arg(long, env = "STEAM_MOST_PLAYED_TTL_SECS")]
+    pub steam_most_played_ttl_secs: Option<u64>,
+    #[arg(long, env = "STEAM_OPENID_PROVIDER")]
+    pub steam_openid_provider: Option<String>,
+}
+
+impl Config {
+    /// Reads the configuration of this process from its file, environment and arguments
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::layered(Cli::parse())
+    }
+
+    /// The defaults, overridden by the file then by `cli`, which clap has already merged
+    /// with the environment
+    pub fn layered(cli: Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+        config.apply(cli);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        toml::from_str(&text).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    fn apply(&mut self, cli: Cli) {
+        fn set<T>(setting: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *setting = value;
+            }
+        }
+
+        set(&mut self.bind_address, cli.bind_address);
+        set(&mut self.port, cli.port);
+        set(&mut self.static_dir, cli.static_dir);
+        set(&mut self.cors_origins, cli.cors_origins);
+        set(&mut self.database, cli.database);
+        set(&mut self.log_filter, cli.log_filter);
+        set(&mut self.public_url, cli.public_url);
+        set(&mut self.session_secret, cli.session_secret);
+        set(&mut self.admin_steam_ids, cli.admin_steam_ids);
+
+        let rooms = &mut self.rooms;
+        set(&mut rooms.idle_timeout_secs, cli.room_idle_timeout_secs);
+        set(&mut rooms.max_lifetime_secs, cli.room_max_lifetime_secs);
+        set(&mut rooms.expiry_warning_secs, cli.room_expiry_warning_secs);
+        set(&mut rooms.max_spectators, cli.room_max_spectators);
+
+        let steam = &mut self.steam;
+        set(&mut steam.api_key, cli.steam_api_key);
+        set(&mut steam.usage_file, cli.steam_usage_file);
+        set(&mut steam.soft_daily_budget, cli.steam_soft_daily_budget);
+        set(&mut steam.hard_daily_budget, cli.steam_hard_daily_budget);
+        set(&mut steam.transport, cli.steam_transport);
+        set(&mut steam.recordings_file, cli.steam_recordings_file);
+        set(&mut steam.cache, cli.steam_cache);
+        set(&mut steam.cache_file, cli.steam_cache_file);
+        set(&mut steam.cache_ttl_secs, cli.steam_cache_ttl_secs);
+        set(&mut steam.reviews_ttl_secs, cli.steam_reviews_ttl_secs);
+        set(&mut steam.news_ttl_secs, cli.steam_news_ttl_secs);
+        set(
+            &mut steam.most_played_ttl_secs,
+            cli.steam_most_played_ttl_secs,
+        );
+        set(&mut steam.openid_provider, cli.steam_openid_provider);
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.steam.api_key.is_empty() {
+            return Err(ConfigError::MissingApiKey);
+        }
+        if self.steam.soft_daily_budget > self.steam.hard_daily_budget {
+            return Err(ConfigError::InvalidBudget {
+                soft: self.steam.soft_daily_budget,
+                hard: self.steam.hard_daily_budget,
+            });
+        }
+        self.cors_origin_headers().map(|_| ())
+    }
+
+    pub fn socket_address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind_address, self.port)
+    }
+
+    pub fn public_url(&self) -> String {
+        if self.public_url.is_empty() {
+            format!("http://{}", self.socket_address())
+        } else {
+            self.public_url.clone()
+        }
+    }
+
+    /// `cors_origins` as header values, checked at startup by `validate`
+    pub fn cors_origin_headers(&self) -> Result<Vec<HeaderValue>, ConfigError> {
+        self.cors_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin)
+                    .map_err(|_| ConfigError::InvalidCorsOrigin(origin.clone()))
+            })
+            .collect()
+    }
+
+    /// The effective configuration as TOML, with the API key and session secret hidden
+    pub fn to_toml(&self) -> String {
+        let mut shown = self.clone();
+        shown.steam.api_key = "<hidden>".to_owned();
+        if !shown.session_secret.is_empty() {
+            shown.session_secret = "<hidden>".to_owned();
+        }
+        toml::to_string_pretty(&shown).unwrap_or_else(|error| error.to_string())
+    }
+}
+
+impl SteamConfig {
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl_secs)
+    }
+
+    pub fn reviews_ttl(&self) -> Duration {
+        Duration::from_secs(self.reviews_ttl_secs)
+    }
+
+    pub fn news_ttl(&self) -> Duration {
+        Duration::from_secs(self.news_ttl_secs)
+    }
+
+    pub fn most_played_ttl(&self) -> Duration {
+        Duration::from_secs(self.most_played_ttl_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{name}-{}.toml", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn flags_override_the_file_which_overrides_defaults() {
+        let path = write_config(
+            "config-layers",
+            r#"
+            port = 8080
+            static_dir = "public"
+
+            [steam]
+            api_key = "from-file"
+            cache = "sqlite"
+            "#,
+        );
+        let cli = Cli::try_parse_from([
+            "server",
+            "--config",
+            path.to_str().unwrap(),
+            "--port",
+            "9000",
+            "--cors-origin",
+            "https://a.example,https://b.example",
+        ])
+        .unwrap();
+
+        let config = Config::layered(cli).unwrap();
+
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.static_dir, PathBuf::from("public"));
+        assert_eq!(config.cors_origins.len(), 2);
+        assert_eq!(config.steam.cache, CacheKind::Sqlite);
+        assert_eq!(config.steam.transport, TransportKind::Http);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        let path = write_config("config-unknown", "prot = 8080\n");
+        let error = Config::from_file(&path).unwrap_err();
+        assert!(matches!(error, ConfigError::Parse { .. }));
+        let _ = std::fs::remove_file(&path);
+    }
+}
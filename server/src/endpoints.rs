@@ -0,0 +1,161 @@
+//! Routes for the endpoints of `library::api`.
+//!
+//! `Router::endpoint` takes the method and path from the endpoint, and only accepts handlers
+//! reading the endpoint's `Request` and `Params` and answering with its `Response`, so a
+//! handler can't drift from what the client expects of it.
+
+use std::future::Future;
+
+use axum::{
+    Router,
+    extract::State,
+    handler::Handler,
+    response::{IntoResponseParts, Json},
+    routing::{MethodRouter, delete, get, post},
+};
+use library::api::{Endpoint, Method};
+
+use crate::api_error::{ApiJson, ApiPath, ErrorResponse};
+use crate::auth::{Admin, Session};
+
+/// Extractors handlers of the endpoint `E` may take. The body and the path parameter can only
+/// be read as the endpoint's own `Request` and `Params`.
+pub trait ExtractorOf<E> {}
+
+impl<E: Endpoint> ExtractorOf<E> for ApiJson<E::Request> {}
+
+impl<E: Endpoint> ExtractorOf<E> for ApiPath<E::Params> {}
+
+impl<E, S> ExtractorOf<E> for State<S> {}
+
+impl<E> ExtractorOf<E> for Session {}
+
+impl<E> ExtractorOf<E> for Option<Session> {}
+
+impl<E> ExtractorOf<E> for Admin {}
+
+/// What handlers of the endpoint `E` answer with: its `Response` as JSON, maybe with headers,
+/// or an error
+pub trait Answers<E> {}
+
+impl<E: Endpoint> Answers<E> for Json<E::Response> {}
+
+impl<E: Endpoint, R: Answers<E>> Answers<E> for Result<R, ErrorResponse> {}
+
+impl<E: Endpoint, P: IntoResponseParts, R: Answers<E>> Answers<E> for (P, R) {}
+
+/// Handlers of the endpoint `E`, taking the extractors `Args`
+pub trait Handles<E, Args> {}
+
+macro_rules! handles {
+    ($($arg:ident),*) => {
+        impl<E, F, Fut, $($arg: ExtractorOf<E>,)*> Handles<E, ($($arg,)*)> for F
+        where
+            F: FnOnce($($arg),*) -> Fut,
+            Fut: Future,
+            Fut::Output: Answers<E>,
+        {
+        }
+    };
+}
+
+handles!();
+handles!(T1);
+handles!(T1, T2);
+handles!(T1, T2, T3);
+handles!(T1, T2, T3, T4);
+handles!(T1, T2, T3, T4, T5);
+
+pub trait EndpointRouter<S> {
+    /// Routes `endpoint` to `handler`
+    fn endpoint<E, H, T, A>(self, endpoint: E, handler: H) -> Self
+    where
+        E: Endpoint,
+        H: Handler<T, S> + Handles<E, A>,
+        T: 'static;
+}
+
+impl<S: Clone + Send + Sync + 'static> EndpointRouter<S> for Router<S> {
+    fn endpoint<E, H, T, A>(self, _: E, handler: H) -> Self
+    where
+        E: Endpoint,
+        H: Handler<T, S> + Handles<E, A>,
+        T: 'static,
+    {
+        let method_router: MethodRouter<S> = match E::METHOD {
+            Method::Get => get(handler),
+            Method::Post => post(handler),
+            Method::Delete => delete(handler),
+        };
+        self.route(E::PATH, method_router)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Body, to_bytes};
+    use axum::http::{Request, StatusCode};
+    use library::api::{GetRoomByCode, JoinRoom};
+    use library::{JoinRoomRequest, Room, RoomResponse};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn answer(ApiPath(code): ApiPath<String>) -> Result<Json<RoomResponse>, ErrorResponse> {
+        Ok(Json(RoomResponse {
+            room: Room {
+                code,
+                ..Room::new(1, Default::default())
+            },
+        }))
+    }
+
+    async fn join(
+        ApiPath(room_id): ApiPath<u64>,
+        ApiJson(request): ApiJson<JoinRoomRequest>,
+    ) -> Json<RoomResponse> {
+        Json(RoomResponse {
+            room: Room {
+                code: request.steam_id,
+                ..Room::new(room_id, Default::default())
+            },
+        })
+    }
+
+    async fn call(router: Router, request: Request<Body>) -> RoomResponse {
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn routed_endpoints_get_their_parameter_back() {
+        let router = Router::new().endpoint(GetRoomByCode, answer);
+        let code = "ab c/d?".to_owned();
+
+        let request = Request::get(GetRoomByCode::path(&code))
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(call(router, request).await.room.code, code);
+    }
+
+    #[tokio::test]
+    async fn routed_endpoints_read_their_request() {
+        let router = Router::new().endpoint(JoinRoom, join);
+        let request = JoinRoomRequest {
+            steam_id: "76561197960287930".to_owned(),
+            ..Default::default()
+        };
+
+        let request = Request::post(JoinRoom::path(&7))
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&request).unwrap()))
+            .unwrap();
+        let RoomResponse { room } = call(router, request).await;
+
+        assert_eq!(room.id, 7);
+        assert_eq!(room.code, "76561197960287930");
+    }
+}
@@ -1,27 +1,58 @@
+mod api_error;
+mod auth;
+mod catalog;
+mod config;
+mod endpoints;
+mod metadata;
+mod news;
+mod recommender;
+mod room_access;
+mod room_codes;
+mod room_expiry;
+mod room_phases;
+mod room_sync;
 mod steam;
+mod storage;
 
-use axum::{
-    Json, Router,
-    extract::State,
-    response::Json as ResponseJson,
-    routing::{get, post},
-};
+use axum::{Router, extract::State, response::Json as ResponseJson, routing::get};
 use library::{
-    Consultant, CounterResponse, Customer, Game, NewCustomerResponse, Room, SteamGameLibrary,
+    ApiErrorCode, AppOwnership, Consultant, ContentLimits, CounterResponse, CreateRoomRequest,
+    Customer, FreeToPlayRequest, FreeToPlayResponse, Game, GetCustomerLibraryRequest,
+    JoinRoomRequest, LeaveRoomRequest, LocalLibraryUpload, NewCustomerResponse, PhaseTimers,
+    RecommendationRequest, RecommendationsResponse, RefreshOwnershipRequest,
+    RefreshOwnershipResponse, Room, RoomPhase, RoomResponse, ServerMessage,
+    SetCustomerPreferencesRequest, SetGameInstalledRequest, SetRoomContentLimitsRequest, StaleData,
+    SteamGameLibrary, SteamUsageResponse, api, normalize_room_code,
 };
 use std::collections::HashMap;
-use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use tokio::sync::RwLock;
 use tower_http::{
-    cors::CorsLayer,
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer},
     services::{ServeDir, ServeFile},
     trace::TraceLayer,
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::steam::steam_client::SteamClient;
+use crate::api_error::{ApiJson, ApiPath, ErrorResponse};
+use crate::auth::{Admin, Auth, Session};
+use crate::catalog::AppCatalog;
+use crate::config::{Config, SteamConfig};
+use crate::endpoints::EndpointRouter;
+use crate::metadata::MetadataCache;
+use crate::news::NewsCache;
+use crate::room_sync::RoomEvents;
+use crate::steam::steam_client::{SteamClient, SteamError};
+use crate::steam::steam_local_library::customer_from_local_files;
+use crate::steam::steam_profile::PlayerSummary;
+use crate::steam::steam_quota;
+use crate::steam::steam_user_library::{SteamGame, SteamUserLibrary};
+use crate::storage::{CloseReason, Storage};
+
+/// News is fetched for the best ranked recommendations only, one Steam call per game
+const MAX_RECOMMENDATIONS_WITH_NEWS: usize = 20;
 
 #[derive(Debug, Clone)]
 pub struct AppModel {
@@ -29,76 +60,543 @@ pub struct AppModel {
     pub customers: Vec<Customer>,
     pub game_library: SteamGameLibrary,
     pub rooms: HashMap<u64, Room>,
+    pub metadata: MetadataCache,
+    pub catalog: AppCatalog,
+    pub news: NewsCache,
     pub counter: u64,
+    pub next_room_id: u64,
+    /// Hashes of the rooms' join passwords, never sent to clients
+    room_passwords: HashMap<u64, String>,
+    storage: Arc<Storage>,
+}
+
+impl Default for AppModel {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl AppModel {
+    /// An empty model that keeps nothing across restarts
     pub fn new() -> Self {
+        Self::with_storage(Storage::in_memory(), SteamGameLibrary::new())
+    }
+
+    /// The model as it was saved in `storage`: customers, open rooms and counters
+    pub fn load(storage: Storage, config: &SteamConfig) -> Self {
+        let stored = storage.load().unwrap_or_else(|error| {
+            log::error!("Can't load the saved rooms and customers: {error}");
+            Default::default()
+        });
+        log::info!(
+            "Loaded {} customers and {} open rooms",
+            stored.customers.len(),
+            stored.rooms.len()
+        );
+
+        let mut app_model = Self {
+            customers: stored.customers,
+            rooms: HashMap::new(),
+            counter: stored.counter,
+            next_room_id: stored.next_room_id,
+            room_passwords: stored.room_passwords,
+            metadata: MetadataCache::with_reviews_ttl(config.reviews_ttl()),
+            catalog: AppCatalog::with_ttl(config.most_played_ttl()),
+            news: NewsCache::with_ttl(config.news_ttl()),
+            ..Self::with_storage(
+                storage,
+                SteamGameLibrary {
+                    api_key: config.api_key.clone(),
+                },
+            )
+        };
+
+        for mut room in stored.rooms {
+            // Saved before rooms had codes or timestamps
+            if room.code.is_empty() {
+                room.code = app_model.new_room_code();
+                app_model.storage.save_room(&room);
+            }
+            if room.created_at == 0 {
+                room.created_at = unix_now();
+                room.last_active_at = room.created_at;
+                app_model.storage.save_room(&room);
+            }
+            app_model.rooms.insert(room.id, room);
+        }
+        app_model
+    }
+
+    fn with_storage(storage: Storage, game_library: SteamGameLibrary) -> Self {
         Self {
             consultants: Vec::new(),
             customers: Vec::new(),
-            game_library: SteamGameLibrary::new(),
+            game_library,
             rooms: HashMap::new(),
+            metadata: MetadataCache::default(),
+            catalog: AppCatalog::default(),
+            news: NewsCache::default(),
             counter: 0,
+            next_room_id: 0,
+            room_passwords: HashMap::new(),
+            storage: Arc::new(storage),
         }
     }
 
     pub fn increment_counter(&mut self) -> u64 {
         self.counter += 1;
+        self.storage.save_counter("counter", self.counter);
         self.counter
     }
+
+    /// Saves the customer as it is in the customer list
+    fn save_customer(&self, steam_id: Option<u64>, steam_name: &str) {
+        if let Some(customer) = self
+            .customers
+            .iter()
+            .find(|c| is_same_customer(c, steam_id, steam_name))
+        {
+            self.storage.save_customer(customer);
+        }
+    }
+
+    /// Stores a customer, replacing any previous one with the same Steam ID (or name, if
+    /// the ID is unknown). Preferences already set by the customer are kept, and so is having
+    /// been verified.
+    pub fn upsert_customer(&mut self, mut customer: Customer) -> Customer {
+        self.recall_customer(customer.steam_id, &customer.steam_name);
+        let existing = self
+            .customers
+            .iter_mut()
+            .find(|c| is_same_customer(c, customer.steam_id, &customer.steam_name));
+
+        match existing {
+            Some(existing) => {
+                customer.preferences = existing.preferences.clone();
+                customer.verified |= existing.verified;
+                *existing = customer.clone();
+            }
+            None => self.customers.push(customer.clone()),
+        }
+        self.storage.save_customer(&customer);
+        customer
+    }
+
+    /// Replaces the customer's preferences, both in the customer list and in every room they
+    /// are part of. Returns the updated customer.
+    pub fn set_customer_preferences(
+        &mut self,
+        request: &SetCustomerPreferencesRequest,
+    ) -> Option<Customer> {
+        self.recall_customer(request.steam_id, &request.steam_name);
+        let rooms_customers = self.rooms.values_mut().flat_map(|room| &mut room.customers);
+        let mut updated = None;
+
+        for customer in self.customers.iter_mut().chain(rooms_customers) {
+            if is_same_customer(customer, request.steam_id, &request.steam_name) {
+                customer.preferences = request.preferences.clone();
+                updated = Some(customer.clone());
+            }
+        }
+
+        self.save_customer(request.steam_id, &request.steam_name);
+        updated
+    }
+
+    /// A copy of the customer, in the customer list or in a room, that was verified as someone
+    /// other than the signed in account, see `room_access::may_act_as`
+    pub fn verified_as_someone_else(
+        &mut self,
+        steam_id: Option<u64>,
+        steam_name: &str,
+        signed_in: Option<u64>,
+    ) -> Option<&Customer> {
+        self.recall_customer(steam_id, steam_name);
+        let rooms_customers = self.rooms.values().flat_map(|room| &room.customers);
+        self.customers.iter().chain(rooms_customers).find(|c| {
+            is_same_customer(c, steam_id, steam_name) && !room_access::may_act_as(c, signed_in)
+        })
+    }
+
+    /// Applies a manual install toggle to the customer, both in the customer list and in
+    /// every room they are part of. Returns the updated customer.
+    pub fn set_game_installed(&mut self, request: &SetGameInstalledRequest) -> Option<Customer> {
+        self.recall_customer(request.steam_id, &request.steam_name);
+        let rooms_customers = self.rooms.values_mut().flat_map(|room| &mut room.customers);
+        let mut updated = None;
+
+        for customer in self.customers.iter_mut().chain(rooms_customers) {
+            if !is_same_customer(customer, request.steam_id, &request.steam_name) {
+                continue;
+            }
+            if let Some(game) = customer
+                .games
+                .iter_mut()
+                .find(|g| g.app_id == request.app_id)
+            {
+                game.installed = request.installed;
+                if request.size_on_disk.is_some() {
+                    game.size_on_disk = request.size_on_disk;
+                }
+                updated = Some(customer.clone());
+            }
+        }
+
+        self.save_customer(request.steam_id, &request.steam_name);
+        updated
+    }
+
+    /// Brings the room members' libraries in line with a fresh ownership check of the
+    /// shortlist, and reports who owns what. Members missing from `checked`, or whose
+    /// library couldn't be read, are reported as unknown and left untouched.
+    pub fn apply_shortlist_ownership(
+        &mut self,
+        room_id: u64,
+        app_ids: &[u64],
+        checked: Vec<(u64, Option<Vec<SteamGame>>)>,
+    ) -> Option<Vec<AppOwnership>> {
+        let checked: HashMap<u64, Vec<Game>> = checked
+            .into_iter()
+            .filter_map(|(steam_id, games)| {
+                Some((steam_id, games?.into_iter().map(Game::from).collect()))
+            })
+            .collect();
+        let room = self.rooms.get_mut(&room_id)?;
+
+        let mut ownership: Vec<AppOwnership> = app_ids
+            .iter()
+            .map(|&app_id| AppOwnership {
+                app_id,
+                owners: Vec::new(),
+                unknown: Vec::new(),
+            })
+            .collect();
+
+        for customer in &mut room.customers {
+            let owned = customer.steam_id.and_then(|id| checked.get(&id));
+
+            for entry in &mut ownership {
+                let Some(owned) = owned else {
+                    entry.unknown.push(customer.steam_name.clone());
+                    continue;
+                };
+
+                let in_library = customer.games.iter().any(|g| g.app_id == entry.app_id);
+                match owned.iter().find(|g| g.app_id == entry.app_id) {
+                    Some(game) => {
+                        entry.owners.push(customer.steam_name.clone());
+                        if !in_library {
+                            customer.games.push(game.clone());
+                        }
+                    }
+                    // Refunded, or the library we had was out of date
+                    None if in_library => customer.games.retain(|g| g.app_id != entry.app_id),
+                    None => {}
+                }
+            }
+        }
+
+        let updated = room.customers.clone();
+        for customer in updated {
+            if customer
+                .steam_id
+                .is_some_and(|id| checked.contains_key(&id))
+            {
+                self.upsert_customer(customer);
+            }
+        }
+
+        Some(ownership)
+    }
+
+    pub fn create_room(
+        &mut self,
+        content_limits: ContentLimits,
+        phase_timers: PhaseTimers,
+        host: u64,
+    ) -> Room {
+        self.next_room_id += 1;
+        self.storage.save_counter("next_room_id", self.next_room_id);
+        let room = Room {
+            host: Some(host),
+            code: self.new_room_code(),
+            phase_timers,
+            created_at: unix_now(),
+            last_active_at: unix_now(),
+            ..Room::new(self.next_room_id, content_limits)
+        };
+        self.storage.save_room(&room);
+        self.rooms.insert(room.id, room.clone());
+        room
+    }
+
+    /// A code no open room has
+    fn new_room_code(&self) -> String {
+        room_codes::generate(|code| self.room_by_code(code).is_some())
+    }
+
+    /// The open room with this code, as normalized by `normalize_room_code`
+    pub fn room_by_code(&self, code: &str) -> Option<&Room> {
+        self.rooms.values().find(|room| room.code == code)
+    }
+
+    /// Applies a change to the room and saves it, unless it is only chat. Returns false if
+    /// the room doesn't exist.
+    pub fn apply_room_event(&mut self, room_id: u64, message: &ServerMessage) -> bool {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return false;
+        };
+        room.apply(message);
+        room.last_active_at = unix_now();
+        // Chat is too frequent to be worth keeping across restarts on its own
+        if !matches!(message, ServerMessage::Chat { .. }) {
+            self.storage.save_room(room);
+        }
+
+        if let ServerMessage::Decided { app_id } = message {
+            let name = room
+                .nominations
+                .iter()
+                .find(|n| n.app_id == *app_id)
+                .map_or_else(|| app_id.to_string(), |n| n.name.clone());
+            self.storage.save_session_result(room, *app_id, &name);
+        }
+        true
+    }
+
+    pub fn set_room_content_limits(
+        &mut self,
+        room_id: u64,
+        content_limits: ContentLimits,
+    ) -> Option<Room> {
+        let room = self.rooms.get_mut(&room_id)?;
+        room.content_limits = content_limits;
+        room.last_active_at = unix_now();
+        self.storage.save_room(room);
+        Some(room.clone())
+    }
+
+    /// Sets or removes (with `None`) the password joining the room takes
+    pub fn set_room_password(&mut self, room_id: u64, password: Option<&str>) {
+        let password_hash = password.map(room_access::hash_password);
+        self.storage
+            .set_room_password(room_id, password_hash.as_deref());
+        match password_hash {
+            Some(password_hash) => self.room_passwords.insert(room_id, password_hash),
+            None => self.room_passwords.remove(&room_id),
+        };
+    }
+
+    /// Whether `password` opens the room, always true for rooms without one
+    pub fn room_password_ok(&self, room_id: u64, password: Option<&str>) -> bool {
+        match self.room_passwords.get(&room_id) {
+            Some(password_hash) => password
+                .is_some_and(|password| room_access::verify_password(password_hash, password)),
+            None => true,
+        }
+    }
+
+    /// Archives the room to storage and frees it, along with its members' libraries
+    pub fn close_room(&mut self, room_id: u64, reason: CloseReason) -> Option<Room> {
+        let mut room = self.rooms.remove(&room_id)?;
+        self.room_passwords.remove(&room_id);
+        room.phase = RoomPhase::Closed;
+        room.phase_deadline = None;
+        self.storage.close_room(&room, reason);
+        self.forget_customers_outside_rooms();
+        Some(room)
+    }
+
+    /// Drops the customers who aren't in any open room from memory. They stay in storage and
+    /// are read back when needed, see `recall_customer`.
+    fn forget_customers_outside_rooms(&mut self) {
+        let rooms = &self.rooms;
+        self.customers.retain(|customer| {
+            rooms.values().any(|room| {
+                room.customers
+                    .iter()
+                    .chain(&room.spectators)
+                    .any(|c| is_same_customer(c, customer.steam_id, &customer.steam_name))
+            })
+        });
+    }
+
+    /// Reads the customer back from storage when they were dropped from memory
+    fn recall_customer(&mut self, steam_id: Option<u64>, steam_name: &str) {
+        if self
+            .customers
+            .iter()
+            .any(|c| is_same_customer(c, steam_id, steam_name))
+        {
+            return;
+        }
+        if let Some(customer) = self.storage.load_customer(steam_id, steam_name) {
+            self.customers.push(customer);
+        }
+    }
+
+    /// Applies the cached metadata to the customer's library, both in the customer list and
+    /// in every room they are part of. See `metadata::classify_library`.
+    pub fn classify_customer(&mut self, steam_id: Option<u64>, steam_name: &str) {
+        let rooms_customers = self.rooms.values_mut().flat_map(|room| &mut room.customers);
+
+        for customer in self.customers.iter_mut().chain(rooms_customers) {
+            if is_same_customer(customer, steam_id, steam_name) {
+                metadata::classify_library(&mut customer.games, &self.metadata);
+            }
+        }
+        self.save_customer(steam_id, steam_name);
+    }
+}
+
+fn is_same_customer(customer: &Customer, steam_id: Option<u64>, steam_name: &str) -> bool {
+    match steam_id {
+        Some(steam_id) => customer.steam_id == Some(steam_id),
+        None => customer.steam_id.is_none() && customer.steam_name == steam_name,
+    }
 }
 
 // Server state containing the app model and other server-specific data
 #[derive(Clone)]
 struct AppState {
     app_model: Arc<RwLock<AppModel>>,
+    steam_client: Arc<SteamClient>,
+    room_events: Arc<RoomEvents>,
+    auth: Arc<Auth>,
+    /// `rooms.max_spectators`
+    max_spectators: usize,
 }
 
 #[tokio::main]
 async fn main() {
+    let config = Config::load();
+    // A configuration that can't be read is still reported through the default filter
+    let log_filter = match &config {
+        Ok(config) => config.log_filter.clone(),
+        Err(_) => Config::default().log_filter,
+    };
     tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")).into()
-            }),
-        )
+        .with(tracing_subscriber::EnvFilter::new(log_filter))
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    let config = match config {
+        Ok(config) => config,
+        Err(error) => {
+            tracing::error!("{error}");
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("Configuration:\n{}", config.to_toml());
+
     // Create the shared state
+    let app_model = AppModel::load(Storage::open_or_in_memory(&config.database), &config.steam);
+    let steam_client = SteamClient::from_config(&config.steam);
     let state = AppState {
-        app_model: Arc::new(RwLock::new(AppModel::new())),
+        app_model: Arc::new(RwLock::new(app_model)),
+        steam_client: Arc::new(steam_client),
+        room_events: Arc::new(RoomEvents::default()),
+        auth: Arc::new(Auth::new(&config)),
+        max_spectators: config.rooms.max_spectators,
     };
+    room_phases::spawn_phase_timers(state.clone());
+    room_expiry::spawn_room_expiry(state.clone(), config.rooms.clone());
+    steam_quota::spawn_usage_flush(state.steam_client.clone());
+    let steam_client = state.steam_client.clone();
+    let app_model = state.app_model.clone();
 
-    let app = create_router(state);
+    let app = create_router(state, &config);
 
     // Start the server
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(config.socket_address())
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
 
-    println!("🚀 Server running on http://127.0.0.1:3000");
-    tracing::info!("listening on {}", listener.local_addr().unwrap());
+    println!("🚀 Server running on http://{}", addr);
+    tracing::info!("listening on {}", addr);
 
     axum::serve(listener, app.layer(TraceLayer::new_for_http()))
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
+
+    // Calls counted since the last periodic flush, and the model's queued writes
+    tokio::task::spawn_blocking(move || {
+        steam_client.quota().flush();
+        app_model.blocking_read().storage.flush();
+    })
+    .await
+    .unwrap();
 }
 
-fn create_router(state: AppState) -> Router {
-    // Create a service to serve static files from the client/dist directory
-    // with fallback to index.html for SPA routing
-    let serve_dir =
-        ServeDir::new("client/dist").not_found_service(ServeFile::new("client/dist/index.html"));
+/// Resolves on Ctrl+C, or when the process is asked to terminate
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    // Configure CORS to allow requests from the client
-    let cors = CorsLayer::permissive(); // This allows all origins, methods, and headers for development
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("Shutting down");
+}
+
+fn create_router(state: AppState, config: &Config) -> Router {
+    // Serve the client from the static directory, with fallback to index.html for SPA routing
+    let serve_dir = ServeDir::new(&config.static_dir)
+        .not_found_service(ServeFile::new(config.static_dir.join("index.html")));
+
+    // Without configured origins any origin is allowed, which suits development. Credentials
+    // are allowed so the session cookie reaches the API from the client's dev server.
+    let origins = config.cors_origin_headers().unwrap_or_default();
+    let cors = if origins.is_empty() {
+        CorsLayer::very_permissive()
+    } else {
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods(AllowMethods::mirror_request())
+            .allow_headers(AllowHeaders::mirror_request())
+            .allow_credentials(true)
+    };
 
     Router::new()
-        // API routes
+        // API routes, see `library::api`
         .route("/api/health", get(health_check))
-        .route("/api/increment", post(increment_counter))
-        .route("/api/get_customer_library", post(get_customer_game_library))
+        .route(api::STEAM_LOGIN_PATH, get(auth::steam_login))
+        .route(auth::CALLBACK_PATH, get(auth::steam_callback))
+        .endpoint(api::GetSession, auth::get_session)
+        .endpoint(api::Logout, auth::logout)
+        .endpoint(api::Increment, increment_counter)
+        .endpoint(api::GetCustomerLibrary, get_customer_game_library)
+        .endpoint(api::UploadLocalLibrary, upload_local_library)
+        .endpoint(api::SetGameInstalled, set_game_installed)
+        .endpoint(api::SetCustomerPreferences, set_customer_preferences)
+        .endpoint(api::SetRoomContentLimits, set_room_content_limits)
+        .endpoint(api::GetRecommendations, get_recommendations)
+        .endpoint(api::GetFreeToPlay, get_free_to_play)
+        .endpoint(api::RefreshOwnership, refresh_ownership)
+        .endpoint(api::GetSteamUsage, get_steam_usage)
+        .endpoint(api::CreateRoom, create_room)
+        .endpoint(api::GetRoom, get_room)
+        .endpoint(api::CloseRoom, close_room)
+        .endpoint(api::GetRoomByCode, get_room_by_code)
+        .endpoint(api::JoinRoom, join_room)
+        .endpoint(api::LeaveRoom, leave_room)
+        .route(api::ROOM_SOCKET_PATH, get(room_sync::room_socket))
         .layer(cors) // Add CORS layer to API routes
         // Serve static files and SPA fallback
         .fallback_service(serve_dir)
@@ -121,40 +619,1236 @@ async fn increment_counter(State(state): State<AppState>) -> ResponseJson<Counte
     })
 }
 
-async fn get_customer_library_from_steam(steam_id: String) -> Customer {
-    let steam_client = SteamClient::from("B72EE916D1F9D8B67E1D5C55AD6436F4".to_string());
+/// Builds a customer from Steam's answers. Through a Steam outage, whatever Steam couldn't
+/// give us comes from `cached`, the last copy we have, and the customer is marked as stale.
+/// A private profile or library isn't an outage and never falls back on the cache.
+fn customer_from_steam(
+    steam_id: &str,
+    profile: Result<PlayerSummary, SteamError>,
+    library: Result<SteamUserLibrary, SteamError>,
+    cached: Option<Customer>,
+) -> Result<Customer, SteamError> {
+    let mut from_cache = false;
 
-    let mut customer = Customer {
-        steam_name: "ass".to_owned(),
-        steam_id: Some(123),
-        games: Vec::new(),
+    let steam_name = match profile {
+        Ok(profile) => profile.persona_name,
+        Err(error) => {
+            log::warn!("Can't get steam profile of {steam_id}: {error}");
+            match cached.as_ref().filter(|_| error.is_outage()) {
+                Some(cached) => {
+                    from_cache = true;
+                    cached.steam_name.clone()
+                }
+                None => steam_id.to_owned(),
+            }
+        }
     };
 
-    match steam_client.get_user_library(&steam_id).await {
+    let games = match library {
         Ok(library) => {
             log::info!("Library received with {} games", library.game_count);
-
-            customer.games = library
-                .games
-                .into_iter()
-                .map(|raw| Game::from(raw))
-                .collect();
+            library.games.into_iter().map(Game::from).collect()
         }
         Err(error) => {
-            log::error!("Can't get steam library : {error}")
+            log::error!("Can't get steam library : {error}");
+            let cached = cached.as_ref().filter(|_| error.is_outage()).ok_or(error)?;
+            from_cache = true;
+            cached.games.clone()
+        }
+    };
+
+    if !from_cache {
+        return Ok(Customer {
+            steam_name,
+            steam_id: steam_id.parse().ok(),
+            games,
+            fetched_at: Some(unix_now()),
+            ..Default::default()
+        });
+    }
+
+    let cached = cached.unwrap_or_default();
+    Ok(Customer {
+        steam_name,
+        games,
+        stale: Some(StaleData {
+            age_secs: cached
+                .fetched_at
+                .map_or(0, |fetched_at| unix_now().saturating_sub(fetched_at)),
+        }),
+        ..cached
+    })
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+/// Sends the stored library to the customer's rooms, then classifies their apps (games, DLC,
+/// soundtracks...) without holding up the response
+fn spawn_library_classification(state: &AppState, customer: &Customer) {
+    let state = state.clone();
+    let customer = customer.clone();
+    tokio::spawn(async move {
+        {
+            let mut app_model = state.app_model.write().await;
+            // Apps already cached by other customers are classified straight away
+            app_model.classify_customer(customer.steam_id, &customer.steam_name);
+            room_sync::publish_library_loaded(
+                &mut app_model,
+                &state.room_events,
+                customer.steam_id,
+                &customer.steam_name,
+            );
         }
+
+        metadata::classify_in_background(
+            state.app_model.clone(),
+            state.steam_client.clone(),
+            &state.room_events,
+            customer.steam_id,
+            customer.steam_name.clone(),
+        )
+        .await;
+    });
+}
+
+/// Fetches a customer from Steam and stores it, verified when `signed_in` as them. Through a
+/// Steam outage the last copy we have keeps the room going, see `customer_from_steam`; stale
+/// copies aren't stored again.
+async fn load_customer(
+    state: &AppState,
+    steam_id_str: &str,
+    signed_in: Option<u64>,
+) -> Result<Customer, SteamError> {
+    let profile = state.steam_client.get_player_summary(steam_id_str).await;
+    let library = state.steam_client.get_user_library(steam_id_str).await;
+
+    let steam_id = steam_id_str.parse().ok();
+    let cached = {
+        let mut app_model = state.app_model.write().await;
+        app_model.recall_customer(steam_id, steam_id_str);
+        app_model
+            .customers
+            .iter()
+            .find(|c| is_same_customer(c, steam_id, steam_id_str))
+            .cloned()
+    };
+
+    let mut customer = customer_from_steam(steam_id_str, profile, library, cached)?;
+    if customer.stale.is_some() {
+        return Ok(customer);
     }
+    customer.verified = signed_in.is_some() && customer.steam_id == signed_in;
 
-    customer
+    let customer = state.app_model.write().await.upsert_customer(customer);
+    spawn_library_classification(state, &customer);
+    Ok(customer)
 }
 
 async fn get_customer_game_library(
     State(state): State<AppState>,
-    Json(steam_id_str): Json<String>,
-) -> ResponseJson<NewCustomerResponse> {
+    session: Option<Session>,
+    ApiJson(request): ApiJson<GetCustomerLibraryRequest>,
+) -> Result<ResponseJson<NewCustomerResponse>, ErrorResponse> {
+    let steam_id_str = request.steam_id;
     tracing::info!("Steam ID request: {}", steam_id_str);
 
-    let customer = get_customer_library_from_steam(steam_id_str).await;
+    let signed_in = session.map(|session| session.steam_id);
+    let customer = load_customer(&state, &steam_id_str, signed_in)
+        .await
+        .map_err(|error| ErrorResponse::from(error).with_detail("steam_id", &steam_id_str))?;
+
+    Ok(ResponseJson(NewCustomerResponse { customer }))
+}
+
+/// Uploads are verified when signed in as the customer, and can't replace a verified customer
+/// otherwise
+async fn upload_local_library(
+    State(state): State<AppState>,
+    session: Option<Session>,
+    ApiJson(upload): ApiJson<LocalLibraryUpload>,
+) -> Result<ResponseJson<NewCustomerResponse>, ErrorResponse> {
+    tracing::info!(
+        "Local library upload from {} ({} manifests)",
+        upload.steam_name,
+        upload.app_manifests.len()
+    );
+
+    let signed_in = session.map(|session| session.steam_id);
+    let mut customer = customer_from_local_files(upload)?;
+    customer.verified = signed_in.is_some() && customer.steam_id == signed_in;
+    customer.fetched_at = Some(unix_now());
+
+    let mut app_model = state.app_model.write().await;
+    if let Some(verified) =
+        app_model.verified_as_someone_else(customer.steam_id, &customer.steam_name, signed_in)
+    {
+        return Err(sign_in_as(verified));
+    }
+    let customer = app_model.upsert_customer(customer);
+    drop(app_model);
+    spawn_library_classification(&state, &customer);
+
+    Ok(ResponseJson(NewCustomerResponse { customer }))
+}
+
+async fn set_game_installed(
+    State(state): State<AppState>,
+    session: Option<Session>,
+    ApiJson(request): ApiJson<SetGameInstalledRequest>,
+) -> Result<ResponseJson<NewCustomerResponse>, ErrorResponse> {
+    tracing::info!(
+        "{} marks app {} as installed: {}",
+        request.steam_name,
+        request.app_id,
+        request.installed
+    );
+
+    let mut app_model = state.app_model.write().await;
+    let signed_in = session.map(|session| session.steam_id);
+    if let Some(verified) =
+        app_model.verified_as_someone_else(request.steam_id, &request.steam_name, signed_in)
+    {
+        return Err(sign_in_as(verified));
+    }
+
+    let customer = app_model.set_game_installed(&request).ok_or_else(|| {
+        ErrorResponse::new(
+            ApiErrorCode::NotFound,
+            format!("{} doesn't own app {}", request.steam_name, request.app_id),
+        )
+        .with_detail("app_id", request.app_id)
+    })?;
+
+    Ok(ResponseJson(NewCustomerResponse { customer }))
+}
+
+async fn set_customer_preferences(
+    State(state): State<AppState>,
+    session: Session,
+    ApiJson(request): ApiJson<SetCustomerPreferencesRequest>,
+) -> Result<ResponseJson<NewCustomerResponse>, ErrorResponse> {
+    if request.steam_id != Some(session.steam_id) {
+        return Err(ErrorResponse::new(
+            ApiErrorCode::Forbidden,
+            "You can only change your own preferences",
+        ));
+    }
+
+    tracing::info!(
+        "{} updates preferences: {:?}",
+        request.steam_name,
+        request.preferences
+    );
+
+    let customer = state
+        .app_model
+        .write()
+        .await
+        .set_customer_preferences(&request)
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                ApiErrorCode::NotFound,
+                format!("Unknown customer {}", request.steam_name),
+            )
+        })?;
+
+    Ok(ResponseJson(NewCustomerResponse { customer }))
+}
+
+async fn set_room_content_limits(
+    State(state): State<AppState>,
+    session: Option<Session>,
+    ApiJson(request): ApiJson<SetRoomContentLimitsRequest>,
+) -> Result<ResponseJson<RoomResponse>, ErrorResponse> {
+    tracing::info!(
+        "Room {} content limits: {:?}",
+        request.room_id,
+        request.content_limits
+    );
+    let mut app_model = state.app_model.write().await;
+    let room = app_model
+        .rooms
+        .get(&request.room_id)
+        .ok_or_else(|| room_not_found(request.room_id))?;
+    require_host_or_admin(&state, room, session)?;
+    let room = app_model
+        .set_room_content_limits(request.room_id, request.content_limits)
+        .ok_or_else(|| room_not_found(request.room_id))?;
+
+    Ok(ResponseJson(RoomResponse { room }))
+}
+
+async fn get_recommendations(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<RecommendationRequest>,
+) -> Result<ResponseJson<RecommendationsResponse>, ErrorResponse> {
+    let room_not_found = || room_not_found(request.room_id);
+
+    let shared_app_ids = {
+        let app_model = state.app_model.read().await;
+        let room = app_model
+            .rooms
+            .get(&request.room_id)
+            .ok_or_else(room_not_found)?;
+        recommender::shared_app_ids(&room.customers)
+    };
+    metadata::ensure_metadata(
+        &state.app_model,
+        &state.steam_client,
+        shared_app_ids.iter().copied(),
+    )
+    .await;
+    metadata::ensure_reviews(&state.app_model, &state.steam_client, shared_app_ids).await;
+
+    let mut recommendations = {
+        let app_model = state.app_model.read().await;
+        let room = app_model
+            .rooms
+            .get(&request.room_id)
+            .ok_or_else(room_not_found)?;
+        recommender::recommend(room, &app_model.metadata, &request)
+    };
+    tracing::info!(
+        "{} recommendations for room {}",
+        recommendations.len(),
+        request.room_id
+    );
+
+    let top_app_ids = recommendations
+        .iter()
+        .take(MAX_RECOMMENDATIONS_WITH_NEWS)
+        .map(|r| r.app_id);
+    news::ensure_news(&state.app_model, &state.steam_client, top_app_ids).await;
+
+    let app_model = state.app_model.read().await;
+    for recommendation in &mut recommendations {
+        recommendation.latest_news = app_model
+            .news
+            .latest_headline(recommendation.app_id)
+            .cloned();
+        recommendation.latest_update = app_model.news.latest_update(recommendation.app_id).cloned();
+        recommendation.stale = [
+            app_model.metadata.stale_reviews_age(recommendation.app_id),
+            app_model.news.stale_age(recommendation.app_id),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .map(StaleData::from);
+    }
+
+    Ok(ResponseJson(RecommendationsResponse { recommendations }))
+}
+
+async fn get_free_to_play(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<FreeToPlayRequest>,
+) -> Result<ResponseJson<FreeToPlayResponse>, ErrorResponse> {
+    if !state
+        .app_model
+        .read()
+        .await
+        .rooms
+        .contains_key(&request.room_id)
+    {
+        return Err(room_not_found(request.room_id));
+    }
+
+    catalog::refresh_catalog(&state.app_model, &state.steam_client).await;
+    let most_played: Vec<u64> = {
+        let app_model = state.app_model.read().await;
+        app_model
+            .catalog
+            .most_played()
+            .iter()
+            .map(|game| game.app_id)
+            .collect()
+    };
+    metadata::ensure_metadata(
+        &state.app_model,
+        &state.steam_client,
+        most_played.iter().copied(),
+    )
+    .await;
+    let free_games: Vec<u64> = {
+        let app_model = state.app_model.read().await;
+        most_played
+            .into_iter()
+            .filter(|app_id| {
+                app_model
+                    .metadata
+                    .get(*app_id)
+                    .is_some_and(|app| app.details.is_free)
+            })
+            .collect()
+    };
+    metadata::ensure_reviews(&state.app_model, &state.steam_client, free_games).await;
+
+    let app_model = state.app_model.read().await;
+    let room = app_model
+        .rooms
+        .get(&request.room_id)
+        .ok_or_else(|| room_not_found(request.room_id))?;
+    let candidates =
+        recommender::free_to_play(room, &app_model.catalog, &app_model.metadata, &request);
+    tracing::info!(
+        "{} free-to-play candidates for room {}",
+        candidates.len(),
+        room.id
+    );
+
+    Ok(ResponseJson(FreeToPlayResponse { candidates }))
+}
+
+async fn refresh_ownership(
+    State(state): State<AppState>,
+    session: Option<Session>,
+    ApiJson(request): ApiJson<RefreshOwnershipRequest>,
+) -> Result<ResponseJson<RefreshOwnershipResponse>, ErrorResponse> {
+    let room_not_found = || room_not_found(request.room_id);
+
+    let steam_ids: Vec<u64> = {
+        let app_model = state.app_model.read().await;
+        let room = app_model
+            .rooms
+            .get(&request.room_id)
+            .ok_or_else(room_not_found)?;
+        // Each check costs a Steam call per member
+        room_access::require_member(room, session.map(|session| session.steam_id))?;
+        room.customers.iter().filter_map(|c| c.steam_id).collect()
+    };
+    tracing::info!(
+        "Checking ownership of {} apps for {} members of room {}",
+        request.app_ids.len(),
+        steam_ids.len(),
+        request.room_id
+    );
+
+    let checked = state
+        .steam_client
+        .get_owned_games_filtered(&steam_ids, &request.app_ids)
+        .await;
+    let ownership = state
+        .app_model
+        .write()
+        .await
+        .apply_shortlist_ownership(request.room_id, &request.app_ids, checked)
+        .ok_or_else(room_not_found)?;
+
+    Ok(ResponseJson(RefreshOwnershipResponse { ownership }))
+}
+
+async fn get_steam_usage(
+    State(state): State<AppState>,
+    Admin(admin): Admin,
+) -> ResponseJson<SteamUsageResponse> {
+    tracing::debug!("{} reads the Steam API usage", admin.steam_id);
+    ResponseJson(state.steam_client.quota().usage())
+}
+
+/// Admins can steer any room, which is the only way to steer rooms saved before they had a host
+fn require_host_or_admin(
+    state: &AppState,
+    room: &Room,
+    session: Option<Session>,
+) -> Result<(), ErrorResponse> {
+    if session.is_some_and(|session| state.auth.is_admin(&session)) {
+        return Ok(());
+    }
+    Ok(room_access::require_host(
+        room,
+        session.map(|session| session.steam_id),
+    )?)
+}
+
+fn sign_in_as(customer: &Customer) -> ErrorResponse {
+    ErrorResponse::new(
+        ApiErrorCode::Forbidden,
+        format!(
+            "{} signed in through Steam, sign in as them to change their library",
+            customer.steam_name
+        ),
+    )
+}
+
+fn room_not_found(room_id: u64) -> ErrorResponse {
+    ErrorResponse::new(
+        ApiErrorCode::NotFound,
+        format!("Room {} doesn't exist", room_id),
+    )
+    .with_detail("room_id", room_id)
+}
 
-    ResponseJson(NewCustomerResponse { customer })
+async fn create_room(
+    State(state): State<AppState>,
+    session: Session,
+    ApiJson(request): ApiJson<CreateRoomRequest>,
+) -> ResponseJson<RoomResponse> {
+    let room = state.app_model.write().await.create_room(
+        request.content_limits,
+        request.phase_timers,
+        session.steam_id,
+    );
+    tracing::info!(
+        "Room {} ({}) created by {}",
+        room.id,
+        room.code,
+        session.steam_id
+    );
+
+    ResponseJson(RoomResponse { room })
+}
+
+/// The whole room for those in it, its public view for everyone else
+fn room_as_seen_by(room: &Room, session: Option<Session>) -> Room {
+    if room_access::sees_whole_room(room, session.map(|session| session.steam_id)) {
+        room.clone()
+    } else {
+        room_access::public_view(room)
+    }
+}
+
+async fn get_room(
+    State(state): State<AppState>,
+    ApiPath(room_id): ApiPath<u64>,
+    session: Option<Session>,
+) -> Result<ResponseJson<RoomResponse>, ErrorResponse> {
+    let app_model = state.app_model.read().await;
+    let room = app_model
+        .rooms
+        .get(&room_id)
+        .map(|room| room_as_seen_by(room, session))
+        .ok_or_else(|| room_not_found(room_id))?;
+
+    Ok(ResponseJson(RoomResponse { room }))
+}
+
+async fn get_room_by_code(
+    State(state): State<AppState>,
+    ApiPath(code): ApiPath<String>,
+    session: Option<Session>,
+) -> Result<ResponseJson<RoomResponse>, ErrorResponse> {
+    let app_model = state.app_model.read().await;
+    let room = normalize_room_code(&code)
+        .and_then(|code| app_model.room_by_code(&code))
+        .map(|room| room_as_seen_by(room, session))
+        .ok_or_else(|| {
+            ErrorResponse::new(
+                ApiErrorCode::NotFound,
+                format!("No open room has the code {}", code),
+            )
+            .with_detail("code", &code)
+        })?;
+
+    Ok(ResponseJson(RoomResponse { room }))
+}
+
+async fn join_room(
+    State(state): State<AppState>,
+    ApiPath(room_id): ApiPath<u64>,
+    session: Option<Session>,
+    ApiJson(request): ApiJson<JoinRoomRequest>,
+) -> Result<ResponseJson<RoomResponse>, ErrorResponse> {
+    let signed_in = session.map(|session| session.steam_id);
+    let check_join = |app_model: &AppModel, steam_id: Option<u64>| {
+        let room = app_model
+            .rooms
+            .get(&room_id)
+            .ok_or_else(|| room_not_found(room_id))?;
+        let password_ok = app_model.room_password_ok(room_id, request.password.as_deref());
+        room_access::check_join(
+            room,
+            steam_id,
+            signed_in,
+            request.spectator,
+            password_ok,
+            state.max_spectators,
+        )
+        .map_err(ErrorResponse::from)
+    };
+    // Fail before spending Steam calls on a room that doesn't exist or won't let them in
+    check_join(
+        &*state.app_model.read().await,
+        request.steam_id.trim().parse().ok(),
+    )?;
+
+    let mut customer = if request.spectator {
+        spectator(&mut *state.app_model.write().await, &request.steam_id)?
+    } else {
+        load_customer(&state, &request.steam_id, signed_in)
+            .await
+            .map_err(|error| {
+                let ErrorResponse(mut error) = error.into();
+                error.message = format!(
+                    "Can't read the Steam library of {}: {}",
+                    request.steam_id, error.message
+                );
+                ErrorResponse(error.with_detail("steam_id", &request.steam_id))
+            })?
+    };
+    customer.verified = signed_in.is_some() && customer.steam_id == signed_in;
+    tracing::info!(
+        "{} joins room {} (verified: {}, spectator: {})",
+        customer.steam_name,
+        room_id,
+        customer.verified,
+        request.spectator
+    );
+
+    let mut app_model = state.app_model.write().await;
+    // The room may have been locked while Steam was being read
+    check_join(&app_model, customer.steam_id)?;
+    let event = if request.spectator {
+        ServerMessage::SpectatorJoined {
+            spectator: customer,
+        }
+    } else {
+        ServerMessage::MemberJoined { member: customer }
+    };
+    room_sync::apply_and_publish(&mut app_model, &state.room_events, room_id, event);
+
+    Ok(ResponseJson(RoomResponse {
+        room: app_model.rooms[&room_id].clone(),
+    }))
+}
+
+/// Spectators bring no library, so Steam isn't asked about them: they go by the name we know
+/// them under, or by their Steam ID
+fn spectator(app_model: &mut AppModel, steam_id_str: &str) -> Result<Customer, ErrorResponse> {
+    let steam_id_str = steam_id_str.trim();
+    let steam_id: u64 = steam_id_str.parse().map_err(|_| {
+        ErrorResponse::new(
+            ApiErrorCode::BadRequest,
+            format!("{steam_id_str} isn't a SteamID64"),
+        )
+        .with_detail("steam_id", steam_id_str)
+    })?;
+    app_model.recall_customer(Some(steam_id), steam_id_str);
+    let steam_name = app_model
+        .customers
+        .iter()
+        .find(|c| c.steam_id == Some(steam_id))
+        .map_or_else(|| steam_id_str.to_owned(), |c| c.steam_name.clone());
+    Ok(Customer {
+        steam_id: Some(steam_id),
+        steam_name,
+        ..Default::default()
+    })
+}
+
+async fn leave_room(
+    State(state): State<AppState>,
+    ApiPath(room_id): ApiPath<u64>,
+    session: Option<Session>,
+    ApiJson(request): ApiJson<LeaveRoomRequest>,
+) -> Result<ResponseJson<RoomResponse>, ErrorResponse> {
+    let mut app_model = state.app_model.write().await;
+    let room = app_model
+        .rooms
+        .get(&room_id)
+        .ok_or_else(|| room_not_found(room_id))?;
+
+    // The room's copy has the name they joined under, which may differ from what the request has
+    let member = room
+        .customers
+        .iter()
+        .chain(&room.spectators)
+        .find(|c| is_same_customer(c, request.steam_id, &request.steam_name));
+    if let Some(member) = member
+        && !room_access::may_remove(room, member, session.map(|session| session.steam_id))
+    {
+        return Err(ErrorResponse::new(
+            ApiErrorCode::Forbidden,
+            format!(
+                "{} signed in through Steam, only they or the host can take them out",
+                member.steam_name
+            ),
+        ));
+    }
+    let member = member.map(|c| (c.steam_id, c.steam_name.clone()));
+    if let Some((steam_id, steam_name)) = member {
+        tracing::info!("{} left room {}", steam_name, room_id);
+        room_sync::apply_and_publish(
+            &mut app_model,
+            &state.room_events,
+            room_id,
+            ServerMessage::MemberLeft {
+                steam_id,
+                steam_name,
+            },
+        );
+    }
+
+    Ok(ResponseJson(RoomResponse {
+        room: app_model.rooms[&room_id].clone(),
+    }))
+}
+
+async fn close_room(
+    State(state): State<AppState>,
+    ApiPath(room_id): ApiPath<u64>,
+    session: Option<Session>,
+) -> Result<ResponseJson<()>, ErrorResponse> {
+    let mut app_model = state.app_model.write().await;
+    let room = app_model
+        .rooms
+        .get(&room_id)
+        .ok_or_else(|| room_not_found(room_id))?;
+    require_host_or_admin(&state, room, session)?;
+    if !room_sync::close_room(
+        &mut app_model,
+        &state.room_events,
+        room_id,
+        CloseReason::Closed,
+    ) {
+        return Err(room_not_found(room_id));
+    }
+    tracing::info!("Room {} closed", room_id);
+
+    Ok(ResponseJson(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use library::CustomerPreferences;
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::steam::steam_cache::NoCache;
+    use crate::steam::steam_openid::{STEAM_OPENID_PROVIDER, SteamOpenId};
+    use crate::steam::steam_transport::FakeTransport;
+
+    fn app_model() -> AppModel {
+        AppModel::with_storage(
+            Storage::in_memory(),
+            SteamGameLibrary {
+                api_key: String::new(),
+            },
+        )
+    }
+
+    fn owned(app_ids: &[u64]) -> Vec<Game> {
+        owned_on_steam(app_ids)
+            .into_iter()
+            .map(Game::from)
+            .collect()
+    }
+
+    fn owned_on_steam(app_ids: &[u64]) -> Vec<SteamGame> {
+        app_ids
+            .iter()
+            .map(|&app_id| SteamGame {
+                app_id,
+                name: format!("Game {app_id}"),
+                total_playtime: 0,
+                playtime_windows_forever: 0,
+                playtime_mac_forever: 0,
+                playtime_linux_forever: 0,
+                playtime_deck_forever: 0,
+                img_icon_url: String::new(),
+            })
+            .collect()
+    }
+
+    fn member(steam_name: &str, steam_id: Option<u64>, app_ids: &[u64]) -> Customer {
+        Customer {
+            steam_name: steam_name.to_owned(),
+            steam_id,
+            games: owned(app_ids),
+            ..Default::default()
+        }
+    }
+
+    fn library(app_ids: &[u64]) -> Result<SteamUserLibrary, SteamError> {
+        Ok(SteamUserLibrary {
+            game_count: app_ids.len() as u32,
+            games: owned_on_steam(app_ids),
+        })
+    }
+
+    fn profile(persona_name: &str) -> Result<PlayerSummary, SteamError> {
+        Ok(PlayerSummary {
+            persona_name: persona_name.to_owned(),
+        })
+    }
+
+    fn cached(fetched_at: Option<u64>) -> Option<Customer> {
+        Some(Customer {
+            fetched_at,
+            ..member("Alice", Some(1), &[620])
+        })
+    }
+
+    fn app_ids(customer: &Customer) -> Vec<u64> {
+        customer.games.iter().map(|g| g.app_id).collect()
+    }
+
+    #[test]
+    fn fresh_customers_come_from_steam() {
+        let customer = customer_from_steam(
+            "1",
+            profile("Alicia"),
+            library(&[620, 730]),
+            cached(Some(1)),
+        )
+        .unwrap();
+
+        assert_eq!(customer.steam_name, "Alicia");
+        assert_eq!(app_ids(&customer), [620, 730]);
+        assert!(customer.stale.is_none());
+        assert!(customer.fetched_at.unwrap() > 1);
+    }
+
+    #[test]
+    fn steam_outages_fall_back_on_the_cached_copy() {
+        let fetched_at = unix_now() - 600;
+
+        let customer = customer_from_steam(
+            "1",
+            Err(SteamError::Unavailable),
+            Err(SteamError::QuotaExceeded),
+            cached(Some(fetched_at)),
+        )
+        .unwrap();
+        assert_eq!(customer.steam_name, "Alice");
+        assert_eq!(app_ids(&customer), [620]);
+        assert!(customer.stale.unwrap().age_secs >= 600);
+        assert_eq!(customer.fetched_at, Some(fetched_at));
+
+        // Only the profile is missing, the library is fresh but the name may not be
+        let customer = customer_from_steam(
+            "1",
+            Err(SteamError::Unavailable),
+            library(&[620, 730]),
+            cached(Some(fetched_at)),
+        )
+        .unwrap();
+        assert_eq!(customer.steam_name, "Alice");
+        assert_eq!(app_ids(&customer), [620, 730]);
+        assert!(customer.stale.is_some());
+
+        let customer = customer_from_steam(
+            "1",
+            profile("Alicia"),
+            Err(SteamError::Unavailable),
+            cached(Some(fetched_at)),
+        )
+        .unwrap();
+        assert_eq!(customer.steam_name, "Alicia");
+        assert_eq!(app_ids(&customer), [620]);
+        assert!(customer.stale.is_some());
+    }
+
+    #[test]
+    fn local_file_customers_are_marked_stale_too() {
+        let customer = customer_from_steam(
+            "1",
+            Err(SteamError::Unavailable),
+            Err(SteamError::Unavailable),
+            cached(None),
+        )
+        .unwrap();
+
+        assert_eq!(customer.stale, Some(StaleData { age_secs: 0 }));
+    }
+
+    #[test]
+    fn private_or_uncached_libraries_fail() {
+        let error = customer_from_steam(
+            "1",
+            profile("Alicia"),
+            Err(SteamError::NoData),
+            cached(Some(1)),
+        )
+        .unwrap_err();
+        assert!(matches!(error, SteamError::NoData));
+
+        let error = customer_from_steam(
+            "1",
+            Err(SteamError::Unavailable),
+            Err(SteamError::Unavailable),
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(error, SteamError::Unavailable));
+
+        // A private profile keeps the Steam ID as name rather than a stale one
+        let customer = customer_from_steam(
+            "1",
+            Err(SteamError::NoData),
+            library(&[620]),
+            cached(Some(1)),
+        )
+        .unwrap();
+        assert_eq!(customer.steam_name, "1");
+        assert!(customer.stale.is_none());
+    }
+
+    const PLAYER_SUMMARIES: &str = "https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v2";
+    const OWNED_GAMES: &str = "https://api.steampowered.com/IPlayerService/GetOwnedGames/v1";
+
+    /// A server whose Steam knows Alice (Steam ID 1), who owns Portal 2
+    fn state() -> AppState {
+        let fake = FakeTransport::default();
+        fake.respond(
+            PLAYER_SUMMARIES,
+            StatusCode::OK,
+            r#"{"response":{"players":[{"personaname":"Alice"}]}}"#,
+        );
+        fake.respond(
+            OWNED_GAMES,
+            StatusCode::OK,
+            r#"{"response":{"game_count":1,"games":[{"appid":620,"name":"Portal 2","playtime_forever":90}]}}"#,
+        );
+        AppState {
+            app_model: Arc::new(RwLock::new(app_model())),
+            steam_client: Arc::new(SteamClient::with_backends(
+                String::new(),
+                Box::new(fake),
+                Box::new(NoCache),
+            )),
+            room_events: Arc::new(RoomEvents::default()),
+            auth: Arc::new(Auth::with_secret(
+                SteamOpenId::new(STEAM_OPENID_PROVIDER.to_owned()),
+                b"secret".to_vec(),
+                "http://127.0.0.1:3000/".to_owned(),
+            )),
+            max_spectators: 4,
+        }
+    }
+
+    fn signed_in(steam_id: u64) -> Session {
+        Session {
+            steam_id,
+            expires_at: u64::MAX,
+        }
+    }
+
+    async fn hosted_room(state: &AppState) -> u64 {
+        let ResponseJson(RoomResponse { room }) = create_room(
+            State(state.clone()),
+            signed_in(9),
+            ApiJson(CreateRoomRequest::default()),
+        )
+        .await;
+        assert_eq!(room.host, Some(9));
+        room.id
+    }
+
+    async fn join(state: &AppState, room_id: u64) -> Result<Room, ErrorResponse> {
+        let request = JoinRoomRequest {
+            steam_id: "1".to_owned(),
+            ..Default::default()
+        };
+        join_room(
+            State(state.clone()),
+            ApiPath(room_id),
+            None,
+            ApiJson(request),
+        )
+        .await
+        .map(|ResponseJson(response)| response.room)
+    }
+
+    async fn leave(state: &AppState, room_id: u64) -> Result<Room, ErrorResponse> {
+        let request = LeaveRoomRequest {
+            steam_id: Some(1),
+            steam_name: "Alice".to_owned(),
+        };
+        leave_room(
+            State(state.clone()),
+            ApiPath(room_id),
+            None,
+            ApiJson(request),
+        )
+        .await
+        .map(|ResponseJson(response)| response.room)
+    }
+
+    #[tokio::test]
+    async fn joins_a_room_with_the_steam_library() {
+        let state = state();
+        let room_id = hosted_room(&state).await;
+
+        let room = join(&state, room_id).await.unwrap();
+
+        assert_eq!(room.customers.len(), 1);
+        assert_eq!(room.customers[0].steam_name, "Alice");
+        assert_eq!(app_ids(&room.customers[0]), [620]);
+        assert!(!room.customers[0].verified);
+        assert_eq!(
+            state.app_model.read().await.rooms[&room_id].customers.len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn joining_a_missing_room_fails_before_asking_steam() {
+        let state = state();
+
+        let ErrorResponse(error) = join(&state, 42).await.unwrap_err();
+
+        assert_eq!(error.code, ApiErrorCode::NotFound);
+        assert_eq!(error.details["room_id"], "42");
+        assert_eq!(state.steam_client.quota().usage().total_calls, 0);
+    }
+
+    #[tokio::test]
+    async fn leaving_twice_leaves_the_room_as_it_is() {
+        let state = state();
+        let room_id = hosted_room(&state).await;
+        join(&state, room_id).await.unwrap();
+
+        let room = leave(&state, room_id).await.unwrap();
+        assert!(room.customers.is_empty());
+
+        let room = leave(&state, room_id).await.unwrap();
+        assert!(room.customers.is_empty());
+
+        let ErrorResponse(error) = leave(&state, room_id + 1).await.unwrap_err();
+        assert_eq!(error.code, ApiErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn spectators_join_without_asking_steam() {
+        let state = AppState {
+            // Steam would refuse every call
+            steam_client: Arc::new(SteamClient::with_backends(
+                String::new(),
+                Box::new(FakeTransport::default()),
+                Box::new(NoCache),
+            )),
+            ..state()
+        };
+        let room_id = hosted_room(&state).await;
+        state
+            .app_model
+            .write()
+            .await
+            .upsert_customer(member("Alice", Some(1), &[620]));
+        let watch = |steam_id: &str| {
+            let request = JoinRoomRequest {
+                steam_id: steam_id.to_owned(),
+                spectator: true,
+                ..Default::default()
+            };
+            join_room(
+                State(state.clone()),
+                ApiPath(room_id),
+                Some(signed_in(2)),
+                ApiJson(request),
+            )
+        };
+
+        let ResponseJson(RoomResponse { room }) = watch("1").await.unwrap();
+        assert_eq!(room.spectators[0].steam_name, "Alice");
+        assert!(room.spectators[0].games.is_empty());
+        let ResponseJson(RoomResponse { room }) = watch(" 2 ").await.unwrap();
+        assert_eq!(room.spectators[1].steam_name, "2");
+        assert!(room.spectators[1].verified);
+
+        let ErrorResponse(error) = watch("Bob").await.unwrap_err();
+        assert_eq!(error.code, ApiErrorCode::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn only_the_host_closes_a_room() {
+        let state = state();
+        let room_id = hosted_room(&state).await;
+        let close =
+            |session: Option<Session>| close_room(State(state.clone()), ApiPath(room_id), session);
+
+        for session in [None, Some(signed_in(1))] {
+            let ErrorResponse(error) = close(session).await.unwrap_err();
+            assert_eq!(error.code, ApiErrorCode::Forbidden);
+        }
+
+        assert!(close(Some(signed_in(9))).await.is_ok());
+        assert!(state.app_model.read().await.rooms.is_empty());
+
+        let ErrorResponse(error) = close(Some(signed_in(9))).await.unwrap_err();
+        assert_eq!(error.code, ApiErrorCode::NotFound);
+        let ErrorResponse(error) = join(&state, room_id).await.unwrap_err();
+        assert_eq!(error.code, ApiErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn only_admins_close_rooms_without_a_host() {
+        let config = Config {
+            admin_steam_ids: vec![5],
+            session_secret: "secret".to_owned(),
+            ..Config::default()
+        };
+        let state = AppState {
+            auth: Arc::new(Auth::new(&config)),
+            ..state()
+        };
+        let room_id = hosted_room(&state).await;
+        state
+            .app_model
+            .write()
+            .await
+            .rooms
+            .get_mut(&room_id)
+            .unwrap()
+            .host = None;
+        let close =
+            |session: Option<Session>| close_room(State(state.clone()), ApiPath(room_id), session);
+
+        for session in [None, Some(signed_in(1)), Some(signed_in(9))] {
+            let ErrorResponse(error) = close(session).await.unwrap_err();
+            assert_eq!(error.code, ApiErrorCode::Forbidden);
+        }
+
+        assert!(close(Some(signed_in(5))).await.is_ok());
+        assert!(state.app_model.read().await.rooms.is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_their_session_changes_a_verified_customer() {
+        let state = state();
+        let upload = |session: Option<Session>| {
+            let upload = LocalLibraryUpload {
+                steam_name: "Alice".to_owned(),
+                steam_id: Some(1),
+                library_folders: None,
+                app_manifests: Vec::new(),
+                local_config: None,
+            };
+            upload_local_library(State(state.clone()), session, ApiJson(upload))
+        };
+        let install = |session: Option<Session>| {
+            let request = SetGameInstalledRequest {
+                steam_name: "Alice".to_owned(),
+                steam_id: Some(1),
+                app_id: 620,
+                installed: true,
+                size_on_disk: None,
+            };
+            set_game_installed(State(state.clone()), session, ApiJson(request))
+        };
+
+        // Anyone can upload for a customer nobody verified, which stays unverified
+        let ResponseJson(response) = upload(None).await.unwrap();
+        assert!(!response.customer.verified);
+        let ResponseJson(response) = upload(Some(signed_in(1))).await.unwrap();
+        assert!(response.customer.verified);
+        state.app_model.write().await.customers[0].games = owned(&[620]);
+
+        for session in [None, Some(signed_in(2))] {
+            let ErrorResponse(error) = upload(session).await.unwrap_err();
+            assert_eq!(error.code, ApiErrorCode::Forbidden);
+            let ErrorResponse(error) = install(session).await.unwrap_err();
+            assert_eq!(error.code, ApiErrorCode::Forbidden);
+        }
+        let ResponseJson(response) = install(Some(signed_in(1))).await.unwrap();
+        assert!(response.customer.games[0].installed);
+    }
+
+    #[test]
+    fn chat_alone_doesnt_write_the_room() {
+        let mut app_model = app_model();
+        let room_id = app_model
+            .create_room(ContentLimits::default(), PhaseTimers::default(), 1)
+            .id;
+        let chat = ServerMessage::Chat {
+            message: library::ChatMessage {
+                from: "Alice".to_owned(),
+                text: "Portal 2?".to_owned(),
+                sent_at: 1,
+            },
+        };
+        let stored_chat =
+            |app_model: &AppModel| app_model.storage.load().unwrap().rooms[0].chat.len();
+
+        assert!(app_model.apply_room_event(room_id, &chat));
+        assert_eq!(app_model.rooms[&room_id].chat.len(), 1);
+        assert_eq!(stored_chat(&app_model), 0);
+
+        app_model.apply_room_event(room_id, &ServerMessage::Decided { app_id: 620 });
+        assert_eq!(stored_chat(&app_model), 1);
+    }
+
+    #[test]
+    fn closing_a_room_forgets_customers_until_needed() {
+        let mut app_model = app_model();
+        let room_id = app_model
+            .create_room(ContentLimits::default(), PhaseTimers::default(), 1)
+            .id;
+        let other_room = app_model
+            .create_room(ContentLimits::default(), PhaseTimers::default(), 1)
+            .id;
+        app_model.upsert_customer(Customer {
+            verified: true,
+            preferences: CustomerPreferences {
+                genres: vec!["Puzzle".to_owned()],
+                ..Default::default()
+            },
+            ..member("Alice", Some(1), &[620])
+        });
+        app_model.upsert_customer(member("Bob", Some(2), &[730]));
+        app_model.rooms.get_mut(&other_room).unwrap().customers =
+            vec![member("Bob", Some(2), &[730])];
+
+        app_model.close_room(room_id, CloseReason::Idle);
+        assert_eq!(app_model.customers.len(), 1);
+        assert_eq!(app_model.customers[0].steam_name, "Bob");
+
+        // Alice comes back from storage as she was, verified and with her preferences
+        assert!(
+            app_model
+                .verified_as_someone_else(Some(1), "Alice", None)
+                .is_some()
+        );
+        let alice = app_model.upsert_customer(member("Alice", Some(1), &[620, 730]));
+        assert!(alice.verified);
+        assert_eq!(alice.preferences.genres, ["Puzzle"]);
+        assert_eq!(app_model.customers.len(), 2);
+    }
+
+    #[test]
+    fn ownership_checks_update_member_libraries() {
+        let mut app_model = app_model();
+        let room_id = app_model
+            .create_room(ContentLimits::default(), PhaseTimers::default(), 1)
+            .id;
+        app_model.rooms.get_mut(&room_id).unwrap().customers = vec![
+            member("Alice", Some(1), &[620, 730]),
+            member("Bob", Some(2), &[620]),
+            member("Carol", None, &[620]),
+            member("Dan", Some(4), &[620]),
+        ];
+        let checked = vec![
+            (1, Some(owned_on_steam(&[620]))),
+            (2, Some(owned_on_steam(&[620, 440]))),
+            // Private profile
+            (4, None),
+        ];
+
+        let ownership = app_model
+            .apply_shortlist_ownership(room_id, &[620, 730, 440], checked)
+            .unwrap();
+
+        assert_eq!(ownership[0].owners, ["Alice", "Bob"]);
+        assert_eq!(ownership[0].unknown, ["Carol", "Dan"]);
+        assert!(ownership[1].owners.is_empty());
+        assert_eq!(ownership[2].owners, ["Bob"]);
+        let room = &app_model.rooms[&room_id];
+        let app_ids =
+            |i: usize| -> Vec<u64> { room.customers[i].games.iter().map(|g| g.app_id).collect() };
+        // Alice got a refund, Bob bought a game, the others are left as they were
+        assert_eq!(app_ids(0), [620]);
+        assert_eq!(app_ids(1), [620, 440]);
+        assert_eq!(app_ids(3), [620]);
+        assert_eq!(app_model.customers.len(), 2);
+        assert!(
+            app_model
+                .apply_shortlist_ownership(room_id + 1, &[620], Vec::new())
+                .is_none()
+        );
+    }
 }
@@ -0,0 +1,428 @@
+//! Cache of per-app store metadata used to filter and rank recommendations.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use library::{AppType, DeckCompatibility, Game, ReviewSummary};
+use tokio::sync::RwLock;
+
+use crate::room_sync::{self, RoomEvents};
+use crate::steam::steam_app_details::AppDetails;
+use crate::steam::steam_client::{SteamClient, SteamError};
+use crate::steam::steam_local_library::placeholder_name;
+use crate::{AppModel, is_same_customer};
+
+/// The store endpoints are rate limited to roughly 200 calls per 5 minutes, so a single
+/// recommendation request only fetches this many unknown apps
+const MAX_FETCHES_PER_REQUEST: usize = 40;
+
+/// Pause between two apps when classifying a whole library in the background. Each app costs
+/// two store calls, which keeps us under the store's rate limit.
+const BACKGROUND_FETCH_DELAY: Duration = Duration::from_secs(3);
+
+/// Number of apps fetched in the background before the library is regrouped, so results
+/// show up progressively for large libraries
+const BACKGROUND_BATCH_SIZE: usize = 20;
+
+/// Review scores move slowly once a game has a few thousand reviews
+pub const DEFAULT_REVIEWS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Apps kept in the cache. Past that the apps cached first are dropped, and fetched again
+/// when needed.
+const MAX_CACHED_APPS: usize = 20_000;
+
+#[derive(Debug, Clone)]
+pub struct AppMetadata {
+    pub details: AppDetails,
+    pub deck: DeckCompatibility,
+}
+
+#[derive(Debug, Clone)]
+struct CachedReviews {
+    summary: ReviewSummary,
+    fetched_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetadataCache {
+    entries: HashMap<u64, AppMetadata>,
+    reviews: HashMap<u64, CachedReviews>,
+    reviews_ttl: Duration,
+    /// Cached apps, in the order they were first cached
+    cached_order: VecDeque<u64>,
+    max_apps: usize,
+}
+
+impl Default for MetadataCache {
+    fn default() -> Self {
+        Self::with_reviews_ttl(DEFAULT_REVIEWS_TTL)
+    }
+}
+
+impl MetadataCache {
+    pub fn with_reviews_ttl(reviews_ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            reviews: HashMap::new(),
+            reviews_ttl,
+            cached_order: VecDeque::new(),
+            max_apps: MAX_CACHED_APPS,
+        }
+    }
+
+    pub fn get(&self, app_id: u64) -> Option<&AppMetadata> {
+        self.entries.get(&app_id)
+    }
+
+    pub fn insert(&mut self, app_id: u64, metadata: AppMetadata) {
+        self.make_room_for(app_id);
+        self.entries.insert(app_id, metadata);
+    }
+
+    pub fn missing(&self, app_ids: impl IntoIterator<Item = u64>) -> Vec<u64> {
+        app_ids
+            .into_iter()
+            .filter(|app_id| !self.entries.contains_key(app_id))
+            .collect()
+    }
+
+    pub fn reviews(&self, app_id: u64) -> Option<&ReviewSummary> {
+        self.reviews.get(&app_id).map(|cached| &cached.summary)
+    }
+
+    /// How old the cached reviews are, when they're past the point they should've been
+    /// refreshed
+    pub fn stale_reviews_age(&self, app_id: u64) -> Option<Duration> {
+        let age = self.reviews.get(&app_id)?.fetched_at.elapsed();
+        (age > self.reviews_ttl).then_some(age)
+    }
+
+    pub fn insert_reviews(&mut self, app_id: u64, summary: ReviewSummary) {
+        self.make_room_for(app_id);
+        let cached = CachedReviews {
+            summary,
+            fetched_at: Instant::now(),
+        };
+        self.reviews.insert(app_id, cached);
+    }
+
+    /// Drops the apps cached first when caching a new one would go past `max_apps`
+    fn make_room_for(&mut self, app_id: u64) {
+        if self.entries.contains_key(&app_id) || self.reviews.contains_key(&app_id) {
+            return;
+        }
+        while self.cached_order.len() >= self.max_apps {
+            let Some(oldest) = self.cached_order.pop_front() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+            self.reviews.remove(&oldest);
+        }
+        self.cached_order.push_back(app_id);
+    }
+
+    /// Past the soft API budget only reviews never fetched count as missing
+    fn missing_reviews(
+        &self,
+        app_ids: impl IntoIterator<Item = u64>,
+        prefers_cache: bool,
+    ) -> Vec<u64> {
+        app_ids
+            .into_iter()
+            .filter(|app_id| {
+                self.reviews.get(app_id).is_none_or(|cached| {
+                    !prefers_cache && cached.fetched_at.elapsed() > self.reviews_ttl
+                })
+            })
+            .collect()
+    }
+}
+
+pub async fn fetch_metadata(
+    steam_client: &SteamClient,
+    app_id: u64,
+) -> Result<AppMetadata, SteamError> {
+    let details = steam_client.get_app_details(app_id).await?;
+    let deck = steam_client
+        .get_deck_compatibility(app_id)
+        .await
+        .unwrap_or_else(|error| {
+            log::warn!("Can't get Steam Deck compatibility for {app_id}: {error}");
+            DeckCompatibility::Unknown
+        });
+
+    Ok(AppMetadata { details, deck })
+}
+
+/// Fetches metadata for the given apps that aren't cached yet. The model lock is only held
+/// to look up and store entries, never across a Steam request.
+pub async fn ensure_metadata(
+    app_model: &RwLock<AppModel>,
+    steam_client: &SteamClient,
+    app_ids: impl IntoIterator<Item = u64>,
+) {
+    let missing = app_model.read().await.metadata.missing(app_ids);
+    if missing.is_empty() {
+        return;
+    }
+    log::info!("Fetching metadata for {} apps", missing.len());
+
+    for app_id in missing.into_iter().take(MAX_FETCHES_PER_REQUEST) {
+        match fetch_metadata(steam_client, app_id).await {
+            Ok(metadata) => app_model.write().await.metadata.insert(app_id, metadata),
+            Err(SteamError::QuotaExceeded | SteamError::Unavailable) => break,
+            Err(error) => log::warn!("Can't get metadata for app {app_id}: {error}"),
+        }
+    }
+}
+
+/// Fetches review summaries for the given apps that aren't cached or are out of date, with
+/// the same per-request cap as `ensure_metadata`.
+pub async fn ensure_reviews(
+    app_model: &RwLock<AppModel>,
+    steam_client: &SteamClient,
+    app_ids: impl IntoIterator<Item = u64>,
+) {
+    let prefers_cache = steam_client.quota().prefers_cache();
+    let missing = app_model
+        .read()
+        .await
+        .metadata
+        .missing_reviews(app_ids, prefers_cache);
+
+    for app_id in missing.into_iter().take(MAX_FETCHES_PER_REQUEST) {
+        match steam_client.get_review_summary(app_id).await {
+            Ok(summary) => app_model
+                .write()
+                .await
+                .metadata
+                .insert_reviews(app_id, summary),
+            Err(SteamError::QuotaExceeded | SteamError::Unavailable) => break,
+            Err(error) => log::warn!("Can't get reviews for app {app_id}: {error}"),
+        }
+    }
+}
+
+/// Sets the type of every app in a library from cached metadata, replaces placeholder names
+/// and moves DLC and soundtracks under their base game when the customer owns it. Running it
+/// again regroups from scratch, so it can be applied as more metadata comes in.
+pub fn classify_library(games: &mut Vec<Game>, metadata: &MetadataCache) {
+    let mut flat = Vec::with_capacity(games.len());
+    for mut game in games.drain(..) {
+        flat.append(&mut game.dlc);
+        flat.push(game);
+    }
+
+    for game in &mut flat {
+        if let Some(app) = metadata.get(game.app_id) {
+            game.app_type = app.details.app_type();
+            if game.name == placeholder_name(game.app_id) && !app.details.name.is_empty() {
+                game.name = app.details.name.clone();
+            }
+        }
+    }
+
+    let owned: HashSet<u64> = flat.iter().map(|g| g.app_id).collect();
+    let base_game_of = |game: &Game| {
+        if !matches!(game.app_type, AppType::Dlc | AppType::Soundtrack) {
+            return None;
+        }
+        metadata
+            .get(game.app_id)?
+            .details
+            .base_game()
+            .filter(|base| *base != game.app_id && owned.contains(base))
+    };
+
+    let mut extras = Vec::new();
+    for game in flat {
+        match base_game_of(&game) {
+            Some(base) => extras.push((base, game)),
+            None => games.push(game),
+        }
+    }
+    for (base, extra) in extras {
+        if let Some(game) = games.iter_mut().find(|g| g.app_id == base) {
+            game.dlc.push(extra);
+        }
+    }
+}
+
+/// Fetches metadata for every app of a customer's library at a pace the store accepts, and
+/// regroups the stored library as batches come in.
+pub async fn classify_in_background(
+    app_model: Arc<RwLock<AppModel>>,
+    steam_client: Arc<SteamClient>,
+    room_events: &RoomEvents,
+    steam_id: Option<u64>,
+    steam_name: String,
+) {
+    let missing = {
+        let app_model = app_model.read().await;
+        let Some(customer) = app_model
+            .customers
+            .iter()
+            .find(|c| is_same_customer(c, steam_id, &steam_name))
+        else {
+            return;
+        };
+        let app_ids = customer
+            .games
+            .iter()
+            .flat_map(|g| std::iter::once(g.app_id).chain(g.dlc.iter().map(|d| d.app_id)));
+        app_model.metadata.missing(app_ids)
+    };
+    log::info!(
+        "Classifying {} apps of {} in the background",
+        missing.len(),
+        steam_name
+    );
+
+    'batches: for batch in missing.chunks(BACKGROUND_BATCH_SIZE) {
+        for &app_id in batch {
+            match fetch_metadata(&steam_client, app_id).await {
+                Ok(metadata) => app_model.write().await.metadata.insert(app_id, metadata),
+                Err(SteamError::QuotaExceeded) => {
+                    log::warn!("Steam API budget spent, stopping classification of {steam_name}");
+                    break 'batches;
+                }
+                Err(error) => log::warn!("Can't get metadata for app {app_id}: {error}"),
+            }
+            tokio::time::sleep(BACKGROUND_FETCH_DELAY).await;
+        }
+        let mut app_model = app_model.write().await;
+        app_model.classify_customer(steam_id, &steam_name);
+        room_sync::publish_library_classified(&mut app_model, room_events, steam_id, &steam_name);
+    }
+
+    // Apps cached by other customers' classification still need to be applied
+    let mut app_model = app_model.write().await;
+    app_model.classify_customer(steam_id, &steam_name);
+    room_sync::publish_library_classified(&mut app_model, room_events, steam_id, &steam_name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::steam::steam_app_details::FullGame;
+
+    fn owned(app_id: u64) -> Game {
+        Game {
+            id: 0,
+            app_id,
+            name: placeholder_name(app_id),
+            playtime_minutes: 0,
+            installed: false,
+            size_on_disk: None,
+            platform_playtime: Default::default(),
+            app_type: AppType::Unknown,
+            dlc: Vec::new(),
+        }
+    }
+
+    fn known(metadata: &mut MetadataCache, app_id: u64, steam_type: &str, base_game: Option<u64>) {
+        let details = AppDetails {
+            app_id,
+            name: format!("Name of {app_id}"),
+            steam_type: steam_type.to_owned(),
+            fullgame: base_game.map(|appid| FullGame { appid }),
+            ..Default::default()
+        };
+        metadata.insert(
+            app_id,
+            AppMetadata {
+                details,
+                deck: DeckCompatibility::Unknown,
+            },
+        );
+    }
+
+    #[test]
+    fn groups_dlc_and_soundtracks_under_owned_base_games() {
+        let mut metadata = MetadataCache::default();
+        known(&mut metadata, 10, "game", None);
+        known(&mut metadata, 11, "dlc", Some(10));
+        known(&mut metadata, 12, "music", Some(10));
+        // The base game isn't owned
+        known(&mut metadata, 21, "dlc", Some(20));
+        let mut games = vec![owned(11), owned(10), owned(12), owned(21), owned(30)];
+
+        classify_library(&mut games, &metadata);
+
+        let top: Vec<(u64, AppType)> = games.iter().map(|g| (g.app_id, g.app_type)).collect();
+        assert_eq!(
+            top,
+            [
+                (10, AppType::Game),
+                (21, AppType::Dlc),
+                (30, AppType::Unknown)
+            ]
+        );
+        let dlc: Vec<u64> = games[0].dlc.iter().map(|d| d.app_id).collect();
+        assert_eq!(dlc, [11, 12]);
+        assert_eq!(games[0].name, "Name of 10");
+        assert_eq!(games[2].name, placeholder_name(30));
+    }
+
+    #[test]
+    fn classifying_again_picks_up_new_metadata() {
+        let mut metadata = MetadataCache::default();
+        known(&mut metadata, 10, "game", None);
+        let mut games = vec![owned(10), owned(11)];
+        classify_library(&mut games, &metadata);
+        assert_eq!(games.len(), 2);
+
+        known(&mut metadata, 11, "dlc", Some(10));
+        classify_library(&mut games, &metadata);
+        classify_library(&mut games, &metadata);
+
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].dlc.len(), 1);
+        assert_eq!(games[0].dlc[0].app_type, AppType::Dlc);
+    }
+
+    #[test]
+    fn only_out_of_date_reviews_are_refetched_within_budget() {
+        let mut metadata = MetadataCache::with_reviews_ttl(Duration::ZERO);
+        let summary = ReviewSummary {
+            total_reviews: 10,
+            positive_ratio: 0.9,
+            review_score: 8,
+            description: "Very Positive".to_owned(),
+        };
+        metadata.insert_reviews(1, summary);
+        std::thread::sleep(Duration::from_millis(2));
+
+        assert_eq!(metadata.missing_reviews([1, 2], false), [1, 2]);
+        assert_eq!(metadata.missing_reviews([1, 2], true), [2]);
+        assert!(metadata.stale_reviews_age(1).is_some());
+        assert_eq!(metadata.stale_reviews_age(2), None);
+    }
+
+    #[test]
+    fn the_apps_cached_first_make_room_for_new_ones() {
+        let mut metadata = MetadataCache {
+            max_apps: 2,
+            ..MetadataCache::default()
+        };
+        let reviews = ReviewSummary {
+            total_reviews: 10,
+            positive_ratio: 0.9,
+            review_score: 8,
+            description: "Very Positive".to_owned(),
+        };
+        known(&mut metadata, 1, "game", None);
+        metadata.insert_reviews(1, reviews.clone());
+        known(&mut metadata, 2, "game", None);
+        known(&mut metadata, 2, "game", None);
+
+        known(&mut metadata, 3, "game", None);
+        assert_eq!(metadata.missing([1, 2, 3]), [1]);
+        assert!(metadata.reviews(1).is_none());
+
+        metadata.insert_reviews(4, reviews);
+        assert_eq!(metadata.missing([2, 3]), [2]);
+        assert!(metadata.reviews(4).is_some());
+    }
+}
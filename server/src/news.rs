@@ -0,0 +1,107 @@
+//! Cache of recent news headlines per app, shown next to recommended games.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use library::NewsHeadline;
+use tokio::sync::RwLock;
+
+use crate::AppModel;
+use crate::steam::steam_client::{SteamClient, SteamError};
+
+/// Headlines don't change often, and "did it get the update" is a question of days
+pub const DEFAULT_NEWS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Enough items to usually find the last patch notes among announcements
+const NEWS_PER_APP: u32 = 10;
+
+#[derive(Debug, Clone)]
+struct CachedNews {
+    headlines: Vec<NewsHeadline>,
+    fetched_at: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewsCache {
+    entries: HashMap<u64, CachedNews>,
+    ttl: Duration,
+}
+
+impl Default for NewsCache {
+    fn default() -> Self {
+        Self::with_ttl(DEFAULT_NEWS_TTL)
+    }
+}
+
+impl NewsCache {
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            ttl,
+        }
+    }
+
+    pub fn latest_headline(&self, app_id: u64) -> Option<&NewsHeadline> {
+        self.entries.get(&app_id)?.headlines.first()
+    }
+
+    /// The most recent news item tagged as patch notes
+    pub fn latest_update(&self, app_id: u64) -> Option<&NewsHeadline> {
+        self.entries
+            .get(&app_id)?
+            .headlines
+            .iter()
+            .find(|headline| headline.is_patch_notes)
+    }
+
+    /// How old the cached headlines are, when they're past the point they should've been
+    /// refreshed
+    pub fn stale_age(&self, app_id: u64) -> Option<Duration> {
+        let age = self.entries.get(&app_id)?.fetched_at.elapsed();
+        (age > self.ttl).then_some(age)
+    }
+
+    /// Past the soft API budget any cached copy will do
+    fn is_fresh(&self, app_id: u64, prefers_cache: bool) -> bool {
+        self.entries
+            .get(&app_id)
+            .is_some_and(|news| prefers_cache || news.fetched_at.elapsed() < self.ttl)
+    }
+}
+
+/// Fetches headlines for the given apps unless a fresh copy is cached.
+pub async fn ensure_news(
+    app_model: &RwLock<AppModel>,
+    steam_client: &SteamClient,
+    app_ids: impl IntoIterator<Item = u64>,
+) {
+    let prefers_cache = steam_client.quota().prefers_cache();
+    let stale: Vec<u64> = {
+        let app_model = app_model.read().await;
+        app_ids
+            .into_iter()
+            .filter(|app_id| !app_model.news.is_fresh(*app_id, prefers_cache))
+            .collect()
+    };
+
+    for app_id in stale {
+        match steam_client.get_news_for_app(app_id, NEWS_PER_APP).await {
+            Ok(items) => {
+                let mut headlines: Vec<NewsHeadline> =
+                    items.into_iter().map(NewsHeadline::from).collect();
+                headlines.sort_by_key(|headline| Reverse(headline.date));
+
+                app_model.write().await.news.entries.insert(
+                    app_id,
+                    CachedNews {
+                        headlines,
+                        fetched_at: Instant::now(),
+                    },
+                );
+            }
+            Err(SteamError::QuotaExceeded | SteamError::Unavailable) => break,
+            Err(error) => log::warn!("Can't get news for app {app_id}: {error}"),
+        }
+    }
+}
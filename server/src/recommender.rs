@@ -0,0 +1,819 @@
+//! Picks the games a room could play together out of its members' libraries.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use library::{
+    AppType, CompatibilityBadge, ContentLimits, Customer, DeckCompatibility, DlcInfo,
+    FreeToPlayCandidate, FreeToPlayRequest, Game, MemberCompatibility, MemberDlc, Platform,
+    Recommendation, RecommendationRequest, RecommendationSort, ReviewSummary, Room,
+};
+
+use crate::catalog::AppCatalog;
+use crate::metadata::{AppMetadata, MetadataCache};
+
+/// Number of free-to-play candidates returned when the request doesn't say
+const DEFAULT_FREE_TO_PLAY_LIMIT: usize = 10;
+
+/// Store categories for games that can be played online, whatever the group size:
+/// MMO, Cross-Platform Multiplayer, Online PvP, Online Co-op. LAN PvP (47) and LAN Co-op (48)
+/// need everyone on one network, so they only count as generic multiplayer.
+const ONLINE_CATEGORIES: [u64; 4] = [20, 27, 36, 38];
+
+/// Generic Multi-player, Co-op and PvP categories, which don't say online or local
+const MULTIPLAYER_CATEGORIES: [u64; 3] = [1, 9, 49];
+
+/// Shared/Split Screen categories, only playable by people sitting together
+const LOCAL_CATEGORIES: [u64; 3] = [24, 37, 39];
+
+/// Steam doesn't publish player caps, split screen rarely goes beyond this
+const MAX_LOCAL_PLAYERS: usize = 4;
+
+/// App ids owned by every one of `customers`, used to know which metadata to fetch before
+/// calling `recommend`.
+pub fn shared_app_ids(customers: &[Customer]) -> Vec<u64> {
+    let Some((first, others)) = customers.split_first() else {
+        return Vec::new();
+    };
+
+    first
+        .games
+        .iter()
+        .map(|g| g.app_id)
+        .filter(|app_id| {
+            others
+                .iter()
+                .all(|c| c.games.iter().any(|g| g.app_id == *app_id))
+        })
+        .collect()
+}
+
+/// Returns every game owned by all members of the room, filtered and ranked according to
+/// `request`. Games that can't run on some member's platform, that nobody can play in a
+/// language they speak, or that break the room's content limits are always left out.
+pub fn recommend(
+    room: &Room,
+    metadata: &MetadataCache,
+    request: &RecommendationRequest,
+) -> Vec<Recommendation> {
+    let customers = &room.customers;
+    let Some((first, others)) = customers.split_first() else {
+        return Vec::new();
+    };
+
+    let libraries: Vec<HashMap<u64, &Game>> = others
+        .iter()
+        .map(|customer| customer.games.iter().map(|g| (g.app_id, g)).collect())
+        .collect();
+
+    let mut recommendations: Vec<Recommendation> = first
+        .games
+        .iter()
+        .filter(|game| {
+            let metadata = metadata.get(game.app_id);
+            (request.include_non_games || is_game(game, metadata))
+                && speaks_a_supported_language(customers, metadata)
+                && within_content_limits(&room.content_limits, metadata)
+        })
+        .filter_map(|game| {
+            let mut copies = vec![(first, game)];
+            for (customer, library) in others.iter().zip(&libraries) {
+                copies.push((customer, *library.get(&game.app_id)?));
+            }
+            Some(shared_game(
+                game,
+                &copies,
+                metadata.get(game.app_id),
+                metadata.reviews(game.app_id),
+            ))
+        })
+        .filter(|r| !request.installed_by_everyone || r.missing_install.is_empty())
+        .filter(|r| {
+            r.compatibility
+                .iter()
+                .all(|c| c.badge != CompatibilityBadge::Unsupported)
+        })
+        .collect();
+
+    match request.sort {
+        RecommendationSort::Ready => recommendations.sort_by_key(|r| {
+            (
+                r.missing_install.len(),
+                Reverse(r.total_playtime_minutes),
+                review_rank(r.reviews.as_ref()),
+            )
+        }),
+        RecommendationSort::SmallestDownload => recommendations.sort_by_key(|r| {
+            (
+                // Unknown sizes go last, a confirmed zero-byte download goes first
+                r.download_bytes.is_none(),
+                r.download_bytes.unwrap_or_default(),
+                Reverse(r.total_playtime_minutes),
+                review_rank(r.reviews.as_ref()),
+            )
+        }),
+    }
+
+    recommendations
+}
+
+fn shared_game(
+    game: &Game,
+    copies: &[(&Customer, &Game)],
+    metadata: Option<&AppMetadata>,
+    reviews: Option<&ReviewSummary>,
+) -> Recommendation {
+    let (installed, missing): (Vec<_>, Vec<_>) = copies.iter().partition(|(_, g)| g.installed);
+
+    // Members who haven't installed the game rarely know its size, so estimate the download
+    // from the largest install anybody reported
+    let known_size = copies.iter().filter_map(|(_, g)| g.size_on_disk).max();
+    let download_bytes = if missing.is_empty() {
+        Some(0)
+    } else {
+        known_size.map(|size| size * missing.len() as u64)
+    };
+
+    Recommendation {
+        app_id: game.app_id,
+        name: game.name.clone(),
+        installed_by: installed
+            .iter()
+            .map(|(c, _)| c.steam_name.clone())
+            .collect(),
+        missing_install: missing.iter().map(|(c, _)| c.steam_name.clone()).collect(),
+        download_bytes,
+        total_playtime_minutes: copies.iter().map(|(_, g)| g.playtime_minutes).sum(),
+        compatibility: copies
+            .iter()
+            .map(|(c, _)| MemberCompatibility {
+                steam_name: c.steam_name.clone(),
+                platform: c.preferences.platform,
+                badge: compatibility_badge(c.preferences.platform, metadata),
+            })
+            .collect(),
+        dlc: copies
+            .iter()
+            .map(|(c, g)| MemberDlc {
+                steam_name: c.steam_name.clone(),
+                dlc: g
+                    .dlc
+                    .iter()
+                    .map(|d| DlcInfo {
+                        app_id: d.app_id,
+                        name: d.name.clone(),
+                        app_type: d.app_type,
+                    })
+                    .collect(),
+            })
+            .collect(),
+        latest_news: None,
+        latest_update: None,
+        reviews: reviews.cloned(),
+        stale: None,
+    }
+}
+
+/// Sort key putting better reviewed games first, used to break ties
+fn review_rank(reviews: Option<&ReviewSummary>) -> Reverse<u8> {
+    Reverse(reviews.map_or(0, |r| r.review_score))
+}
+
+/// Popular free games from Steam's most played chart that the room doesn't all own already,
+/// matching the room's size, genre preferences, platforms, languages and content limits.
+pub fn free_to_play(
+    room: &Room,
+    catalog: &AppCatalog,
+    metadata: &MetadataCache,
+    request: &FreeToPlayRequest,
+) -> Vec<FreeToPlayCandidate> {
+    let customers = &room.customers;
+    let liked_genres: Vec<&String> = customers
+        .iter()
+        .flat_map(|c| &c.preferences.genres)
+        .collect();
+
+    let mut candidates: Vec<FreeToPlayCandidate> = catalog
+        .most_played()
+        .iter()
+        .filter_map(|ranked| {
+            let app = metadata.get(ranked.app_id)?;
+            let details = &app.details;
+            if !details.is_free || details.app_type() != AppType::Game {
+                return None;
+            }
+
+            let owned_by: Vec<String> = customers
+                .iter()
+                .filter(|c| c.games.iter().any(|g| g.app_id == ranked.app_id))
+                .map(|c| c.steam_name.clone())
+                .collect();
+            // Those already show up in the owned-game recommendations
+            if !customers.is_empty() && owned_by.len() == customers.len() {
+                return None;
+            }
+
+            let categories: Vec<u64> = details.categories.iter().map(|c| c.id).collect();
+            if !supports_player_count(&categories, customers.len())
+                || !speaks_a_supported_language(customers, Some(app))
+                || !within_content_limits(&room.content_limits, Some(app))
+            {
+                return None;
+            }
+
+            let genres: Vec<String> = details
+                .genres
+                .iter()
+                .map(|g| g.description.clone())
+                .collect();
+            let matching_genres: Vec<String> = genres
+                .iter()
+                .filter(|g| liked_genres.iter().any(|l| l.eq_ignore_ascii_case(g)))
+                .cloned()
+                .collect();
+            if !liked_genres.is_empty() && matching_genres.is_empty() {
+                return None;
+            }
+
+            let compatibility: Vec<MemberCompatibility> = customers
+                .iter()
+                .map(|c| MemberCompatibility {
+                    steam_name: c.steam_name.clone(),
+                    platform: c.preferences.platform,
+                    badge: compatibility_badge(c.preferences.platform, Some(app)),
+                })
+                .collect();
+            if compatibility
+                .iter()
+                .any(|c| c.badge == CompatibilityBadge::Unsupported)
+            {
+                return None;
+            }
+
+            Some(FreeToPlayCandidate {
+                app_id: ranked.app_id,
+                name: details.name.clone(),
+                rank: ranked.rank,
+                peak_players: ranked.peak_in_game,
+                genres,
+                matching_genres,
+                owned_by,
+                compatibility,
+                reviews: metadata.reviews(ranked.app_id).cloned(),
+            })
+        })
+        .collect();
+
+    // Nobody has played these yet, so reviews weigh in before popularity
+    candidates.sort_by_key(|c| {
+        (
+            Reverse(c.matching_genres.len()),
+            review_rank(c.reviews.as_ref()),
+            c.rank,
+        )
+    });
+    candidates.truncate(request.limit.unwrap_or(DEFAULT_FREE_TO_PLAY_LIMIT));
+    candidates
+}
+
+fn supports_player_count(categories: &[u64], players: usize) -> bool {
+    let has_any = |wanted: &[u64]| categories.iter().any(|c| wanted.contains(c));
+
+    if players <= 1 || has_any(&ONLINE_CATEGORIES) {
+        true
+    } else if has_any(&LOCAL_CATEGORIES) {
+        // Split screen games also carry the generic Multi-player category
+        players <= MAX_LOCAL_PLAYERS
+    } else {
+        has_any(&MULTIPLAYER_CATEGORIES)
+    }
+}
+
+/// Unclassified apps are given the benefit of the doubt
+fn is_game(game: &Game, metadata: Option<&AppMetadata>) -> bool {
+    let app_type = metadata.map_or(game.app_type, |m| m.details.app_type());
+    matches!(app_type, AppType::Game | AppType::Unknown)
+}
+
+/// Every member who declared languages must share at least one with the game. Games we have
+/// no metadata for are kept.
+fn speaks_a_supported_language(customers: &[Customer], metadata: Option<&AppMetadata>) -> bool {
+    let Some(metadata) = metadata else {
+        return true;
+    };
+    let supported = metadata.details.languages();
+    if supported.is_empty() {
+        return true;
+    }
+
+    customers.iter().all(|customer| {
+        let languages = &customer.preferences.languages;
+        languages.is_empty()
+            || languages
+                .iter()
+                .any(|l| supported.iter().any(|s| s.eq_ignore_ascii_case(l)))
+    })
+}
+
+/// When the room restricts content, games we know nothing about are left out as well, as
+/// we can't vouch for them.
+fn within_content_limits(limits: &ContentLimits, metadata: Option<&AppMetadata>) -> bool {
+    if !limits.is_active() {
+        return true;
+    }
+    let Some(metadata) = metadata else {
+        return false;
+    };
+
+    let age_ok = limits
+        .max_required_age
+        .is_none_or(|max| metadata.details.required_age <= max);
+    let descriptors_ok = metadata
+        .details
+        .content_descriptors()
+        .iter()
+        .all(|d| !limits.blocked_descriptors.contains(d));
+
+    age_ok && descriptors_ok
+}
+
+pub fn compatibility_badge(
+    platform: Platform,
+    metadata: Option<&AppMetadata>,
+) -> CompatibilityBadge {
+    let Some(metadata) = metadata else {
+        return CompatibilityBadge::Unknown;
+    };
+    let native = metadata.details.platforms;
+    let native_badge = |supported: bool| {
+        if supported {
+            CompatibilityBadge::Native
+        } else {
+            CompatibilityBadge::Unsupported
+        }
+    };
+
+    // A store page without any platform flag tells us nothing
+    if !native.windows && !native.mac && !native.linux {
+        return CompatibilityBadge::Unknown;
+    }
+
+    match (platform, metadata.deck) {
+        (Platform::Windows, _) => native_badge(native.windows),
+        (Platform::Mac, _) => native_badge(native.mac),
+        (Platform::Linux, _) if native.linux => CompatibilityBadge::Native,
+        // Without a native build, Linux players go through Proton like the Deck does
+        (Platform::Linux, DeckCompatibility::Verified | DeckCompatibility::Playable) => {
+            CompatibilityBadge::Playable
+        }
+        (Platform::Linux, DeckCompatibility::Unsupported) => CompatibilityBadge::Unsupported,
+        (Platform::Linux, DeckCompatibility::Unknown) => CompatibilityBadge::Unknown,
+        (Platform::SteamDeck, DeckCompatibility::Verified) => CompatibilityBadge::Verified,
+        (Platform::SteamDeck, DeckCompatibility::Playable) => CompatibilityBadge::Playable,
+        (Platform::SteamDeck, DeckCompatibility::Unsupported) => CompatibilityBadge::Unsupported,
+        (Platform::SteamDeck, DeckCompatibility::Unknown) if native.linux => {
+            CompatibilityBadge::Native
+        }
+        (Platform::SteamDeck, DeckCompatibility::Unknown) => CompatibilityBadge::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::tests::charted;
+    use crate::steam::steam_app_details::{AppDetails, ContentDescriptors, StoreLabel};
+    use library::{ContentDescriptor, PlatformSupport};
+
+    fn game(
+        app_id: u64,
+        playtime_minutes: u64,
+        installed: bool,
+        size_on_disk: Option<u64>,
+    ) -> Game {
+        Game {
+            id: 0,
+            app_id,
+            name: format!("Game {app_id}"),
+            playtime_minutes,
+            installed,
+            size_on_disk,
+            platform_playtime: Default::default(),
+            app_type: AppType::Unknown,
+            dlc: Vec::new(),
+        }
+    }
+
+    fn customer(steam_name: &str, games: Vec<Game>) -> Customer {
+        Customer {
+            steam_name: steam_name.to_owned(),
+            games,
+            ..Default::default()
+        }
+    }
+
+    fn room(customers: Vec<Customer>) -> Room {
+        Room {
+            customers,
+            ..Room::new(1, ContentLimits::default())
+        }
+    }
+
+    fn app_ids(recommendations: &[Recommendation]) -> Vec<u64> {
+        recommendations.iter().map(|r| r.app_id).collect()
+    }
+
+    #[test]
+    fn ranks_games_the_room_can_start_first() {
+        let room = room(vec![
+            customer(
+                "Alice",
+                vec![
+                    game(1, 10, true, Some(100)),
+                    game(2, 500, true, Some(50)),
+                    game(3, 0, true, None),
+                ],
+            ),
+            customer(
+                "Bob",
+                vec![game(1, 10, true, None), game(2, 500, false, None)],
+            ),
+        ]);
+        let metadata = MetadataCache::default();
+
+        let ranked = recommend(&room, &metadata, &RecommendationRequest::default());
+        assert_eq!(app_ids(&ranked), [1, 2]);
+        assert_eq!(ranked[0].download_bytes, Some(0));
+        assert_eq!(ranked[1].installed_by, ["Alice"]);
+        assert_eq!(ranked[1].missing_install, ["Bob"]);
+        // Bob's size is unknown, Alice's install stands in for it
+        assert_eq!(ranked[1].download_bytes, Some(50));
+        assert_eq!(ranked[1].total_playtime_minutes, 1000);
+
+        let installed = RecommendationRequest {
+            installed_by_everyone: true,
+            ..Default::default()
+        };
+        assert_eq!(app_ids(&recommend(&room, &metadata, &installed)), [1]);
+    }
+
+    #[test]
+    fn smallest_downloads_come_first_and_unknown_sizes_last() {
+        let room = room(vec![
+            customer(
+                "Alice",
+                vec![
+                    game(1, 0, true, Some(900)),
+                    game(2, 0, true, Some(50)),
+                    game(3, 0, false, None),
+                    game(4, 0, false, Some(10)),
+                ],
+            ),
+            customer(
+                "Bob",
+                vec![
+                    game(1, 0, true, Some(900)),
+                    game(2, 0, false, None),
+                    game(3, 0, false, None),
+                    game(4, 0, false, None),
+                ],
+            ),
+        ]);
+        let request = RecommendationRequest {
+            sort: RecommendationSort::SmallestDownload,
+            ..Default::default()
+        };
+
+        let ranked = recommend(&room, &MetadataCache::default(), &request);
+
+        assert_eq!(app_ids(&ranked), [1, 4, 2, 3]);
+        // Both still have to download it
+        assert_eq!(ranked[1].download_bytes, Some(20));
+        assert_eq!(ranked[3].download_bytes, None);
+    }
+
+    fn app(steam_type: &str, platforms: PlatformSupport, deck: DeckCompatibility) -> AppMetadata {
+        AppMetadata {
+            details: AppDetails {
+                steam_type: steam_type.to_owned(),
+                platforms,
+                ..Default::default()
+            },
+            deck,
+        }
+    }
+
+    const WINDOWS_ONLY: PlatformSupport = PlatformSupport {
+        windows: true,
+        mac: false,
+        linux: false,
+    };
+
+    #[test]
+    fn badges_follow_native_builds_then_deck_ratings() {
+        use CompatibilityBadge as Badge;
+        use DeckCompatibility as Deck;
+
+        let badge = |platform, platforms, deck| {
+            compatibility_badge(platform, Some(&app("game", platforms, deck)))
+        };
+        let linux = PlatformSupport {
+            linux: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            badge(Platform::Windows, WINDOWS_ONLY, Deck::Unknown),
+            Badge::Native
+        );
+        assert_eq!(
+            badge(Platform::Mac, WINDOWS_ONLY, Deck::Verified),
+            Badge::Unsupported
+        );
+        assert_eq!(
+            badge(Platform::Linux, linux, Deck::Unsupported),
+            Badge::Native
+        );
+        assert_eq!(
+            badge(Platform::Linux, WINDOWS_ONLY, Deck::Verified),
+            Badge::Playable
+        );
+        assert_eq!(
+            badge(Platform::Linux, WINDOWS_ONLY, Deck::Unsupported),
+            Badge::Unsupported
+        );
+        assert_eq!(
+            badge(Platform::SteamDeck, WINDOWS_ONLY, Deck::Verified),
+            Badge::Verified
+        );
+        assert_eq!(
+            badge(Platform::SteamDeck, WINDOWS_ONLY, Deck::Playable),
+            Badge::Playable
+        );
+        assert_eq!(
+            badge(Platform::SteamDeck, linux, Deck::Unknown),
+            Badge::Native
+        );
+        assert_eq!(
+            badge(Platform::SteamDeck, WINDOWS_ONLY, Deck::Unknown),
+            Badge::Unknown
+        );
+        // A store page without platforms, or no store page at all
+        assert_eq!(
+            badge(
+                Platform::Windows,
+                PlatformSupport::default(),
+                Deck::Verified
+            ),
+            Badge::Unknown
+        );
+        assert_eq!(compatibility_badge(Platform::Mac, None), Badge::Unknown);
+    }
+
+    #[test]
+    fn games_someone_can_not_run_are_left_out() {
+        let mut mac_player = customer("Bob", vec![game(1, 0, true, None), game(2, 0, true, None)]);
+        mac_player.preferences.platform = Platform::Mac;
+        let room = room(vec![
+            customer(
+                "Alice",
+                vec![game(1, 0, true, None), game(2, 0, true, None)],
+            ),
+            mac_player,
+        ]);
+        let mut metadata = MetadataCache::default();
+        metadata.insert(1, app("game", WINDOWS_ONLY, DeckCompatibility::Unknown));
+
+        let recommendations = recommend(&room, &metadata, &RecommendationRequest::default());
+
+        // Nothing is known about 2, so it's kept with an unknown badge
+        assert_eq!(app_ids(&recommendations), [2]);
+        assert_eq!(
+            recommendations[0].compatibility[1].badge,
+            CompatibilityBadge::Unknown
+        );
+        assert_eq!(recommendations[0].compatibility[1].platform, Platform::Mac);
+    }
+
+    fn details(supported_languages: &str, required_age: u32, descriptors: Vec<u64>) -> AppMetadata {
+        AppMetadata {
+            details: AppDetails {
+                steam_type: "game".to_owned(),
+                supported_languages: supported_languages.to_owned(),
+                required_age,
+                content_descriptors: ContentDescriptors { ids: descriptors },
+                ..Default::default()
+            },
+            deck: DeckCompatibility::Unknown,
+        }
+    }
+
+    #[test]
+    fn everyone_needs_a_language_they_speak() {
+        let speaking = |languages: &[&str]| {
+            let mut customer = customer("", Vec::new());
+            customer.preferences.languages = languages.iter().map(|l| l.to_string()).collect();
+            customer
+        };
+        let french = details(
+            "English<strong>*</strong>, French<br>*full audio",
+            0,
+            vec![],
+        );
+
+        assert!(speaks_a_supported_language(
+            &[speaking(&["french"]), speaking(&[])],
+            Some(&french)
+        ));
+        assert!(!speaks_a_supported_language(
+            &[speaking(&["French"]), speaking(&["German"])],
+            Some(&french)
+        ));
+        // No language list on the store page, or no store page at all
+        assert!(speaks_a_supported_language(
+            &[speaking(&["German"])],
+            Some(&details("", 0, vec![]))
+        ));
+        assert!(speaks_a_supported_language(&[speaking(&["German"])], None));
+    }
+
+    #[test]
+    fn content_limits_leave_out_what_they_block_and_unknown_games() {
+        let limits = ContentLimits {
+            max_required_age: Some(12),
+            blocked_descriptors: vec![ContentDescriptor::FrequentViolenceOrGore],
+        };
+
+        assert!(within_content_limits(
+            &limits,
+            Some(&details("", 12, vec![5]))
+        ));
+        assert!(!within_content_limits(
+            &limits,
+            Some(&details("", 18, vec![]))
+        ));
+        assert!(!within_content_limits(
+            &limits,
+            Some(&details("", 0, vec![2]))
+        ));
+        assert!(!within_content_limits(&limits, None));
+        assert!(within_content_limits(&ContentLimits::default(), None));
+
+        let mut room = room(vec![customer(
+            "Alice",
+            vec![game(1, 0, true, None), game(2, 0, true, None)],
+        )]);
+        room.content_limits = limits;
+        let mut metadata = MetadataCache::default();
+        metadata.insert(1, details("", 18, vec![]));
+        metadata.insert(2, details("", 7, vec![]));
+        assert_eq!(
+            app_ids(&recommend(
+                &room,
+                &metadata,
+                &RecommendationRequest::default()
+            )),
+            [2]
+        );
+    }
+
+    #[test]
+    fn lan_games_are_not_online_games() {
+        // Split screen with LAN play, such as many couch co-op games
+        let lan_and_split_screen = [1, 24, 47];
+        assert!(supports_player_count(&lan_and_split_screen, 4));
+        assert!(!supports_player_count(&lan_and_split_screen, 6));
+
+        assert!(supports_player_count(&[1, 48], 6));
+        assert!(supports_player_count(&[24, 38], 6));
+        assert!(!supports_player_count(&[2], 2));
+        assert!(supports_player_count(&[2], 1));
+    }
+
+    #[test]
+    fn non_games_are_only_kept_when_asked_for() {
+        let mut soundtrack = game(2, 0, true, None);
+        soundtrack.app_type = AppType::Soundtrack;
+        let room = room(vec![customer(
+            "Alice",
+            vec![game(1, 0, true, None), soundtrack, game(3, 0, true, None)],
+        )]);
+        let mut metadata = MetadataCache::default();
+        // Store metadata wins over the type the library was saved with
+        metadata.insert(
+            3,
+            app("application", WINDOWS_ONLY, DeckCompatibility::Unknown),
+        );
+
+        let games = recommend(&room, &metadata, &RecommendationRequest::default());
+        let everything = RecommendationRequest {
+            include_non_games: true,
+            ..Default::default()
+        };
+
+        assert_eq!(app_ids(&games), [1]);
+        assert_eq!(
+            app_ids(&recommend(&room, &metadata, &everything)),
+            [1, 2, 3]
+        );
+    }
+
+    fn free_game(name: &str, genres: &[&str], categories: &[u64]) -> AppMetadata {
+        let label = |(id, description): (usize, &&str)| StoreLabel {
+            id: id as u64,
+            description: description.to_string(),
+        };
+        AppMetadata {
+            details: AppDetails {
+                name: name.to_owned(),
+                steam_type: "game".to_owned(),
+                is_free: true,
+                genres: genres.iter().enumerate().map(label).collect(),
+                categories: categories
+                    .iter()
+                    .map(|&id| StoreLabel {
+                        id,
+                        description: String::new(),
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            deck: DeckCompatibility::Unknown,
+        }
+    }
+
+    #[test]
+    fn free_games_match_the_room_then_follow_the_chart() {
+        let mut alice = customer("Alice", vec![game(30, 0, true, None)]);
+        alice.preferences.genres = vec!["Action".to_owned(), "Strategy".to_owned()];
+        let room = room(vec![alice, customer("Bob", vec![game(30, 0, true, None)])]);
+        let catalog = charted(&[10, 20, 30, 40, 50, 60]);
+        let mut metadata = MetadataCache::default();
+        metadata.insert(10, free_game("Shooter", &["Action"], &[36]));
+        metadata.insert(20, free_game("Tactics", &["Action", "Strategy"], &[38]));
+        // Everyone owns it already
+        metadata.insert(30, free_game("Owned", &["Action"], &[36]));
+        // Single player only
+        metadata.insert(40, free_game("Solo", &["Action"], &[2]));
+        metadata.insert(50, free_game("Racing", &["Racing"], &[36]));
+        let mut paid = free_game("Paid", &["Action"], &[36]);
+        paid.details.is_free = false;
+        metadata.insert(60, paid);
+
+        let candidates = free_to_play(&room, &catalog, &metadata, &FreeToPlayRequest::default());
+
+        let names: Vec<&str> = candidates.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Tactics", "Shooter"]);
+        assert_eq!(candidates[0].matching_genres, ["Action", "Strategy"]);
+        assert_eq!(candidates[1].rank, 1);
+
+        let one = FreeToPlayRequest {
+            limit: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(free_to_play(&room, &catalog, &metadata, &one).len(), 1);
+    }
+
+    fn reviewed(metadata: &mut MetadataCache, app_id: u64, review_score: u8) {
+        metadata.insert_reviews(
+            app_id,
+            ReviewSummary {
+                total_reviews: 100,
+                positive_ratio: 0.5,
+                review_score,
+                description: String::new(),
+            },
+        );
+    }
+
+    #[test]
+    fn reviews_break_ties_without_overriding_readiness() {
+        let room = room(vec![customer(
+            "Alice",
+            vec![
+                game(1, 60, true, Some(10)),
+                game(2, 60, true, Some(10)),
+                game(3, 60, false, Some(10)),
+                game(4, 60, true, Some(10)),
+            ],
+        )]);
+        let mut metadata = MetadataCache::default();
+        reviewed(&mut metadata, 1, 5);
+        reviewed(&mut metadata, 2, 9);
+        reviewed(&mut metadata, 3, 9);
+
+        let ready = recommend(&room, &metadata, &RecommendationRequest::default());
+        assert_eq!(app_ids(&ready), [2, 1, 4, 3]);
+        assert_eq!(ready[0].reviews.as_ref().unwrap().review_score, 9);
+        assert_eq!(ready[2].reviews, None);
+
+        let smallest = RecommendationRequest {
+            sort: RecommendationSort::SmallestDownload,
+            ..Default::default()
+        };
+        assert_eq!(
+            app_ids(&recommend(&room, &metadata, &smallest)),
+            [2, 1, 4, 3]
+        );
+    }
+}
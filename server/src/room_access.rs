@@ -0,0 +1,403 @@
+//! Who may join a room and act in it.
+//!
+//! The host (see `Room::role_of`) can lock the room, require a password, kick or ban people,
+//! hand the room over and promote spectators. Members who joined signed in through Steam can
+//! only be acted as by a connection signed in as them; members who only gave a Steam ID can be
+//! acted as by anyone who knows it, as before sign-in existed.
+//!
+//! Spectators, and connections that aren't a member's, only watch: they get the room's live
+//! state but can't nominate, vote or chat. Rooms take up to `rooms.max_spectators` spectators.
+//! Watchers who aren't signed in as one of them only get the room's `public_view`.
+
+use hmac::{Hmac, Mac};
+use library::{ApiError, ApiErrorCode, Customer, Role, Room, ServerMessage};
+use sha2::Sha256;
+
+use crate::is_same_customer;
+
+pub const DEFAULT_MAX_SPECTATORS: usize = 50;
+
+const SALT_LENGTH: usize = 16;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// `<hex salt>$<hex HMAC-SHA256 of the password keyed by the salt>`. Room passwords only keep
+/// strangers with a leaked link out for an evening, so a fast hash is enough.
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0; SALT_LENGTH];
+    getrandom::fill(&mut salt).expect("the OS provides random numbers");
+    let digest = password_mac(&salt, password).finalize().into_bytes();
+    format!("{}${}", hex::encode(salt), hex::encode(digest))
+}
+
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    let Some((salt, digest)) = hash.split_once('$') else {
+        return false;
+    };
+    match (hex::decode(salt), hex::decode(digest)) {
+        (Ok(salt), Ok(digest)) => password_mac(&salt, password).verify_slice(&digest).is_ok(),
+        _ => false,
+    }
+}
+
+fn password_mac(salt: &[u8], password: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(salt).expect("HMAC takes any key size");
+    mac.update(password.as_bytes());
+    mac
+}
+
+/// Whether `steam_id` may join the room, as a spectator or not. Those already in the room
+/// and the host can always come back, such as to reload their library.
+pub fn check_join(
+    room: &Room,
+    steam_id: Option<u64>,
+    signed_in: Option<u64>,
+    spectator: bool,
+    password_ok: bool,
+    max_spectators: usize,
+) -> Result<(), ApiError> {
+    let forbidden = |message: &str| Err(ApiError::new(ApiErrorCode::Forbidden, message));
+
+    if steam_id.is_some_and(|steam_id| room.banned.contains(&steam_id)) {
+        return forbidden("You were banned from this room");
+    }
+    if let Some(member) = room
+        .customers
+        .iter()
+        .find(|c| steam_id.is_some() && c.steam_id == steam_id)
+    {
+        if spectator {
+            return Err(ApiError::new(
+                ApiErrorCode::Conflict,
+                "Members can't also be spectators",
+            ));
+        }
+        if member.verified && member.steam_id != signed_in {
+            return forbidden("This member signed in through Steam, sign in as them to rejoin");
+        }
+        return Ok(());
+    }
+    match steam_id.and_then(|steam_id| room.role_of(steam_id)) {
+        Some(Role::Spectator) if !spectator => {
+            return forbidden("Spectators become members when the host promotes them");
+        }
+        Some(_) => return Ok(()),
+        None => {}
+    }
+    if room.locked {
+        return forbidden("The room is locked");
+    }
+    if room.password_protected && !password_ok {
+        return forbidden("Wrong room password");
+    }
+    if spectator && room.spectators.len() >= max_spectators {
+        return Err(ApiError::new(
+            ApiErrorCode::Conflict,
+            format!("Room {} has as many spectators as it takes", room.id),
+        )
+        .with_detail("max_spectators", max_spectators));
+    }
+    if !spectator && !room.phase.accepts_members() {
+        return Err(ApiError::new(
+            ApiErrorCode::Conflict,
+            format!("Room {} is past the point of joining", room.id),
+        )
+        .with_detail("phase", room.phase.label()));
+    }
+    Ok(())
+}
+
+/// The member a connection acts as: `member` is the Steam ID (or name) it gave
+pub fn acting_member<'a>(
+    room: &'a Room,
+    member: Option<&str>,
+    signed_in: Option<u64>,
+) -> Result<&'a Customer, String> {
+    let customer = member
+        .and_then(|member| {
+            room.customers
+                .iter()
+                .find(|c| is_same_customer(c, member.parse().ok(), member))
+        })
+        .ok_or("Only room members can do that, join the room first")?;
+    if !may_act_as(customer, signed_in) {
+        return Err(format!(
+            "{} signed in through Steam, sign in as them to act as them",
+            customer.steam_name
+        ));
+    }
+    Ok(customer)
+}
+
+/// Members who signed in through Steam can only be acted as when signed in as them
+pub fn may_act_as(customer: &Customer, signed_in: Option<u64>) -> bool {
+    !customer.verified || customer.steam_id == signed_in
+}
+
+/// Whether a request sees the whole room: the host, members and spectators signed in as
+/// themselves do. Others get `public_view`.
+pub fn sees_whole_room(room: &Room, signed_in: Option<u64>) -> bool {
+    signed_in.is_some_and(|steam_id| room.role_of(steam_id).is_some())
+}
+
+/// What anyone with the room's link or code may see before joining: how to join and who is
+/// there, without libraries, Steam IDs, votes or chat
+pub fn public_view(room: &Room) -> Room {
+    let names_only = |customers: &[Customer]| {
+        customers
+            .iter()
+            .map(|c| Customer {
+                steam_name: c.steam_name.clone(),
+                verified: c.verified,
+                ..Default::default()
+            })
+            .collect()
+    };
+    Room {
+        customers: names_only(&room.customers),
+        code: room.code.clone(),
+        phase: room.phase,
+        phase_deadline: room.phase_deadline,
+        phase_timers: room.phase_timers,
+        created_at: room.created_at,
+        last_active_at: room.last_active_at,
+        spectators: names_only(&room.spectators),
+        locked: room.locked,
+        password_protected: room.password_protected,
+        ..Room::new(room.id, room.content_limits.clone())
+    }
+}
+
+/// How an event reaches connections that only get `public_view`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublicEvent {
+    /// It carries nothing more than the public view
+    AsIs,
+    /// It changes who is in the room, so a fresh public view is sent instead
+    Snapshot,
+    Hidden,
+}
+
+pub fn public_event(message: &ServerMessage) -> PublicEvent {
+    match message {
+        ServerMessage::PhaseChanged { .. }
+        | ServerMessage::AccessChanged { .. }
+        | ServerMessage::RoomExpiring { .. }
+        | ServerMessage::RoomClosed => PublicEvent::AsIs,
+        ServerMessage::MemberJoined { .. }
+        | ServerMessage::MemberLeft { .. }
+        | ServerMessage::SpectatorJoined { .. }
+        | ServerMessage::Kicked { .. }
+        | ServerMessage::Promoted { .. } => PublicEvent::Snapshot,
+        ServerMessage::Snapshot { .. }
+        | ServerMessage::LibraryLoaded { .. }
+        | ServerMessage::LibraryClassified { .. }
+        | ServerMessage::Nominated { .. }
+        | ServerMessage::Voted { .. }
+        | ServerMessage::Chat { .. }
+        | ServerMessage::Decided { .. }
+        | ServerMessage::HostChanged { .. }
+        | ServerMessage::Error { .. } => PublicEvent::Hidden,
+    }
+}
+
+/// Members signed in as themselves, and the host
+pub fn require_member(room: &Room, signed_in: Option<u64>) -> Result<(), ApiError> {
+    let is_member = signed_in.is_some_and(|steam_id| room.role_of(steam_id) == Some(Role::Member));
+    if is_member || room.is_host(signed_in) {
+        Ok(())
+    } else {
+        Err(ApiError::new(
+            ApiErrorCode::Forbidden,
+            "Only the room's members can do that, sign in as one",
+        ))
+    }
+}
+
+/// Whether a connection only watches the room: it isn't a member's, nor the signed-in host's
+pub fn only_watches(room: &Room, member: Option<&str>, signed_in: Option<u64>) -> bool {
+    let is_member = member.is_some_and(|member| {
+        room.customers
+            .iter()
+            .any(|c| is_same_customer(c, member.parse().ok(), member))
+    });
+    !is_member && !room.is_host(signed_in)
+}
+
+pub fn require_host(room: &Room, signed_in: Option<u64>) -> Result<(), ApiError> {
+    if room.is_host(signed_in) {
+        Ok(())
+    } else {
+        Err(ApiError::new(
+            ApiErrorCode::Forbidden,
+            "Only the room's host can do that",
+        ))
+    }
+}
+
+/// Whether a request may take this member out of the room: the host can, members can leave,
+/// and members who never proved who they are can be taken out by anyone
+pub fn may_remove(room: &Room, member: &Customer, signed_in: Option<u64>) -> bool {
+    room.is_host(signed_in) || may_act_as(member, signed_in)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use library::{ContentLimits, RoomPhase};
+
+    #[test]
+    fn passwords_are_salted_and_checked() {
+        let hash = hash_password("hunter2");
+        assert_ne!(hash, hash_password("hunter2"));
+        assert!(verify_password(&hash, "hunter2"));
+        assert!(!verify_password(&hash, "hunter3"));
+        assert!(!verify_password("not a hash", "hunter2"));
+    }
+
+    #[test]
+    fn locked_rooms_only_let_their_own_people_back_in() {
+        let mut room = Room {
+            host: Some(1),
+            locked: true,
+            banned: vec![3],
+            ..Room::new(1, ContentLimits::default())
+        };
+        room.customers.push(Customer {
+            steam_id: Some(2),
+            verified: true,
+            ..Default::default()
+        });
+
+        assert!(check_join(&room, Some(1), None, false, false, 1).is_ok());
+        assert!(check_join(&room, Some(2), Some(2), false, false, 1).is_ok());
+        // Nobody else can rejoin as a member who signed in
+        assert!(check_join(&room, Some(2), None, false, false, 1).is_err());
+        assert_eq!(
+            check_join(&room, Some(3), Some(3), false, true, 1)
+                .unwrap_err()
+                .code,
+            ApiErrorCode::Forbidden
+        );
+        assert!(check_join(&room, Some(4), None, false, true, 1).is_err());
+
+        room.locked = false;
+        room.password_protected = true;
+        assert!(check_join(&room, Some(4), None, false, false, 1).is_err());
+        assert!(check_join(&room, Some(4), None, false, true, 1).is_ok());
+
+        room.phase = RoomPhase::Voting;
+        assert!(check_join(&room, Some(4), None, false, true, 1).is_err());
+        assert!(check_join(&room, Some(4), None, true, true, 1).is_ok());
+
+        // Spectators past the limit are turned away, those already watching can come back
+        room.spectators.push(Customer {
+            steam_id: Some(5),
+            ..Default::default()
+        });
+        assert!(check_join(&room, Some(4), None, true, true, 1).is_err());
+        assert!(check_join(&room, Some(5), None, true, true, 1).is_ok());
+    }
+
+    #[test]
+    fn verified_members_are_only_acted_as_when_signed_in() {
+        let mut room = Room::new(1, ContentLimits::default());
+        room.customers.push(Customer {
+            steam_name: "Alice".to_owned(),
+            steam_id: Some(2),
+            verified: true,
+            ..Default::default()
+        });
+
+        assert!(acting_member(&room, Some("2"), None).is_err());
+        assert!(acting_member(&room, Some("2"), Some(5)).is_err());
+        assert!(acting_member(&room, Some("2"), Some(2)).is_ok());
+        assert!(acting_member(&room, Some("6"), Some(6)).is_err());
+
+        room.host = Some(7);
+        assert!(!only_watches(&room, Some("2"), None));
+        assert!(!only_watches(&room, None, Some(7)));
+        assert!(only_watches(&room, Some("6"), Some(6)));
+        assert!(only_watches(&room, None, None));
+    }
+
+    #[test]
+    fn outsiders_only_see_the_public_view() {
+        let mut room = Room {
+            host: Some(1),
+            code: "ABC234".to_owned(),
+            password_protected: true,
+            ..Room::new(1, ContentLimits::default())
+        };
+        room.customers.push(Customer {
+            steam_name: "Alice".to_owned(),
+            steam_id: Some(2),
+            verified: true,
+            ..Default::default()
+        });
+        room.chat.push(library::ChatMessage {
+            from: "Alice".to_owned(),
+            text: "The password is hunter2".to_owned(),
+            sent_at: 0,
+        });
+
+        assert!(sees_whole_room(&room, Some(1)));
+        assert!(sees_whole_room(&room, Some(2)));
+        assert!(!sees_whole_room(&room, Some(3)));
+        assert!(!sees_whole_room(&room, None));
+
+        let public = public_view(&room);
+        assert_eq!(public.code, "ABC234");
+        assert!(public.password_protected);
+        assert_eq!(public.customers[0].steam_name, "Alice");
+        assert_eq!(public.customers[0].steam_id, None);
+        assert!(public.chat.is_empty());
+        assert_eq!(public.host, None);
+
+        // Watchers are only sent what the public view shows
+        assert_eq!(
+            public_event(&ServerMessage::Chat {
+                message: room.chat[0].clone()
+            }),
+            PublicEvent::Hidden
+        );
+        assert_eq!(
+            public_event(&ServerMessage::HostChanged { host: 2 }),
+            PublicEvent::Hidden
+        );
+        assert_eq!(
+            public_event(&ServerMessage::MemberJoined {
+                member: room.customers[0].clone()
+            }),
+            PublicEvent::Snapshot
+        );
+        assert_eq!(
+            public_event(&ServerMessage::AccessChanged {
+                locked: true,
+                password_protected: true
+            }),
+            PublicEvent::AsIs
+        );
+    }
+
+    #[test]
+    fn only_signed_in_members_and_the_host_act_for_the_room() {
+        let mut room = Room {
+            host: Some(1),
+            ..Room::new(1, ContentLimits::default())
+        };
+        room.customers.push(Customer {
+            steam_id: Some(2),
+            ..Default::default()
+        });
+        room.spectators.push(Customer {
+            steam_id: Some(3),
+            ..Default::default()
+        });
+
+        assert!(require_member(&room, Some(1)).is_ok());
+        assert!(require_member(&room, Some(2)).is_ok());
+        assert!(require_member(&room, Some(3)).is_err());
+        assert!(require_member(&room, None).is_err());
+    }
+}
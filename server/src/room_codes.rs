@@ -0,0 +1,54 @@
+//! Short room codes, easier to read out on voice chat than room IDs.
+//!
+//! Codes are drawn at random from `ROOM_CODE_ALPHABET`, redrawn while they clash with an open
+//! room. They always hold a letter so they can't be taken for a room ID.
+
+use library::{ROOM_CODE_ALPHABET, ROOM_CODE_LENGTH, normalize_room_code};
+
+/// A code for which `is_taken` is false
+pub fn generate(is_taken: impl Fn(&str) -> bool) -> String {
+    loop {
+        let code = random_code();
+        // All digit draws fail normalizing, they would read as a room ID
+        if normalize_room_code(&code).is_some() && !is_taken(&code) {
+            return code;
+        }
+    }
+}
+
+fn random_code() -> String {
+    let alphabet = ROOM_CODE_ALPHABET.as_bytes();
+    // Bytes past the last whole multiple of the alphabet are redrawn so every character is as
+    // likely
+    let unbiased_limit = (256 / alphabet.len() * alphabet.len()) as u8;
+
+    let mut code = String::with_capacity(ROOM_CODE_LENGTH);
+    while code.len() < ROOM_CODE_LENGTH {
+        let mut byte = [0];
+        getrandom::fill(&mut byte).expect("the OS provides random numbers");
+        if byte[0] < unbiased_limit {
+            code.push(alphabet[byte[0] as usize % alphabet.len()] as char);
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use library::RoomRef;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unambiguous_and_never_reused() {
+        let taken = RefCell::new(HashSet::new());
+        for _ in 0..500 {
+            let code = generate(|code| taken.borrow().contains(code));
+            assert_eq!(code.len(), ROOM_CODE_LENGTH);
+            assert!(!code.contains(['0', 'O', '1', 'I', 'l']));
+            assert_eq!(RoomRef::parse(&code), Some(RoomRef::Code(code.clone())));
+            assert!(taken.borrow_mut().insert(code));
+        }
+    }
+}
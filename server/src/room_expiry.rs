@@ -0,0 +1,151 @@
+//! Closes rooms nobody uses any more, so they don't pile up in memory.
+//!
+//! A room expires once it has been idle for `rooms.idle_timeout_secs` or open for
+//! `rooms.max_lifetime_secs`, whichever comes first. Its members are sent a
+//! `ServerMessage::RoomExpiring` `rooms.expiry_warning_secs` beforehand, so anything happening
+//! in the room in the meantime keeps it open. Expired rooms are archived to storage and
+//! dropped from `AppModel.rooms`, along with the copies of their members' libraries. Customers
+//! left in no open room are dropped from `AppModel.customers` too, see `AppModel::close_room`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use library::{Room, ServerMessage};
+
+use crate::config::RoomsConfig;
+use crate::storage::CloseReason;
+use crate::{AppState, room_sync, unix_now};
+
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(4 * 60 * 60);
+pub const DEFAULT_MAX_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+pub const DEFAULT_EXPIRY_WARNING: Duration = Duration::from_secs(5 * 60);
+
+/// How often rooms are checked
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// When the room expires, and why
+fn expiry(room: &Room, config: &RoomsConfig) -> (u64, CloseReason) {
+    let idle_at = room.last_active_at + config.idle_timeout_secs;
+    let max_lifetime_at = room.created_at + config.max_lifetime_secs;
+    if idle_at < max_lifetime_at {
+        (idle_at, CloseReason::Idle)
+    } else {
+        (max_lifetime_at, CloseReason::MaxLifetime)
+    }
+}
+
+/// What a sweep does to the rooms
+#[derive(Debug, Default, PartialEq)]
+struct Sweep {
+    /// Rooms to warn, with when they expire
+    warn: Vec<(u64, u64)>,
+    close: Vec<(u64, CloseReason)>,
+}
+
+/// `warned` holds the expiry each room was last warned about, so members are warned once
+/// per expiry rather than on every sweep
+fn sweep<'a>(
+    rooms: impl Iterator<Item = &'a Room>,
+    config: &RoomsConfig,
+    now: u64,
+    warned: &HashMap<u64, u64>,
+) -> Sweep {
+    let mut sweep = Sweep::default();
+    for room in rooms {
+        let (expires_at, reason) = expiry(room, config);
+        if expires_at <= now {
+            sweep.close.push((room.id, reason));
+        } else if expires_at <= now + config.expiry_warning_secs
+            && warned.get(&room.id) != Some(&expires_at)
+        {
+            sweep.warn.push((room.id, expires_at));
+        }
+    }
+    sweep
+}
+
+/// Expires rooms for as long as the server runs
+pub fn spawn_room_expiry(state: AppState, config: RoomsConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        let mut warned = HashMap::new();
+        loop {
+            interval.tick().await;
+            let now = unix_now();
+            let sweep = sweep(
+                state.app_model.read().await.rooms.values(),
+                &config,
+                now,
+                &warned,
+            );
+
+            for (room_id, expires_at) in sweep.warn {
+                log::info!("Room {room_id} expires in {}s", expires_at - now);
+                state
+                    .room_events
+                    .publish(room_id, ServerMessage::RoomExpiring { expires_at });
+                warned.insert(room_id, expires_at);
+            }
+
+            if !sweep.close.is_empty() {
+                let mut app_model = state.app_model.write().await;
+                for (room_id, reason) in sweep.close {
+                    // Something may have happened in the room since the sweep
+                    let still_expired = app_model
+                        .rooms
+                        .get(&room_id)
+                        .is_some_and(|room| expiry(room, &config).0 <= unix_now());
+                    if still_expired
+                        && room_sync::close_room(
+                            &mut app_model,
+                            &state.room_events,
+                            room_id,
+                            reason,
+                        )
+                    {
+                        log::info!("Room {room_id} expired ({reason:?})");
+                    }
+                }
+                warned.retain(|room_id, _| app_model.rooms.contains_key(room_id));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use library::ContentLimits;
+
+    fn room(id: u64, created_at: u64, last_active_at: u64) -> Room {
+        Room {
+            created_at,
+            last_active_at,
+            ..Room::new(id, ContentLimits::default())
+        }
+    }
+
+    #[test]
+    fn rooms_are_warned_once_then_closed() {
+        let config = RoomsConfig {
+            idle_timeout_secs: 100,
+            max_lifetime_secs: 1000,
+            expiry_warning_secs: 10,
+            ..Default::default()
+        };
+        let rooms = [room(1, 0, 0), room(2, 0, 5), room(3, 0, 950)];
+        let mut warned = HashMap::new();
+
+        let first = sweep(rooms.iter(), &config, 100, &warned);
+        assert_eq!(first.close, vec![(1, CloseReason::Idle)]);
+        assert_eq!(first.warn, vec![(2, 105)]);
+
+        warned.insert(2, 105);
+        let second = sweep(rooms.iter(), &config, 101, &warned);
+        assert!(second.warn.is_empty());
+
+        // Busy rooms still close at their maximum lifetime
+        let late = sweep(rooms[2..].iter(), &config, 1000, &warned);
+        assert_eq!(late.close, vec![(3, CloseReason::MaxLifetime)]);
+    }
+}
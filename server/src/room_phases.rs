@@ -0,0 +1,206 @@
+//! The room lifecycle: which phase changes are allowed, and timers that end phases.
+//!
+//! Every change is published as a `ServerMessage::PhaseChanged`, so clients lay out the room
+//! from its phase alone. Members can only move a room on to its next phase through its timer
+//! or the host; the host can also skip phases or go back, see `transition`.
+
+use std::time::Duration;
+
+use library::{Nomination, Room, RoomPhase, ServerMessage};
+
+use crate::{AppState, room_sync, unix_now};
+
+/// How often phase deadlines are checked
+const TIMER_TICK: Duration = Duration::from_secs(1);
+
+/// The event moving `room` to `to`, if the rules allow it
+pub fn transition(
+    room: &Room,
+    to: RoomPhase,
+    by_host: bool,
+    now: u64,
+) -> Result<ServerMessage, String> {
+    if room.phase == to {
+        return Err(format!("The room is already in the {} phase", to.label()));
+    }
+    if room.phase == RoomPhase::Closed {
+        return Err("The room is closed".to_owned());
+    }
+    if !room.phase.can_move_to(to) && !by_host {
+        return Err(format!(
+            "Only the host can take the room from {} to {}",
+            room.phase.label(),
+            to.label()
+        ));
+    }
+    if to == RoomPhase::Voting && room.nominations.is_empty() {
+        return Err("Nominate a game before voting".to_owned());
+    }
+    if to == RoomPhase::Decided && room.decision.is_none() {
+        return Err("Decide on a game to end voting".to_owned());
+    }
+    Ok(entering(room, to, now))
+}
+
+/// The events settling the room on a nominated game and ending the vote
+pub fn decide(room: &Room, app_id: u64, now: u64) -> Result<Vec<ServerMessage>, String> {
+    if room.phase != RoomPhase::Voting {
+        return Err("Games are decided on once voting started".to_owned());
+    }
+    if !room.nominations.iter().any(|n| n.app_id == app_id) {
+        return Err("Only nominated games can be decided on".to_owned());
+    }
+    Ok(vec![
+        ServerMessage::Decided { app_id },
+        entering(room, RoomPhase::Decided, now),
+    ])
+}
+
+fn entering(room: &Room, phase: RoomPhase, now: u64) -> ServerMessage {
+    ServerMessage::PhaseChanged {
+        phase,
+        deadline: room
+            .phase_timers
+            .duration_secs(phase)
+            .map(|secs| now + secs),
+    }
+}
+
+/// What happens when the room's phase timer runs out. A phase that can't end yet, such as
+/// nominating without nominations, loses its timer and waits for the host.
+pub fn on_deadline(room: &Room, now: u64) -> Vec<ServerMessage> {
+    let waiting_for_host = ServerMessage::PhaseChanged {
+        phase: room.phase,
+        deadline: None,
+    };
+    match room.phase {
+        RoomPhase::Voting => match most_voted(&room.nominations) {
+            Some(app_id) => decide(room, app_id, now).unwrap_or_else(|_| vec![waiting_for_host]),
+            None => vec![waiting_for_host],
+        },
+        phase => phase
+            .next()
+            .filter(|next| *next != RoomPhase::Closed)
+            .and_then(|next| transition(room, next, false, now).ok())
+            .map_or_else(|| vec![waiting_for_host], |event| vec![event]),
+    }
+}
+
+/// Ties go to the game nominated first
+fn most_voted(nominations: &[Nomination]) -> Option<u64> {
+    nominations
+        .iter()
+        .rev()
+        .max_by_key(|n| n.votes.len())
+        .map(|n| n.app_id)
+}
+
+/// Ends phases as their timers run out, for as long as the server runs
+pub fn spawn_phase_timers(state: AppState) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TIMER_TICK);
+        loop {
+            interval.tick().await;
+            let now = unix_now();
+            let is_due = |room: &Room| room.phase_deadline.is_some_and(|deadline| deadline <= now);
+
+            if !state.app_model.read().await.rooms.values().any(is_due) {
+                continue;
+            }
+            let mut app_model = state.app_model.write().await;
+            let due: Vec<(u64, Vec<ServerMessage>)> = app_model
+                .rooms
+                .values()
+                .filter(|room| is_due(room))
+                .map(|room| (room.id, on_deadline(room, now)))
+                .collect();
+            for (room_id, events) in due {
+                log::info!("Room {room_id} phase timer ran out");
+                for event in events {
+                    room_sync::apply_and_publish(
+                        &mut app_model,
+                        &state.room_events,
+                        room_id,
+                        event,
+                    );
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use library::{ContentLimits, MemberRef, PhaseTimers};
+
+    fn room_in(phase: RoomPhase) -> Room {
+        Room {
+            phase,
+            phase_timers: PhaseTimers {
+                voting_secs: Some(60),
+                ..Default::default()
+            },
+            ..Room::new(1, ContentLimits::default())
+        }
+    }
+
+    fn nomination(app_id: u64, votes: &[&str]) -> Nomination {
+        let member = |steam_name: &str| MemberRef {
+            steam_id: None,
+            steam_name: steam_name.to_owned(),
+        };
+        Nomination {
+            app_id,
+            name: format!("Game {app_id}"),
+            nominated_by: member("Alice"),
+            votes: votes.iter().map(|&v| member(v)).collect(),
+        }
+    }
+
+    #[test]
+    fn only_the_host_skips_or_goes_back() {
+        let lobby = room_in(RoomPhase::Lobby);
+        assert!(transition(&lobby, RoomPhase::LoadingLibraries, false, 0).is_ok());
+        assert!(transition(&lobby, RoomPhase::Nominating, false, 0).is_err());
+        assert!(transition(&lobby, RoomPhase::Nominating, true, 0).is_ok());
+        assert!(transition(&lobby, RoomPhase::Closed, false, 0).is_ok());
+
+        let mut nominating = room_in(RoomPhase::Nominating);
+        assert!(transition(&nominating, RoomPhase::Voting, true, 0).is_err());
+        nominating.nominations.push(nomination(10, &[]));
+        assert!(matches!(
+            transition(&nominating, RoomPhase::Voting, false, 100),
+            Ok(ServerMessage::PhaseChanged {
+                phase: RoomPhase::Voting,
+                deadline: Some(160)
+            })
+        ));
+
+        let closed = room_in(RoomPhase::Closed);
+        assert!(transition(&closed, RoomPhase::Lobby, true, 0).is_err());
+    }
+
+    #[test]
+    fn voting_timer_decides_on_the_most_voted_game() {
+        let mut room = room_in(RoomPhase::Voting);
+        room.nominations = vec![
+            nomination(10, &["Alice"]),
+            nomination(20, &["Bob", "Carol"]),
+            nomination(30, &[]),
+        ];
+        for event in on_deadline(&room, 0) {
+            room.apply(&event);
+        }
+        assert_eq!(room.decision, Some(20));
+        assert_eq!(room.phase, RoomPhase::Decided);
+
+        let mut nominating = room_in(RoomPhase::Nominating);
+        nominating.phase_deadline = Some(0);
+        for event in on_deadline(&nominating, 0) {
+            nominating.apply(&event);
+        }
+        assert_eq!(nominating.phase, RoomPhase::Nominating);
+        assert_eq!(nominating.phase_deadline, None);
+    }
+}
@@ -0,0 +1,699 @@
+//! Real-time room synchronization over WebSocket.
+//!
+//! Each room has a broadcast channel of `ServerMessage`s. Every change to a room, whether it
+//! comes from a WebSocket message or a REST endpoint, is applied to the room with
+//! `Room::apply` and then published, so connected clients applying the same events stay in
+//! sync. A client gets a full snapshot when it (re)connects or falls behind.
+//!
+//! Connections that only watch (see `room_access::only_watches`) and aren't signed in as one
+//! of the room's spectators get its `room_access::public_view`, and only the events that
+//! change it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use axum::{
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
+};
+use futures_util::{SinkExt, StreamExt};
+use library::{
+    ChatMessage, ClientMessage, Customer, MemberRef, Nomination, Role, Room, RoomPhase,
+    ServerMessage,
+};
+use serde::Deserialize;
+use tokio::sync::broadcast;
+
+use crate::api_error::{ApiPath, ErrorResponse};
+use crate::auth::Session;
+use crate::room_access::PublicEvent;
+use crate::steam::steam_local_library::placeholder_name;
+use crate::storage::CloseReason;
+use crate::{AppModel, AppState, is_same_customer, room_not_found, unix_now};
+use crate::{room_access, room_phases};
+
+/// Events a slow client can fall behind by before it's sent a fresh snapshot instead
+const ROOM_CHANNEL_CAPACITY: usize = 64;
+
+const MAX_CHAT_LENGTH: usize = 500;
+
+#[derive(Debug, Default)]
+pub struct RoomEvents {
+    channels: Mutex<HashMap<u64, broadcast::Sender<ServerMessage>>>,
+}
+
+impl RoomEvents {
+    pub fn subscribe(&self, room_id: u64) -> broadcast::Receiver<ServerMessage> {
+        self.channels
+            .lock()
+            .unwrap()
+            .entry(room_id)
+            .or_insert_with(|| broadcast::channel(ROOM_CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Sends an event to everyone connected to the room, if anyone is
+    pub fn publish(&self, room_id: u64, message: ServerMessage) {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(sender) = channels.get(&room_id)
+            && sender.send(message).is_err()
+        {
+            // Everybody left, the channel is recreated on the next connection
+            channels.remove(&room_id);
+        }
+    }
+
+    /// Tells connected clients the room is gone and drops its channel
+    pub fn close(&self, room_id: u64) {
+        if let Some(sender) = self.channels.lock().unwrap().remove(&room_id) {
+            let _ = sender.send(ServerMessage::RoomClosed);
+        }
+    }
+}
+
+/// Applies an event to the room and publishes it. Returns false if the room doesn't exist.
+pub fn apply_and_publish(
+    app_model: &mut AppModel,
+    room_events: &RoomEvents,
+    room_id: u64,
+    message: ServerMessage,
+) -> bool {
+    if !app_model.apply_room_event(room_id, &message) {
+        return false;
+    }
+    room_events.publish(room_id, message);
+    true
+}
+
+/// Closes the room and tells connected clients. Returns false if the room doesn't exist.
+pub fn close_room(
+    app_model: &mut AppModel,
+    room_events: &RoomEvents,
+    room_id: u64,
+    reason: CloseReason,
+) -> bool {
+    if app_model.close_room(room_id, reason).is_none() {
+        return false;
+    }
+    room_events.publish(
+        room_id,
+        ServerMessage::PhaseChanged {
+            phase: RoomPhase::Closed,
+            deadline: None,
+        },
+    );
+    room_events.close(room_id);
+    true
+}
+
+/// Tells every room the customer is in that their library changed
+pub fn publish_library_loaded(
+    app_model: &mut AppModel,
+    room_events: &RoomEvents,
+    steam_id: Option<u64>,
+    steam_name: &str,
+) {
+    let Some(customer) = app_model
+        .customers
+        .iter()
+        .find(|c| is_same_customer(c, steam_id, steam_name))
+        .cloned()
+    else {
+        return;
+    };
+    publish_to_member_rooms(app_model, room_events, steam_id, steam_name, |member| {
+        ServerMessage::LibraryLoaded {
+            // What the room knows of them, such as being verified, stays
+            member: Customer {
+                games: customer.games.clone(),
+                fetched_at: customer.fetched_at,
+                stale: None,
+                ..member.clone()
+            },
+        }
+    });
+}
+
+/// Sends the member's library, as classified so far, to every room they are in
+pub fn publish_library_classified(
+    app_model: &mut AppModel,
+    room_events: &RoomEvents,
+    steam_id: Option<u64>,
+    steam_name: &str,
+) {
+    publish_to_member_rooms(app_model, room_events, steam_id, steam_name, |member| {
+        ServerMessage::LibraryClassified {
+            steam_id: member.steam_id,
+            steam_name: member.steam_name.clone(),
+            games: member.games.clone(),
+        }
+    });
+}
+
+fn publish_to_member_rooms(
+    app_model: &mut AppModel,
+    room_events: &RoomEvents,
+    steam_id: Option<u64>,
+    steam_name: &str,
+    event: impl Fn(&Customer) -> ServerMessage,
+) {
+    let events: Vec<(u64, ServerMessage)> = app_model
+        .rooms
+        .values()
+        .filter_map(|room| {
+            let member = room
+                .customers
+                .iter()
+                .find(|c| is_same_customer(c, steam_id, steam_name))?;
+            Some((room.id, event(member)))
+        })
+        .collect();
+    for (room_id, event) in events {
+        apply_and_publish(app_model, room_events, room_id, event);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoomSocketQuery {
+    /// SteamID64 (or name, for members who uploaded local files) of the member connecting.
+    /// Without it the connection can only watch, unless it's signed in as a member.
+    #[serde(default)]
+    member: Option<String>,
+    /// Needed to watch a password protected room without having joined it
+    #[serde(default)]
+    password: Option<String>,
+}
+
+pub async fn room_socket(
+    State(state): State<AppState>,
+    ApiPath(room_id): ApiPath<u64>,
+    Query(query): Query<RoomSocketQuery>,
+    session: Option<Session>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ErrorResponse> {
+    let signed_in = session.map(|session| session.steam_id);
+    let (member, view) = {
+        let app_model = state.app_model.read().await;
+        let room = app_model
+            .rooms
+            .get(&room_id)
+            .ok_or_else(|| room_not_found(room_id))?;
+        let password_ok = app_model.room_password_ok(room_id, query.password.as_deref());
+        connection_access(room, query.member, signed_in, password_ok)?
+    };
+
+    Ok(ws.on_upgrade(move |socket| {
+        handle_room_socket(socket, state, room_id, member, signed_in, view)
+    }))
+}
+
+/// What a connection is sent of the room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoomView {
+    Whole,
+    /// `room_access::public_view`, see `room_access::public_event`
+    Public,
+}
+
+/// The member a connection acts as, if `acting_member` accepts it, and what it sees of the
+/// room: the whole room for members, the host and spectators signed in as themselves, as
+/// through the REST endpoints. Other connections only watch, and are let in like spectators
+/// joining the room: not when banned, locked out or without the password.
+fn connection_access(
+    room: &Room,
+    member: Option<String>,
+    signed_in: Option<u64>,
+    password_ok: bool,
+) -> Result<(Option<String>, RoomView), ErrorResponse> {
+    // Members signed in as themselves needn't say who they are
+    let member = member
+        .or_else(|| signed_in.map(|steam_id| steam_id.to_string()))
+        .filter(|member| room_access::acting_member(room, Some(member), signed_in).is_ok());
+    if !room_access::only_watches(room, member.as_deref(), signed_in) {
+        return Ok((member, RoomView::Whole));
+    }
+    room_access::check_join(room, signed_in, signed_in, true, password_ok, usize::MAX)?;
+    let view = if room_access::sees_whole_room(room, signed_in) {
+        RoomView::Whole
+    } else {
+        RoomView::Public
+    };
+    Ok((member, view))
+}
+
+async fn handle_room_socket(
+    socket: WebSocket,
+    state: AppState,
+    room_id: u64,
+    member: Option<String>,
+    signed_in: Option<u64>,
+    view: RoomView,
+) {
+    // Subscribe before taking the snapshot so no event falls in between
+    let mut events = state.room_events.subscribe(room_id);
+    let (mut sender, mut receiver) = socket.split();
+
+    if !send_snapshot(&state, room_id, view, &mut sender).await {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let sent = match event {
+                    Ok(ServerMessage::RoomClosed) => {
+                        let _ = send(&mut sender, &ServerMessage::RoomClosed).await;
+                        break;
+                    }
+                    Ok(message) if kicks_connection(&message, member.as_deref(), signed_in) => {
+                        let _ = send(&mut sender, &message).await;
+                        let _ = sender.send(Message::Close(None)).await;
+                        break;
+                    }
+                    Ok(message) if view == RoomView::Public => {
+                        match room_access::public_event(&message) {
+                            PublicEvent::AsIs => send(&mut sender, &message).await,
+                            PublicEvent::Snapshot => {
+                                send_snapshot(&state, room_id, view, &mut sender).await
+                            }
+                            PublicEvent::Hidden => true,
+                        }
+                    }
+                    Ok(message) => send(&mut sender, &message).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::debug!("Room {room_id} client fell {skipped} events behind");
+                        send_snapshot(&state, room_id, view, &mut sender).await
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if !sent {
+                    break;
+                }
+            }
+            incoming = receiver.next() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let result = match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(message) => handle_client_message(&state, room_id, member.as_deref(), signed_in, message).await,
+                    Err(error) => Err(format!("Unknown message: {error}")),
+                };
+                if let Err(message) = result
+                    && !send(&mut sender, &ServerMessage::Error { message }).await
+                {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the event takes the connection's own member or spectator out of the room
+fn kicks_connection(message: &ServerMessage, member: Option<&str>, signed_in: Option<u64>) -> bool {
+    let ServerMessage::Kicked {
+        steam_id,
+        steam_name,
+        ..
+    } = message
+    else {
+        return false;
+    };
+    match steam_id {
+        Some(steam_id) => {
+            signed_in == Some(*steam_id)
+                || member.and_then(|member| member.parse().ok()) == Some(*steam_id)
+        }
+        None => member == Some(steam_name.as_str()),
+    }
+}
+
+type SocketSender = futures_util::stream::SplitSink<WebSocket, Message>;
+
+/// Returns false once the client is gone
+async fn send(sender: &mut SocketSender, message: &ServerMessage) -> bool {
+    match serde_json::to_string(message) {
+        Ok(json) => sender.send(Message::Text(json.into())).await.is_ok(),
+        Err(error) => {
+            log::error!("Can't serialize room message: {error}");
+            true
+        }
+    }
+}
+
+async fn send_snapshot(
+    state: &AppState,
+    room_id: u64,
+    view: RoomView,
+    sender: &mut SocketSender,
+) -> bool {
+    let room = state
+        .app_model
+        .read()
+        .await
+        .rooms
+        .get(&room_id)
+        .map(|room| match view {
+            RoomView::Whole => room.clone(),
+            RoomView::Public => room_access::public_view(room),
+        });
+    match room {
+        Some(room) => send(sender, &ServerMessage::Snapshot { room }).await,
+        None => {
+            let _ = send(sender, &ServerMessage::RoomClosed).await;
+            false
+        }
+    }
+}
+
+/// Validates a member's message and turns it into room events
+async fn handle_client_message(
+    state: &AppState,
+    room_id: u64,
+    member: Option<&str>,
+    signed_in: Option<u64>,
+    message: ClientMessage,
+) -> Result<(), String> {
+    let mut app_model = state.app_model.write().await;
+    let room = app_model
+        .rooms
+        .get(&room_id)
+        .ok_or_else(|| room_not_found(room_id).0.message)?;
+    let is_host = room.is_host(signed_in);
+    // Hosts needn't be members to steer the room, everything else is done as a member
+    let sender = match &message {
+        ClientMessage::Nominate { .. }
+        | ClientMessage::Vote { .. }
+        | ClientMessage::Chat { .. } => {
+            MemberRef::of(room_access::acting_member(room, member, signed_in)?)
+        }
+        _ if !is_host => return Err("Only the room's host can do that".to_owned()),
+        _ => MemberRef::default(),
+    };
+    let now = unix_now();
+
+    let events = match message {
+        ClientMessage::Nominate { app_id } => {
+            if room.phase != RoomPhase::Nominating {
+                return Err("Games can only be nominated while nominating".to_owned());
+            }
+            if room.nominations.iter().any(|n| n.app_id == app_id) {
+                return Err("This game is already nominated".to_owned());
+            }
+            let name = game_name(&app_model, room, app_id);
+            vec![ServerMessage::Nominated {
+                nomination: Nomination {
+                    app_id,
+                    name,
+                    nominated_by: sender,
+                    votes: Vec::new(),
+                },
+            }]
+        }
+        ClientMessage::Vote { app_id } => {
+            if room.phase != RoomPhase::Voting {
+                return Err("Voting hasn't started".to_owned());
+            }
+            if !room.nominations.iter().any(|n| n.app_id == app_id) {
+                return Err("Only nominated games can be voted for".to_owned());
+            }
+            vec![ServerMessage::Voted {
+                app_id,
+                voter: sender,
+            }]
+        }
+        ClientMessage::Chat { text } => {
+            let text = text.trim();
+            if text.is_empty() {
+                return Err("Empty message".to_owned());
+            }
+            vec![ServerMessage::Chat {
+                message: ChatMessage {
+                    from: sender.steam_name,
+                    text: text.chars().take(MAX_CHAT_LENGTH).collect(),
+                    sent_at: now,
+                },
+            }]
+        }
+        ClientMessage::Decide { app_id } => room_phases::decide(room, app_id, now)?,
+        ClientMessage::SetPhase { phase } => {
+            let event = room_phases::transition(room, phase, true, now)?;
+            if phase == RoomPhase::Closed {
+                close_room(
+                    &mut app_model,
+                    &state.room_events,
+                    room_id,
+                    CloseReason::Closed,
+                );
+                return Ok(());
+            }
+            vec![event]
+        }
+        ClientMessage::Lock { locked } => vec![ServerMessage::AccessChanged {
+            locked,
+            password_protected: room.password_protected,
+        }],
+        ClientMessage::SetPassword { password } => {
+            let locked = room.locked;
+            let password = password.filter(|password| !password.is_empty());
+            app_model.set_room_password(room_id, password.as_deref());
+            vec![ServerMessage::AccessChanged {
+                locked,
+                password_protected: password.is_some(),
+            }]
+        }
+        ClientMessage::Kick {
+            steam_id,
+            steam_name,
+            ban,
+        } => {
+            let target = room
+                .customers
+                .iter()
+                .chain(&room.spectators)
+                .find(|c| is_same_customer(c, steam_id, &steam_name))
+                .ok_or("Nobody by that name is in the room")?;
+            if target.steam_id.is_some() && target.steam_id == room.host {
+                return Err("Hand the room over before leaving it".to_owned());
+            }
+            if ban && target.steam_id.is_none() {
+                return Err(format!(
+                    "{} has no Steam ID to ban, kick them instead",
+                    target.steam_name
+                ));
+            }
+            vec![ServerMessage::Kicked {
+                steam_id: target.steam_id,
+                steam_name: target.steam_name.clone(),
+                banned: ban,
+            }]
+        }
+        ClientMessage::TransferHost { steam_id } => {
+            if room.role_of(steam_id) != Some(Role::Member) {
+                return Err("Only members can become the host".to_owned());
+            }
+            vec![ServerMessage::HostChanged { host: steam_id }]
+        }
+        ClientMessage::Promote {
+            steam_id,
+            steam_name,
+        } => {
+            let spectator = room
+                .spectators
+                .iter()
+                .find(|c| is_same_customer(c, steam_id, &steam_name))
+                .ok_or("Nobody by that name is watching the room")?;
+            // Spectators are kept without their library, which the model still has
+            let member = app_model
+                .customers
+                .iter()
+                .find(|c| is_same_customer(c, spectator.steam_id, &spectator.steam_name))
+                .map_or_else(
+                    || spectator.clone(),
+                    |customer| Customer {
+                        verified: spectator.verified,
+                        ..customer.clone()
+                    },
+                );
+            vec![ServerMessage::Promoted { member }]
+        }
+    };
+
+    for event in events {
+        apply_and_publish(&mut app_model, &state.room_events, room_id, event);
+    }
+    Ok(())
+}
+
+/// The name of a game as a member's library or its store page knows it
+fn game_name(app_model: &AppModel, room: &Room, app_id: u64) -> String {
+    room.customers
+        .iter()
+        .flat_map(|c| &c.games)
+        .find(|g| g.app_id == app_id)
+        .map(|g| g.name.clone())
+        .or_else(|| {
+            let app = app_model.metadata.get(app_id)?;
+            Some(app.details.name.clone()).filter(|name| !name.is_empty())
+        })
+        .unwrap_or_else(|| placeholder_name(app_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use library::{ApiErrorCode, ContentLimits, Game, PhaseTimers};
+
+    use super::*;
+    use crate::SteamGameLibrary;
+    use crate::steam::steam_user_library::SteamGame;
+    use crate::storage::Storage;
+
+    #[test]
+    fn connections_act_as_members_only_when_allowed_to() {
+        let mut room = Room {
+            host: Some(1),
+            ..Room::new(1, ContentLimits::default())
+        };
+        room.customers = vec![
+            Customer {
+                steam_name: "Alice".to_owned(),
+                steam_id: Some(2),
+                verified: true,
+                ..Default::default()
+            },
+            Customer {
+                steam_name: "Bob".to_owned(),
+                steam_id: Some(3),
+                ..Default::default()
+            },
+        ];
+        let access = |member: Option<&str>, signed_in: Option<u64>| {
+            connection_access(&room, member.map(str::to_owned), signed_in, false)
+                .map_err(|ErrorResponse(error)| error.code)
+        };
+
+        let whole = |member: Option<&str>| Ok((member.map(str::to_owned), RoomView::Whole));
+        assert_eq!(access(Some("2"), Some(2)), whole(Some("2")));
+        assert_eq!(access(None, Some(2)), whole(Some("2")));
+        assert_eq!(access(Some("3"), None), whole(Some("3")));
+        assert_eq!(access(None, Some(1)), whole(None));
+        // Giving a verified member's Steam ID only gets to watch the public view
+        assert_eq!(access(Some("2"), None), Ok((None, RoomView::Public)));
+        assert_eq!(access(None, None), Ok((None, RoomView::Public)));
+    }
+
+    #[test]
+    fn watching_takes_what_joining_takes() {
+        let mut room = Room {
+            host: Some(1),
+            banned: vec![4],
+            ..Room::new(1, ContentLimits::default())
+        };
+        room.spectators.push(Customer {
+            steam_id: Some(5),
+            ..Default::default()
+        });
+        let watch = |room: &Room, signed_in: Option<u64>, password_ok: bool| {
+            connection_access(room, None, signed_in, password_ok)
+                .map(|(_, view)| view)
+                .map_err(|ErrorResponse(error)| error.code)
+        };
+
+        assert_eq!(watch(&room, Some(4), true), Err(ApiErrorCode::Forbidden));
+
+        room.password_protected = true;
+        assert_eq!(watch(&room, None, false), Err(ApiErrorCode::Forbidden));
+        assert_eq!(watch(&room, None, true), Ok(RoomView::Public));
+
+        room.locked = true;
+        assert_eq!(watch(&room, None, true), Err(ApiErrorCode::Forbidden));
+        // Spectators who joined, and the host, still get in and see the whole room
+        assert_eq!(watch(&room, Some(5), false), Ok(RoomView::Whole));
+        assert_eq!(watch(&room, Some(1), false), Ok(RoomView::Whole));
+    }
+
+    #[test]
+    fn kicks_close_the_kicked_connections_only() {
+        let kicked = |steam_id: Option<u64>, steam_name: &str| ServerMessage::Kicked {
+            steam_id,
+            steam_name: steam_name.to_owned(),
+            banned: false,
+        };
+
+        assert!(kicks_connection(&kicked(Some(2), "Bob"), Some("2"), None));
+        assert!(kicks_connection(&kicked(Some(2), "Bob"), None, Some(2)));
+        assert!(kicks_connection(&kicked(None, "Bob"), Some("Bob"), None));
+        assert!(!kicks_connection(
+            &kicked(Some(2), "Bob"),
+            Some("Bob"),
+            None
+        ));
+        assert!(!kicks_connection(
+            &kicked(Some(2), "Bob"),
+            Some("3"),
+            Some(1)
+        ));
+        assert!(!kicks_connection(&kicked(None, "Bob"), None, None));
+        assert!(!kicks_connection(
+            &ServerMessage::RoomClosed,
+            Some("2"),
+            Some(2)
+        ));
+    }
+
+    #[test]
+    fn reloaded_libraries_reach_the_rooms_before_classification() {
+        let mut app_model = AppModel::with_storage(
+            Storage::in_memory(),
+            SteamGameLibrary {
+                api_key: String::new(),
+            },
+        );
+        let room_id = app_model
+            .create_room(ContentLimits::default(), PhaseTimers::default(), 1)
+            .id;
+        let bob = Customer {
+            steam_name: "Bob".to_owned(),
+            steam_id: Some(2),
+            ..Default::default()
+        };
+        app_model.rooms.get_mut(&room_id).unwrap().customers = vec![Customer {
+            verified: true,
+            ..bob.clone()
+        }];
+        app_model.upsert_customer(Customer {
+            games: vec![Game::from(SteamGame {
+                app_id: 620,
+                name: "Portal 2".to_owned(),
+                total_playtime: 0,
+                playtime_windows_forever: 0,
+                playtime_mac_forever: 0,
+                playtime_linux_forever: 0,
+                playtime_deck_forever: 0,
+                img_icon_url: String::new(),
+            })],
+            ..bob
+        });
+        let room_events = RoomEvents::default();
+        let mut events = room_events.subscribe(room_id);
+
+        publish_library_loaded(&mut app_model, &room_events, Some(2), "Bob");
+        publish_library_classified(&mut app_model, &room_events, Some(2), "Bob");
+
+        let member = &app_model.rooms[&room_id].customers[0];
+        assert_eq!(member.games[0].app_id, 620);
+        assert!(member.verified);
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ServerMessage::LibraryLoaded { member }) if member.verified
+        ));
+        assert!(matches!(
+            events.try_recv(),
+            Ok(ServerMessage::LibraryClassified { games, .. }) if games.len() == 1
+        ));
+    }
+}
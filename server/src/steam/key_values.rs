@@ -0,0 +1,298 @@
+//! Parser for Valve's KeyValues text format.
+//!
+//! This is the format used by the files the Steam client keeps on disk, such as
+//! `libraryfolders.vdf`, `appmanifest_<appid>.acf` and `localconfig.vdf`:
+//!
+//! ```text
+//! "AppState"
+//! {
+//!     "appid"        "620"
+//!     "name"         "Portal 2"
+//!     "StateFlags"   "4"
+//!     "SizeOnDisk"   "12968423748"
+//! }
+//! ```
+//!
+//! Keys are matched case-insensitively, because Steam itself is not consistent
+//! about it (`Software` vs `software` in `localconfig.vdf`).
+
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum KeyValuesError {
+    #[error("Unexpected end of input on line {0}")]
+    UnexpectedEof(usize),
+    #[error("Unexpected token `{token}` on line {line}")]
+    UnexpectedToken { line: usize, token: String },
+    #[error("Unterminated string starting on line {0}")]
+    UnterminatedString(usize),
+}
+
+/// A single node of a KeyValues document: either a plain string value or a section of
+/// nested key/value pairs. Duplicate keys are kept in file order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyValue {
+    Value(String),
+    Section(Vec<(String, KeyValue)>),
+}
+
+impl KeyValue {
+    /// Returns the first child with the given key, if this node is a section.
+    pub fn get(&self, key: &str) -> Option<&KeyValue> {
+        self.entries()
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Follows a chain of keys through nested sections.
+    pub fn path(&self, keys: &[&str]) -> Option<&KeyValue> {
+        keys.iter().try_fold(self, |node, key| node.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            KeyValue::Value(v) => Some(v),
+            KeyValue::Section(_) => None,
+        }
+    }
+
+    /// Returns the child pairs of a section, or nothing for a plain value.
+    pub fn entries(&self) -> &[(String, KeyValue)] {
+        match self {
+            KeyValue::Value(_) => &[],
+            KeyValue::Section(entries) => entries,
+        }
+    }
+
+    /// Looks up a child value and parses it, e.g. `manifest.parse_value::<u64>("SizeOnDisk")`.
+    pub fn parse_value<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.as_str()?.trim().parse().ok()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Open,
+    Close,
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, KeyValuesError> {
+        loop {
+            let Some(c) = self.chars.next() else {
+                return Ok(None);
+            };
+
+            match c {
+                '\n' => self.line += 1,
+                c if c.is_whitespace() => {}
+                '/' if self.chars.peek() == Some(&'/') => self.skip_line(),
+                // Platform conditionals such as `[$WIN32]` are irrelevant for the files we read
+                '[' => self.skip_conditional(),
+                '{' => return Ok(Some(Token::Open)),
+                '}' => return Ok(Some(Token::Close)),
+                '"' => return self.quoted().map(|s| Some(Token::Text(s))),
+                c => return Ok(Some(Token::Text(self.unquoted(c)))),
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        for c in self.chars.by_ref() {
+            if c == '\n' {
+                self.line += 1;
+                break;
+            }
+        }
+    }
+
+    fn skip_conditional(&mut self) {
+        for c in self.chars.by_ref() {
+            if c == ']' {
+                break;
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, KeyValuesError> {
+        let start_line = self.line;
+        let mut text = String::new();
+
+        while let Some(c) = self.chars.next() {
+            match c {
+                '"' => return Ok(text),
+                '\\' => match self.chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some(other) => text.push(other),
+                    None => break,
+                },
+                '\n' => {
+                    self.line += 1;
+                    text.push(c);
+                }
+                c => text.push(c),
+            }
+        }
+
+        Err(KeyValuesError::UnterminatedString(start_line))
+    }
+
+    fn unquoted(&mut self, first: char) -> String {
+        let mut text = String::from(first);
+        while let Some(&c) = self.chars.peek() {
+            if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+                break;
+            }
+            text.push(c);
+            self.chars.next();
+        }
+        text
+    }
+}
+
+/// Parses a KeyValues document into a root section holding its top-level pairs.
+pub fn parse(input: &str) -> Result<KeyValue, KeyValuesError> {
+    let mut lexer = Lexer::new(input.trim_start_matches('\u{feff}'));
+    let entries = parse_entries(&mut lexer, false)?;
+    Ok(KeyValue::Section(entries))
+}
+
+fn parse_entries(
+    lexer: &mut Lexer,
+    nested: bool,
+) -> Result<Vec<(String, KeyValue)>, KeyValuesError> {
+    let mut entries = Vec::new();
+
+    loop {
+        let key = match lexer.next_token()? {
+            Some(Token::Text(key)) => key,
+            Some(Token::Close) if nested => return Ok(entries),
+            None if !nested => return Ok(entries),
+            None => return Err(KeyValuesError::UnexpectedEof(lexer.line)),
+            Some(token) => {
+                return Err(KeyValuesError::UnexpectedToken {
+                    line: lexer.line,
+                    token: token_text(&token),
+                });
+            }
+        };
+
+        let value = match lexer.next_token()? {
+            Some(Token::Text(value)) => KeyValue::Value(value),
+            Some(Token::Open) => KeyValue::Section(parse_entries(lexer, true)?),
+            Some(token) => {
+                return Err(KeyValuesError::UnexpectedToken {
+                    line: lexer.line,
+                    token: token_text(&token),
+                });
+            }
+            None => return Err(KeyValuesError::UnexpectedEof(lexer.line)),
+        };
+
+        entries.push((key, value));
+    }
+}
+
+fn token_text(token: &Token) -> String {
+    match token {
+        Token::Text(text) => text.clone(),
+        Token::Open => "{".to_owned(),
+        Token::Close => "}".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(text: &str) -> KeyValue {
+        KeyValue::Value(text.to_owned())
+    }
+
+    #[test]
+    fn parses_nested_sections_in_file_order() {
+        let root = parse(
+            r#"
+            "Root"
+            {
+                "a"     "1"
+                "Inner" { "b" "2" "b" "3" }
+                unquoted value
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(root.path(&["root", "inner", "B"]), Some(&value("2")));
+        assert_eq!(
+            root.path(&["Root", "Inner"]).unwrap().entries(),
+            [("b".to_owned(), value("2")), ("b".to_owned(), value("3"))]
+        );
+        assert_eq!(root.path(&["Root", "unquoted"]), Some(&value("value")));
+        assert_eq!(root.path(&["Root", "a"]).unwrap().entries(), []);
+        assert_eq!(root.get("Root").unwrap().parse_value::<u64>("a"), Some(1));
+    }
+
+    #[test]
+    fn reads_escapes_comments_and_conditionals() {
+        let root = parse(
+            "\u{feff}// Written by Steam\n\
+             \"k\" \"say \\\"hi\\\"\\n\\\\\" // trailing comment\n\
+             \"win\" \"1\" [$WIN32]\n\
+             \"path\" \"C:/Games\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(root.get("k"), Some(&value("say \"hi\"\n\\")));
+        assert_eq!(root.get("win"), Some(&value("1")));
+        assert_eq!(root.get("path"), Some(&value("C:/Games")));
+    }
+
+    #[test]
+    fn reports_where_documents_break() {
+        assert_eq!(
+            parse("\"a\"\n\"b"),
+            Err(KeyValuesError::UnterminatedString(2))
+        );
+        assert_eq!(
+            parse("\"a\" \"ends with \\"),
+            Err(KeyValuesError::UnterminatedString(1))
+        );
+        assert_eq!(
+            parse("\"a\"\n{\n\"b\" \"c\"\n"),
+            Err(KeyValuesError::UnexpectedEof(4))
+        );
+        assert_eq!(parse("\"a\""), Err(KeyValuesError::UnexpectedEof(1)));
+        assert_eq!(
+            parse("}"),
+            Err(KeyValuesError::UnexpectedToken {
+                line: 1,
+                token: "}".to_owned()
+            })
+        );
+        assert_eq!(
+            parse("\"a\" }"),
+            Err(KeyValuesError::UnexpectedToken {
+                line: 1,
+                token: "}".to_owned()
+            })
+        );
+    }
+}
@@ -1,2 +1,15 @@
+pub mod key_values;
+pub mod steam_app_details;
+pub mod steam_apps;
+pub mod steam_cache;
+pub mod steam_circuit;
 pub mod steam_client;
-pub mod steam_user_library;
\ No newline at end of file
+pub mod steam_endpoint;
+pub mod steam_local_library;
+pub mod steam_news;
+pub mod steam_openid;
+pub mod steam_profile;
+pub mod steam_quota;
+pub mod steam_reviews;
+pub mod steam_transport;
+pub mod steam_user_library;
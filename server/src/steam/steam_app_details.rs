@@ -0,0 +1,177 @@
+//! This module deals with store metadata about a single app.
+
+use library::{AppType, ContentDescriptor, DeckCompatibility, PlatformSupport};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::steam::steam_client::SteamClient;
+use crate::steam::steam_client::SteamError;
+
+/// The Steam store "appdetails" endpoint, which isn't part of the Web API
+const ENDPOINT_APP_DETAILS: &str = "https://store.steampowered.com/api/appdetails";
+
+/// The endpoint the store uses to render the Steam Deck compatibility badge
+const ENDPOINT_DECK_COMPATIBILITY: &str =
+    "https://store.steampowered.com/saleaction/ajaxgetdeckappcompatibilityreport";
+
+/// Helper struct used during deserializing the API response, which is keyed by app id:
+/// `{ "620": { "success": true, "data": { ... } } }`
+#[derive(Debug, Deserialize)]
+struct AppDetailsEnvelope {
+    success: bool,
+    data: Option<AppDetails>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AppDetails {
+    #[serde(rename(deserialize = "steam_appid"))]
+    pub app_id: u64,
+    pub name: String,
+    /// "game", "dlc", "music", "demo", ... see `AppType::from_steam_type`
+    #[serde(default, rename(deserialize = "type"))]
+    pub steam_type: String,
+    /// For DLC and soundtracks, the game they belong to
+    #[serde(default)]
+    pub fullgame: Option<FullGame>,
+    #[serde(default)]
+    pub platforms: PlatformSupport,
+    /// HTML list such as `English<strong>*</strong>, French<br><strong>*</strong>languages
+    /// with full audio support`, see `languages()`
+    #[serde(default)]
+    pub supported_languages: String,
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub required_age: u32,
+    #[serde(default)]
+    pub content_descriptors: ContentDescriptors,
+    #[serde(default)]
+    pub is_free: bool,
+    /// Store features such as "Multi-player" or "Online Co-op"
+    #[serde(default)]
+    pub categories: Vec<StoreLabel>,
+    #[serde(default)]
+    pub genres: Vec<StoreLabel>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StoreLabel {
+    /// A number for categories but a string for genres
+    #[serde(deserialize_with = "number_or_string")]
+    pub id: u64,
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FullGame {
+    /// Sent as a string, unlike every other app id
+    #[serde(deserialize_with = "number_or_string")]
+    pub appid: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ContentDescriptors {
+    #[serde(default)]
+    pub ids: Vec<u64>,
+}
+
+impl AppDetails {
+    pub fn app_type(&self) -> AppType {
+        AppType::from_steam_type(&self.steam_type)
+    }
+
+    /// The base game of a DLC or soundtrack
+    pub fn base_game(&self) -> Option<u64> {
+        self.fullgame.as_ref().map(|fullgame| fullgame.appid)
+    }
+
+    /// The supported languages, without the store's markup and audio footnote
+    pub fn languages(&self) -> Vec<String> {
+        let list = self
+            .supported_languages
+            .split("<br>")
+            .next()
+            .unwrap_or_default();
+
+        list.split(',')
+            .map(|language| strip_tags(language).trim_end_matches('*').trim().to_owned())
+            .filter(|language| !language.is_empty())
+            .collect()
+    }
+
+    pub fn content_descriptors(&self) -> Vec<ContentDescriptor> {
+        self.content_descriptors
+            .ids
+            .iter()
+            .filter_map(|&id| ContentDescriptor::from_steam_id(id))
+            .collect()
+    }
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// The store sends some numbers as either `0` or `"18"` depending on the app
+fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64> + Default,
+{
+    let number = match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.trim().trim_end_matches('+').parse().ok(),
+        _ => None,
+    };
+    Ok(number.and_then(|n| T::try_from(n).ok()).unwrap_or_default())
+}
+
+impl SteamClient {
+    pub async fn get_app_details(&self, app_id: u64) -> Result<AppDetails, SteamError> {
+        let response = self
+            .get_store_request(ENDPOINT_APP_DETAILS, vec![("appids", app_id)])
+            .await?;
+
+        let envelope = response
+            .get(app_id.to_string())
+            .cloned()
+            .ok_or(SteamError::NoData)?;
+
+        match self.parse_response::<AppDetailsEnvelope, AppDetailsEnvelope>(envelope)? {
+            AppDetailsEnvelope {
+                success: true,
+                data: Some(details),
+            } => Ok(details),
+            _ => Err(SteamError::NoData),
+        }
+    }
+
+    pub async fn get_deck_compatibility(
+        &self,
+        app_id: u64,
+    ) -> Result<DeckCompatibility, SteamError> {
+        let response = self
+            .get_store_request(ENDPOINT_DECK_COMPATIBILITY, vec![("nAppID", app_id)])
+            .await?;
+
+        // `results` is an empty array for apps Valve hasn't reviewed yet
+        let category = response
+            .pointer("/results/resolved_category")
+            .and_then(|c| c.as_u64());
+
+        Ok(match category {
+            Some(1) => DeckCompatibility::Unsupported,
+            Some(2) => DeckCompatibility::Playable,
+            Some(3) => DeckCompatibility::Verified,
+            _ => DeckCompatibility::Unknown,
+        })
+    }
+}
@@ -0,0 +1,45 @@
+//! This module deals with Steam-wide app lists, such as the most played games.
+
+use serde::Deserialize;
+
+use crate::steam::steam_client::SteamClient;
+use crate::steam::steam_client::SteamError;
+use crate::steam::steam_endpoint::{QueryParams, SteamEndpoint};
+use crate::steam::steam_quota::CallPriority;
+
+/// The Steam API "GetMostPlayedGames (v0001)" endpoint, ranked by daily peak players
+struct GetMostPlayedGames;
+
+impl SteamEndpoint for GetMostPlayedGames {
+    const INTERFACE: &'static str = "ISteamChartsService";
+    const METHOD: &'static str = "GetMostPlayedGames";
+    const VERSION: u32 = 1;
+    const PRIORITY: CallPriority = CallPriority::Optional;
+    type Response = MostPlayedRanks;
+
+    fn params(&self) -> QueryParams {
+        QueryParams::new()
+    }
+}
+
+/// Helper struct used during deserializing the API response.
+#[derive(Debug, Default, Deserialize)]
+struct MostPlayedRanks {
+    #[serde(default)]
+    ranks: Vec<MostPlayedGame>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MostPlayedGame {
+    pub rank: u32,
+    #[serde(rename(deserialize = "appid"))]
+    pub app_id: u64,
+    #[serde(default)]
+    pub peak_in_game: u64,
+}
+
+impl SteamClient {
+    pub async fn get_most_played_games(&self) -> Result<Vec<MostPlayedGame>, SteamError> {
+        Ok(self.call(&GetMostPlayedGames).await?.ranks)
+    }
+}
@@ -0,0 +1,162 @@
+//! Short-lived cache of raw Steam responses, shared by every caller of `SteamClient`. It saves
+//! repeated calls when several rooms ask about the same apps or users at once.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Long enough to share responses between the requests of one busy evening, short enough
+/// that a library change shows up quickly
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Where Steam responses are cached, see `open`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheKind {
+    Memory,
+    Sqlite,
+    None,
+}
+
+impl FromStr for CacheKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "memory" => Ok(Self::Memory),
+            "sqlite" => Ok(Self::Sqlite),
+            "none" => Ok(Self::None),
+            other => Err(format!(
+                "unknown cache {other}, expected memory, sqlite or none"
+            )),
+        }
+    }
+}
+
+/// Opens the cache. A SQLite file that can't be opened falls back to memory.
+pub fn open(kind: CacheKind, path: &Path) -> Box<dyn ResponseCache> {
+    match kind {
+        CacheKind::None => Box::new(NoCache),
+        CacheKind::Memory => Box::new(MemoryCache::default()),
+        CacheKind::Sqlite => match SqliteCache::open(path) {
+            Ok(cache) => Box::new(cache),
+            Err(error) => {
+                log::error!(
+                    "Can't open the response cache {}, keeping it in memory: {error}",
+                    path.display()
+                );
+                Box::new(MemoryCache::default())
+            }
+        },
+    }
+}
+
+pub trait ResponseCache: Send + Sync {
+    /// The cached response for `key`, if it hasn't expired
+    fn get(&self, key: &str) -> Option<Value>;
+
+    fn put(&self, key: &str, response: &Value, ttl: Duration);
+}
+
+/// Always misses, every call goes to Steam
+#[derive(Debug, Default)]
+pub struct NoCache;
+
+impl ResponseCache for NoCache {
+    fn get(&self, _key: &str) -> Option<Value> {
+        None
+    }
+
+    fn put(&self, _key: &str, _response: &Value, _ttl: Duration) {}
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, (Value, Instant)>>,
+}
+
+impl ResponseCache for MemoryCache {
+    fn get(&self, key: &str) -> Option<Value> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((response, expires_at)) if Instant::now() < *expires_at => Some(response.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn put(&self, key: &str, response: &Value, ttl: Duration) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), (response.clone(), Instant::now() + ttl));
+    }
+}
+
+/// Keeps responses across restarts in a SQLite file
+pub struct SqliteCache {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteCache {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS response_cache (
+                key TEXT PRIMARY KEY,
+                response TEXT NOT NULL,
+                expires_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl ResponseCache for SqliteCache {
+    fn get(&self, key: &str) -> Option<Value> {
+        let connection = self.connection.lock().unwrap();
+        let response: Option<String> = connection
+            .query_row(
+                "SELECT response FROM response_cache WHERE key = ?1 AND expires_at > ?2",
+                params![key, unix_now()],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap_or_else(|error| {
+                log::warn!("Can't read the response cache: {error}");
+                None
+            });
+        response.and_then(|response| serde_json::from_str(&response).ok())
+    }
+
+    fn put(&self, key: &str, response: &Value, ttl: Duration) {
+        let connection = self.connection.lock().unwrap();
+        let expires_at = unix_now() + ttl.as_secs() as i64;
+        let written = connection.execute(
+            "INSERT OR REPLACE INTO response_cache (key, response, expires_at)
+             VALUES (?1, ?2, ?3)",
+            params![key, response.to_string(), expires_at],
+        );
+        if let Err(error) = written {
+            log::warn!("Can't write the response cache: {error}");
+        }
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs() as i64)
+}
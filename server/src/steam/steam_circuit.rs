@@ -0,0 +1,182 @@
+//! Per-endpoint circuit breakers, so a Steam outage fails fast instead of making every room
+//! request wait on timeouts.
+//!
+//! After `FAILURE_THRESHOLD` failures in a row an endpoint's circuit opens and calls to it are
+//! refused for `OPEN_DURATION`. The first call after that is let through as a trial: success
+//! closes the circuit, failure opens it again.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::steam::steam_client::SteamError;
+
+const FAILURE_THRESHOLD: u32 = 5;
+
+const OPEN_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, Default)]
+enum CircuitState {
+    #[default]
+    Closed,
+    Open {
+        until: Instant,
+    },
+    /// A trial call is in flight, others are refused until it completes. A trial that never
+    /// reports back (refused by the quota, say) is replaced after `OPEN_DURATION`.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct CircuitBreakers {
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreakers {
+    /// Refuses the call with `SteamError::Unavailable` while the endpoint's circuit is open
+    pub fn allow(&self, endpoint: &str) -> Result<(), SteamError> {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(endpoint.to_owned()).or_default();
+
+        match circuit.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open { until } if Instant::now() < until => Err(SteamError::Unavailable),
+            CircuitState::HalfOpen { since } if since.elapsed() < OPEN_DURATION => {
+                Err(SteamError::Unavailable)
+            }
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => {
+                circuit.state = CircuitState::HalfOpen {
+                    since: Instant::now(),
+                };
+                Ok(())
+            }
+        }
+    }
+
+    pub fn record_success(&self, endpoint: &str) {
+        let mut circuits = self.circuits.lock().unwrap();
+        if let Some(circuit) = circuits.get_mut(endpoint) {
+            if !matches!(circuit.state, CircuitState::Closed) {
+                log::info!("Steam endpoint {endpoint} is back, closing its circuit");
+            }
+            *circuit = Circuit::default();
+        }
+    }
+
+    /// Only failures on Steam's side count: network errors, 5xx and rate limiting
+    pub fn record_failure(&self, endpoint: &str) {
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(endpoint.to_owned()).or_default();
+        circuit.consecutive_failures += 1;
+
+        let trial_failed = matches!(circuit.state, CircuitState::HalfOpen { .. });
+        if trial_failed || circuit.consecutive_failures >= FAILURE_THRESHOLD {
+            log::warn!(
+                "Steam endpoint {endpoint} failed {} times in a row, opening its circuit",
+                circuit.consecutive_failures
+            );
+            circuit.state = CircuitState::Open {
+                until: Instant::now() + OPEN_DURATION,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENDPOINT: &str = "https://api.steampowered.com/IPlayerService/GetOwnedGames/v1";
+
+    fn open_circuit(breakers: &CircuitBreakers) {
+        for _ in 0..FAILURE_THRESHOLD {
+            breakers.record_failure(ENDPOINT);
+        }
+    }
+
+    /// Moves the circuit's clock forward instead of waiting `OPEN_DURATION`
+    fn expire(breakers: &CircuitBreakers) {
+        let past = Instant::now().checked_sub(OPEN_DURATION).unwrap();
+        let mut circuits = breakers.circuits.lock().unwrap();
+        let circuit = circuits.get_mut(ENDPOINT).unwrap();
+        circuit.state = match circuit.state {
+            CircuitState::Open { .. } => CircuitState::Open { until: past },
+            CircuitState::HalfOpen { .. } => CircuitState::HalfOpen { since: past },
+            CircuitState::Closed => CircuitState::Closed,
+        };
+    }
+
+    #[test]
+    fn opens_after_failures_in_a_row() {
+        let breakers = CircuitBreakers::default();
+        for _ in 1..FAILURE_THRESHOLD {
+            breakers.record_failure(ENDPOINT);
+        }
+        breakers.record_success(ENDPOINT);
+        for _ in 1..FAILURE_THRESHOLD {
+            breakers.record_failure(ENDPOINT);
+        }
+        assert!(breakers.allow(ENDPOINT).is_ok());
+
+        breakers.record_failure(ENDPOINT);
+
+        assert!(matches!(
+            breakers.allow(ENDPOINT),
+            Err(SteamError::Unavailable)
+        ));
+        assert!(
+            breakers
+                .allow("https://api.steampowered.com/ISteamNews")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn lets_one_trial_through_once_open_duration_passed() {
+        let breakers = CircuitBreakers::default();
+        open_circuit(&breakers);
+        expire(&breakers);
+
+        assert!(breakers.allow(ENDPOINT).is_ok());
+        assert!(breakers.allow(ENDPOINT).is_err());
+
+        // A trial that never reports back is replaced
+        expire(&breakers);
+        assert!(breakers.allow(ENDPOINT).is_ok());
+    }
+
+    #[test]
+    fn successful_trial_closes_the_circuit() {
+        let breakers = CircuitBreakers::default();
+        open_circuit(&breakers);
+        expire(&breakers);
+        breakers.allow(ENDPOINT).unwrap();
+
+        breakers.record_success(ENDPOINT);
+
+        assert!(breakers.allow(ENDPOINT).is_ok());
+        assert!(breakers.allow(ENDPOINT).is_ok());
+        breakers.record_failure(ENDPOINT);
+        assert!(breakers.allow(ENDPOINT).is_ok());
+    }
+
+    #[test]
+    fn failed_trial_opens_the_circuit_again() {
+        let breakers = CircuitBreakers::default();
+        open_circuit(&breakers);
+        expire(&breakers);
+        breakers.allow(ENDPOINT).unwrap();
+
+        breakers.record_failure(ENDPOINT);
+
+        assert!(breakers.allow(ENDPOINT).is_err());
+    }
+}
@@ -1,11 +1,22 @@
+use std::time::Duration;
+
 use log::warn;
-use reqwest::Client;
 use reqwest::Error;
 use reqwest::StatusCode;
 use serde::{Serialize, de::DeserializeOwned};
 use serde_json::Value;
 use thiserror::Error;
 
+use crate::config::SteamConfig;
+use crate::steam::key_values::KeyValuesError;
+use crate::steam::steam_cache::{self, DEFAULT_CACHE_TTL, MemoryCache, ResponseCache};
+use crate::steam::steam_circuit::CircuitBreakers;
+use crate::steam::steam_endpoint::SteamEndpoint;
+use crate::steam::steam_quota::{CallPriority, QuotaBudget, SteamQuota};
+use crate::steam::steam_transport::{
+    self, ReqwestTransport, SteamTransport, TransportResponse, request_key,
+};
+
 // Possible SteamID's
 // SteamID - STEAM_0:0:11101
 // SteamID3 - [U:1:22202]
@@ -26,6 +37,32 @@ pub enum SteamError {
     /// deserialization error in serde.
     #[error("The data you requested is either private or empty")]
     NoData,
+    /// A local Steam file (VDF/ACF) could not be understood
+    #[error("Malformed Steam file: {0}")]
+    MalformedFile(String),
+    /// The daily Steam API budget is spent and the call wasn't essential
+    #[error("The daily Steam API budget is spent, try again tomorrow")]
+    QuotaExceeded,
+    /// Steam has been failing and the endpoint's circuit breaker is open
+    #[error("Steam is currently unavailable")]
+    Unavailable,
+}
+
+impl SteamError {
+    /// Steam couldn't be asked or didn't answer. Private or missing data, or a local file we
+    /// can't read, isn't an outage: asking again later wouldn't help.
+    pub fn is_outage(&self) -> bool {
+        matches!(
+            self,
+            Self::FailedRequest(_) | Self::QuotaExceeded | Self::Unavailable
+        )
+    }
+}
+
+impl From<KeyValuesError> for SteamError {
+    fn from(err: KeyValuesError) -> Self {
+        Self::MalformedFile(err.to_string())
+    }
 }
 
 impl From<reqwest::Error> for SteamError {
@@ -36,69 +73,155 @@ impl From<reqwest::Error> for SteamError {
     }
 }
 
-/// This struct holds the blocking reqwest client and is used to interact with the API.
+/// This struct holds the transport and response cache and is used to interact with the API.
 pub struct SteamClient {
-    client: Client,
+    transport: Box<dyn SteamTransport>,
+    cache: Box<dyn ResponseCache>,
+    cache_ttl: Duration,
     api_key: String,
+    quota: SteamQuota,
+    circuits: CircuitBreakers,
 }
 
 impl Default for SteamClient {
     fn default() -> Self {
-        let client = reqwest::Client::new();
-        Self {
-            client,
-            api_key: String::new(),
-        }
+        Self::new()
     }
 }
 
 impl SteamClient {
-    /// Returns a new SteamClient instance carrying a developer API token
-    pub fn from(api_key: String) -> Self {
-        let client = reqwest::Client::new();
-        Self { client, api_key }
+    /// Returns a new SteamClient instance carrying the configured API key, transport, cache
+    /// and budget
+    pub fn from_config(config: &SteamConfig) -> Self {
+        let budget = QuotaBudget {
+            soft: config.soft_daily_budget,
+            hard: config.hard_daily_budget,
+        };
+        Self {
+            cache_ttl: config.cache_ttl(),
+            quota: SteamQuota::load(config.usage_file.clone(), budget),
+            ..Self::with_backends(
+                config.api_key.clone(),
+                steam_transport::open(config.transport, &config.recordings_file),
+                steam_cache::open(config.cache, &config.cache_file),
+            )
+        }
     }
 
     /// Return a SteamClient without a Steam API token
     pub fn new() -> Self {
-        let client = reqwest::Client::new();
+        Self::with_backends(
+            String::new(),
+            Box::new(ReqwestTransport::default()),
+            Box::new(MemoryCache::default()),
+        )
+    }
+
+    /// A SteamClient reaching Steam through `transport` and caching responses in `cache`,
+    /// without a call budget
+    pub fn with_backends(
+        api_key: String,
+        transport: Box<dyn SteamTransport>,
+        cache: Box<dyn ResponseCache>,
+    ) -> Self {
         Self {
-            client,
-            api_key: String::new(),
+            transport,
+            cache,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            api_key,
+            quota: SteamQuota::default(),
+            circuits: CircuitBreakers::default(),
         }
     }
 
-    pub async fn get_request<T: Serialize>(
+    pub fn quota(&self) -> &SteamQuota {
+        &self.quota
+    }
+
+    /// Calls a Steam Web API endpoint and returns the data out of its response envelope
+    pub async fn call<E: SteamEndpoint>(&self, endpoint: &E) -> Result<E::Response, SteamError> {
+        let response = self
+            .send_request(
+                &E::url(),
+                endpoint.params().into_pairs(),
+                E::PRIORITY,
+                endpoint.cacheable(),
+            )
+            .await?;
+        E::unwrap_response(response)
+    }
+
+    /// A call to the Steam store, which isn't part of the Web API and has no typed endpoints.
+    /// Store data always has a cached or degraded fallback, so the call is refused once the
+    /// daily budget is spent.
+    pub async fn get_store_request<T: Serialize>(
         &self,
         endpoint: &str,
         query: Vec<(&str, T)>,
     ) -> Result<Value, SteamError> {
+        self.send_request(
+            endpoint,
+            query_strings(query)?,
+            CallPriority::Optional,
+            true,
+        )
+        .await
+    }
+
+    async fn send_request(
+        &self,
+        endpoint: &str,
+        mut query: Vec<(String, String)>,
+        priority: CallPriority,
+        cacheable: bool,
+    ) -> Result<Value, SteamError> {
+        // The key is part of the query string, it must never travel in clear text
+        if !endpoint.starts_with("https://") {
+            return Err(SteamError::FailedRequest(format!(
+                "Refusing to call {endpoint} without HTTPS"
+            )));
+        }
+
+        let cache_key = request_key(endpoint, &query);
+        if cacheable && let Some(response) = self.cache.get(&cache_key) {
+            return Ok(response);
+        }
+
+        let endpoint_key = endpoint_key(endpoint);
+        self.circuits.allow(&endpoint_key)?;
+        self.quota.admit(&endpoint_key, priority)?;
         if self.api_key.is_empty() {
             warn!("Not using a valid API key. Is this on purpose?")
         }
-        let request = self
-            .client
-            .get(endpoint)
-            .query(&[("key", self.api_key.clone())])
-            .query(&query)
-            .build()?;
-
-        log::debug!("New request : {:?}", request);
-
-        let response = self.client.execute(request);
-
-        match response.await {
-            Ok(r) => match r.status() {
-                StatusCode::OK => Ok(r.json().await.unwrap()),  // we trust steam that we'll actually get json w/ a 200 response, so unwrap() is good enough
-                StatusCode::UNAUTHORIZED => {
-                    Err(SteamError::FailedRequest("Unauthorized. Either you have used an invalid API key, or the data you wanted to access is private".to_string()))
+        query.insert(0, ("key".to_owned(), self.api_key.clone()));
+
+        let response = self.transport.get(endpoint, &query).await;
+        match &response {
+            Ok(r) if r.status.is_server_error() || r.status == StatusCode::TOO_MANY_REQUESTS => {
+                self.circuits.record_failure(&endpoint_key)
+            }
+            Ok(_) => self.circuits.record_success(&endpoint_key),
+            Err(_) => self.circuits.record_failure(&endpoint_key),
+        }
+
+        match response? {
+            TransportResponse {
+                status: StatusCode::OK,
+                body,
+            } => {
+                let response: Value =
+                    serde_json::from_str(&body).map_err(|_| SteamError::NoData)?;
+                if cacheable {
+                    self.cache.put(&cache_key, &response, self.cache_ttl);
                 }
-                _ => Err(SteamError::FailedRequest(
-                    "Steam could not process your request. Double-check your provided parameters (Steam ID, app ID, ...).".to_string(),
-                )),
-            },
-            Err(_) => Err(SteamError::FailedRequest(
-                "Something went wrong with your request".to_string(),
+                Ok(response)
+            }
+            TransportResponse {
+                status: StatusCode::UNAUTHORIZED,
+                ..
+            } => Err(SteamError::FailedRequest("Unauthorized. Either you have used an invalid API key, or the data you wanted to access is private".to_string())),
+            _ => Err(SteamError::FailedRequest(
+                "Steam could not process your request. Double-check your provided parameters (Steam ID, app ID, ...).".to_string(),
             )),
         }
     }
@@ -115,3 +238,266 @@ impl SteamClient {
         }
     }
 }
+
+/// Endpoints that take the app id in their path (`appreviews/620`) are counted and guarded
+/// together
+fn endpoint_key(endpoint: &str) -> String {
+    match endpoint.rsplit_once('/') {
+        Some((base, last)) if !last.is_empty() && last.chars().all(|c| c.is_ascii_digit()) => {
+            base.to_owned()
+        }
+        _ => endpoint.to_owned(),
+    }
+}
+
+/// Query parameters as strings, the way they end up in the URL
+fn query_strings<T: Serialize>(query: Vec<(&str, T)>) -> Result<Vec<(String, String)>, SteamError> {
+    query
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match serde_json::to_value(value).map_err(|_| SteamError::NoData)? {
+                Value::String(value) => value,
+                value => value.to_string(),
+            };
+            Ok((name.to_owned(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::steam::steam_cache::NoCache;
+    use crate::steam::steam_endpoint::QueryParams;
+    use crate::steam::steam_quota::QuotaBudget;
+    use crate::steam::steam_transport::{FakeTransport, RecordReplayMode, RecordReplayTransport};
+
+    const OWNED_GAMES: &str = "https://api.steampowered.com/IPlayerService/GetOwnedGames/v1";
+    const NEWS: &str = "https://api.steampowered.com/ISteamNews/GetNewsForApp/v2";
+
+    fn client_with(
+        fake: FakeTransport,
+        cache: Box<dyn ResponseCache>,
+    ) -> (SteamClient, Arc<Mutex<Vec<String>>>) {
+        let requests = fake.requests.clone();
+        let client = SteamClient::with_backends("secret".to_owned(), Box::new(fake), cache);
+        (client, requests)
+    }
+
+    #[tokio::test]
+    async fn reads_a_library_from_the_transport() {
+        let fake = FakeTransport::default();
+        fake.respond(
+            OWNED_GAMES,
+            StatusCode::OK,
+            r#"{"response":{"game_count":1,"games":[{"appid":620,"name":"Portal 2","playtime_forever":90}]}}"#,
+        );
+        let (client, requests) = client_with(fake, Box::new(NoCache));
+
+        let library = client.get_user_library("76561197960287930").await.unwrap();
+
+        assert_eq!(library.game_count, 1);
+        assert_eq!(library.games[0].name, "Portal 2");
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(!requests[0].contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn serves_repeated_requests_from_the_cache() {
+        let fake = FakeTransport::default();
+        fake.respond(
+            OWNED_GAMES,
+            StatusCode::OK,
+            r#"{"response":{"game_count":0}}"#,
+        );
+        let (client, requests) = client_with(fake, Box::new(MemoryCache::default()));
+
+        client.get_user_library("1").await.unwrap();
+        client.get_user_library("1").await.unwrap();
+        client.get_user_library("2").await.unwrap();
+
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn ownership_checks_always_reach_steam() {
+        let fake = FakeTransport::default();
+        fake.respond(
+            OWNED_GAMES,
+            StatusCode::OK,
+            r#"{"response":{"game_count":1,"games":[{"appid":620,"name":"Portal 2","playtime_forever":0}]}}"#,
+        );
+        let (client, requests) = client_with(fake, Box::new(MemoryCache::default()));
+
+        let first = client.get_owned_games_filtered(&[1, 2], &[620]).await;
+        let second = client.get_owned_games_filtered(&[1], &[620]).await;
+        client.get_user_library("1").await.unwrap();
+        client.get_user_library("1").await.unwrap();
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].1.as_ref().unwrap()[0].app_id, 620);
+        assert_eq!(second[0].0, 1);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[0].contains("appids_filter[0]=620"));
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_not_cached() {
+        let fake = FakeTransport::default();
+        fake.respond(OWNED_GAMES, StatusCode::UNAUTHORIZED, "");
+        let (client, requests) = client_with(fake, Box::new(MemoryCache::default()));
+
+        assert!(client.get_user_library("1").await.is_err());
+        assert!(client.get_user_library("1").await.is_err());
+
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn opens_the_circuit_after_repeated_failures() {
+        let fake = FakeTransport::default();
+        fake.respond(OWNED_GAMES, StatusCode::SERVICE_UNAVAILABLE, "");
+        let (client, requests) = client_with(fake, Box::new(NoCache));
+
+        for _ in 0..5 {
+            let error = client.get_user_library("1").await.unwrap_err();
+            assert!(matches!(error, SteamError::FailedRequest(_)));
+        }
+        let error = client.get_user_library("1").await.unwrap_err();
+
+        assert!(matches!(error, SteamError::Unavailable));
+        assert_eq!(requests.lock().unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn private_libraries_leave_the_circuit_closed() {
+        let fake = FakeTransport::default();
+        fake.respond(OWNED_GAMES, StatusCode::OK, r#"{"response":{}}"#);
+        let (client, requests) = client_with(fake, Box::new(NoCache));
+
+        for _ in 0..6 {
+            let error = client.get_user_library("1").await.unwrap_err();
+            assert!(matches!(error, SteamError::NoData));
+            assert!(!error.is_outage());
+        }
+
+        assert_eq!(requests.lock().unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn refuses_optional_calls_past_the_hard_budget() {
+        let fake = FakeTransport::default();
+        fake.respond(
+            OWNED_GAMES,
+            StatusCode::OK,
+            r#"{"response":{"game_count":0}}"#,
+        );
+        fake.respond(NEWS, StatusCode::OK, r#"{"appnews":{"newsitems":[]}}"#);
+        let (mut client, requests) = client_with(fake, Box::new(NoCache));
+        client.quota = SteamQuota::in_memory(QuotaBudget { soft: 1, hard: 1 });
+
+        client.get_user_library("1").await.unwrap();
+        let error = client.get_news_for_app(620, 1).await.unwrap_err();
+        client.get_user_library("2").await.unwrap();
+
+        assert!(matches!(error, SteamError::QuotaExceeded));
+        assert!(client.quota().prefers_cache());
+        assert_eq!(client.quota().usage().total_calls, 2);
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    /// Declared the same way as the real endpoints
+    struct GetTestData {
+        ids: Vec<u64>,
+    }
+
+    impl SteamEndpoint for GetTestData {
+        const INTERFACE: &'static str = "ITest";
+        const METHOD: &'static str = "GetTestData";
+        const VERSION: u32 = 3;
+        type Response = Vec<u64>;
+
+        fn params(&self) -> QueryParams {
+            QueryParams::new()
+                .flag("verbose", false)
+                .list("ids", &self.ids)
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_typed_endpoints_over_https() {
+        let fake = FakeTransport::default();
+        fake.respond(
+            "https://api.steampowered.com/ITest/GetTestData/v3",
+            StatusCode::OK,
+            r#"{"response":[1,2]}"#,
+        );
+        let (client, requests) = client_with(fake, Box::new(NoCache));
+
+        let data = client.call(&GetTestData { ids: vec![4, 5] }).await.unwrap();
+
+        assert_eq!(data, vec![1, 2]);
+        assert_eq!(
+            requests.lock().unwrap()[0],
+            "https://api.steampowered.com/ITest/GetTestData/v3?verbose=0&ids[0]=4&ids[1]=5"
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_envelope_is_no_data() {
+        let fake = FakeTransport::default();
+        fake.respond(
+            "https://api.steampowered.com/ITest/GetTestData/v3",
+            StatusCode::OK,
+            r#"{"something_else":[1,2]}"#,
+        );
+        let (client, _) = client_with(fake, Box::new(NoCache));
+
+        let error = client.call(&GetTestData { ids: vec![] }).await.unwrap_err();
+
+        assert!(matches!(error, SteamError::NoData));
+    }
+
+    #[tokio::test]
+    async fn refuses_plain_http() {
+        let (client, requests) = client_with(FakeTransport::default(), Box::new(NoCache));
+
+        let error = client
+            .get_store_request(
+                "http://store.steampowered.com/api/appdetails",
+                vec![("appids", 620)],
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, SteamError::FailedRequest(_)));
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_responses() {
+        let path = std::env::temp_dir().join(format!("steam-replay-{}.json", std::process::id()));
+        let endpoint = GetTestData { ids: vec![1] };
+        let recorded = serde_json::json!({
+            request_key(&GetTestData::url(), &endpoint.params().into_pairs()): {
+                "status": 200,
+                "body": r#"{"response":[3]}"#,
+            }
+        });
+        std::fs::write(&path, recorded.to_string()).unwrap();
+
+        let transport = RecordReplayTransport::open(path.clone(), RecordReplayMode::Replay);
+        let client =
+            SteamClient::with_backends("secret".to_owned(), Box::new(transport), Box::new(NoCache));
+        let replayed = client.call(&endpoint).await;
+        let missing = client.call(&GetTestData { ids: vec![2] }).await;
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(replayed.unwrap(), vec![3]);
+        assert!(matches!(missing, Err(SteamError::FailedRequest(_))));
+    }
+}
@@ -0,0 +1,113 @@
+//! Typed description of Steam Web API endpoints.
+//!
+//! An endpoint is a struct holding its request parameters, implementing `SteamEndpoint` with
+//! its interface, method, version and response type. `SteamClient::call` builds the HTTPS URL,
+//! injects the API key and unwraps the response envelope, so adding an endpoint is mostly a
+//! matter of declaring it:
+//!
+//! ```ignore
+//! struct GetPlayerSummaries { steam_ids: Vec<u64> }
+//!
+//! impl SteamEndpoint for GetPlayerSummaries {
+//!     const INTERFACE: &'static str = "ISteamUser";
+//!     const METHOD: &'static str = "GetPlayerSummaries";
+//!     const VERSION: u32 = 2;
+//!     type Response = PlayerSummaries;
+//!
+//!     fn params(&self) -> QueryParams {
+//!         QueryParams::new().list_joined("steamids", &self.steam_ids)
+//!     }
+//! }
+//! ```
+
+use std::fmt::Display;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::steam::steam_client::SteamError;
+use crate::steam::steam_quota::CallPriority;
+
+const WEB_API_HOST: &str = "https://api.steampowered.com";
+
+pub trait SteamEndpoint {
+    /// "IPlayerService", "ISteamUser", ...
+    const INTERFACE: &'static str;
+    /// "GetOwnedGames", "GetPlayerSummaries", ...
+    const METHOD: &'static str;
+    const VERSION: u32;
+    /// The field of the JSON response holding the actual data. Most interfaces use
+    /// `response`, older ones name it after the data (`applist`, `appnews`).
+    const ENVELOPE: &'static str = "response";
+    const PRIORITY: CallPriority = CallPriority::Essential;
+
+    type Response: DeserializeOwned;
+
+    fn params(&self) -> QueryParams;
+
+    /// Whether this request may be answered from the response cache, and its response kept
+    /// there
+    fn cacheable(&self) -> bool {
+        true
+    }
+
+    fn url() -> String {
+        format!(
+            "{WEB_API_HOST}/{}/{}/v{}",
+            Self::INTERFACE,
+            Self::METHOD,
+            Self::VERSION
+        )
+    }
+
+    /// Takes the data out of its envelope. A missing envelope or unexpected data is
+    /// `SteamError::NoData`, which is what Steam sends for private profiles.
+    fn unwrap_response(mut response: Value) -> Result<Self::Response, SteamError> {
+        let data = response
+            .get_mut(Self::ENVELOPE)
+            .map(Value::take)
+            .ok_or(SteamError::NoData)?;
+        serde_json::from_value(data).map_err(|_| SteamError::NoData)
+    }
+}
+
+/// Request parameters in the shape the Web API expects them
+#[derive(Debug, Clone, Default)]
+pub struct QueryParams(Vec<(String, String)>);
+
+impl QueryParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn param(mut self, name: &str, value: impl Display) -> Self {
+        self.0.push((name.to_owned(), value.to_string()));
+        self
+    }
+
+    /// Booleans are sent as `1` or `0`
+    pub fn flag(self, name: &str, value: bool) -> Self {
+        self.param(name, u8::from(value))
+    }
+
+    /// Array parameters are indexed: `appids_filter[0]=620&appids_filter[1]=730`
+    pub fn list<T: Display>(mut self, name: &str, values: &[T]) -> Self {
+        self.0.extend(
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (format!("{name}[{i}]"), value.to_string())),
+        );
+        self
+    }
+
+    /// Some older methods take a single comma separated parameter instead
+    pub fn list_joined<T: Display>(self, name: &str, values: &[T]) -> Self {
+        let joined: Vec<String> = values.iter().map(ToString::to_string).collect();
+        self.param(name, joined.join(","))
+    }
+
+    pub fn into_pairs(self) -> Vec<(String, String)> {
+        self.0
+    }
+}
@@ -0,0 +1,322 @@
+//! This module builds a user's games library from the files the Steam client keeps on disk.
+//! It is used for users whose profile is private, where GetOwnedGames returns nothing.
+
+use std::collections::BTreeMap;
+
+use library::{Customer, Game, LocalLibraryUpload};
+
+use crate::steam::key_values::{self, KeyValue};
+use crate::steam::steam_client::SteamError;
+
+/// `StateFlags` bit set by Steam once every file of an app is on disk
+const STATE_FULLY_INSTALLED: u32 = 4;
+
+/// The size of every app installed in a Steam library folder, from `libraryfolders.vdf`
+#[derive(Debug, Default)]
+pub struct LocalLibraryFolder {
+    pub apps: BTreeMap<u64, u64>,
+}
+
+/// An installed (or installing) app, from `appmanifest_<appid>.acf`
+#[derive(Debug)]
+pub struct LocalAppManifest {
+    pub app_id: u64,
+    pub name: String,
+    pub size_on_disk: u64,
+    pub fully_installed: bool,
+}
+
+/// Playtime Steam tracks locally per app, from `localconfig.vdf`
+#[derive(Debug, Default)]
+pub struct LocalPlaytime {
+    pub playtime_minutes: u64,
+}
+
+pub fn parse_library_folders(text: &str) -> Result<Vec<LocalLibraryFolder>, SteamError> {
+    let root = key_values::parse(text)?;
+    let folders = root
+        .get("libraryfolders")
+        .ok_or_else(|| SteamError::MalformedFile("missing `libraryfolders` section".to_owned()))?;
+
+    let folders = folders
+        .entries()
+        .iter()
+        // Older files also carry plain values such as "contentstatsid" next to the folders
+        .filter(|(_, folder)| matches!(folder, KeyValue::Section(_)))
+        .map(|(_, folder)| LocalLibraryFolder {
+            apps: folder
+                .get("apps")
+                .map(|apps| {
+                    apps.entries()
+                        .iter()
+                        .filter_map(|(app_id, size)| {
+                            Some((app_id.parse().ok()?, size.as_str()?.parse().ok()?))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(folders)
+}
+
+pub fn parse_app_manifest(text: &str) -> Result<LocalAppManifest, SteamError> {
+    let root = key_values::parse(text)?;
+    let state = root
+        .get("AppState")
+        .ok_or_else(|| SteamError::MalformedFile("missing `AppState` section".to_owned()))?;
+
+    let app_id = state
+        .parse_value("appid")
+        .ok_or_else(|| SteamError::MalformedFile("app manifest without `appid`".to_owned()))?;
+    let state_flags: u32 = state.parse_value("StateFlags").unwrap_or_default();
+
+    Ok(LocalAppManifest {
+        app_id,
+        name: state
+            .get("name")
+            .and_then(KeyValue::as_str)
+            .unwrap_or_default()
+            .to_owned(),
+        size_on_disk: state.parse_value("SizeOnDisk").unwrap_or_default(),
+        fully_installed: state_flags & STATE_FULLY_INSTALLED != 0,
+    })
+}
+
+pub fn parse_local_config(text: &str) -> Result<BTreeMap<u64, LocalPlaytime>, SteamError> {
+    let root = key_values::parse(text)?;
+    let Some(apps) = root.path(&["UserLocalConfigStore", "Software", "Valve", "Steam", "apps"])
+    else {
+        return Ok(BTreeMap::new());
+    };
+
+    let playtimes = apps
+        .entries()
+        .iter()
+        .filter_map(|(app_id, app)| {
+            let playtime = LocalPlaytime {
+                playtime_minutes: app.parse_value("Playtime").unwrap_or_default(),
+            };
+            Some((app_id.parse().ok()?, playtime))
+        })
+        .collect();
+
+    Ok(playtimes)
+}
+
+/// Builds a `Customer` out of the uploaded files. Apps only known from `localconfig.vdf`
+/// are owned but not installed; manifests and library folders provide the install state
+/// and size on disk.
+pub fn customer_from_local_files(upload: LocalLibraryUpload) -> Result<Customer, SteamError> {
+    let mut games: BTreeMap<u64, Game> = BTreeMap::new();
+
+    if let Some(local_config) = &upload.local_config {
+        for (app_id, playtime) in parse_local_config(local_config)? {
+            let game = games.entry(app_id).or_insert_with(|| unnamed_game(app_id));
+            game.playtime_minutes = playtime.playtime_minutes;
+        }
+    }
+
+    if let Some(library_folders) = &upload.library_folders {
+        for folder in parse_library_folders(library_folders)? {
+            for (app_id, size) in folder.apps {
+                let game = games.entry(app_id).or_insert_with(|| unnamed_game(app_id));
+                game.installed = true;
+                game.size_on_disk = Some(size);
+            }
+        }
+    }
+
+    for manifest in &upload.app_manifests {
+        let manifest = parse_app_manifest(manifest)?;
+        let game = games
+            .entry(manifest.app_id)
+            .or_insert_with(|| unnamed_game(manifest.app_id));
+
+        if !manifest.name.is_empty() {
+            game.name = manifest.name;
+        }
+        game.installed = manifest.fully_installed;
+        game.size_on_disk = Some(manifest.size_on_disk);
+    }
+
+    log::info!(
+        "Local library for {} built with {} games",
+        upload.steam_name,
+        games.len()
+    );
+
+    Ok(Customer {
+        steam_name: upload.steam_name,
+        steam_id: upload.steam_id,
+        games: games.into_values().collect(),
+        ..Default::default()
+    })
+}
+
+/// Name given to apps we only know by id, until their metadata is fetched
+pub fn placeholder_name(app_id: u64) -> String {
+    format!("App {app_id}")
+}
+
+fn unnamed_game(app_id: u64) -> Game {
+    Game {
+        id: 0,
+        app_id,
+        name: placeholder_name(app_id),
+        playtime_minutes: 0,
+        installed: false,
+        size_on_disk: None,
+        platform_playtime: Default::default(),
+        app_type: Default::default(),
+        dlc: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Trimmed from real Steam client files
+    const LIBRARY_FOLDERS: &str = r#"
+"libraryfolders"
+{
+	"contentstatsid"		"-4429215796543214245"
+	"0"
+	{
+		"path"		"C:\\Program Files (x86)\\Steam"
+		"label"		""
+		"contentid"		"4428394563223474410"
+		"totalsize"		"0"
+		"apps"
+		{
+			"228980"		"153560151"
+			"620"		"12968423748"
+		}
+	}
+	"1"
+	{
+		"path"		"D:\\SteamLibrary"
+		"apps"
+		{
+			"570"		"38104593471"
+		}
+	}
+}
+"#;
+
+    const APP_MANIFEST: &str = r#"
+"AppState"
+{
+	"appid"		"620"
+	"Universe"		"1"
+	"name"		"Portal 2"
+	"StateFlags"		"4"
+	"installdir"		"Portal 2"
+	"SizeOnDisk"		"12968423748"
+	"InstalledDepots"
+	{
+		"621"
+		{
+			"manifest"		"4253148862429478153"
+			"size"		"12967871092"
+		}
+	}
+}
+"#;
+
+    const UPDATING_MANIFEST: &str = r#"
+"AppState"
+{
+	"appid"		"570"
+	"name"		"Dota 2"
+	"StateFlags"		"1026"
+	"SizeOnDisk"		"0"
+}
+"#;
+
+    const LOCAL_CONFIG: &str = r#"
+"UserLocalConfigStore"
+{
+	"software"
+	{
+		"valve"
+		{
+			"Steam"
+			{
+				"apps"
+				{
+					"620"
+					{
+						"LastPlayed"		"1700000000"
+						"Playtime"		"754"
+					}
+					"730"
+					{
+						"Playtime"		"12"
+					}
+				}
+			}
+		}
+	}
+}
+"#;
+
+    #[test]
+    fn reads_library_folders() {
+        let folders = parse_library_folders(LIBRARY_FOLDERS).unwrap();
+
+        assert_eq!(folders.len(), 2);
+        assert_eq!(folders[0].apps.get(&620), Some(&12968423748));
+        assert_eq!(folders[1].apps.get(&570), Some(&38104593471));
+        assert!(matches!(
+            parse_library_folders("\"other\" {}"),
+            Err(SteamError::MalformedFile(_))
+        ));
+    }
+
+    #[test]
+    fn reads_app_manifests() {
+        let manifest = parse_app_manifest(APP_MANIFEST).unwrap();
+        assert_eq!(manifest.app_id, 620);
+        assert_eq!(manifest.name, "Portal 2");
+        assert_eq!(manifest.size_on_disk, 12968423748);
+        assert!(manifest.fully_installed);
+
+        assert!(
+            !parse_app_manifest(UPDATING_MANIFEST)
+                .unwrap()
+                .fully_installed
+        );
+        assert!(matches!(
+            parse_app_manifest("\"AppState\" { \"name\" \"No id\" }"),
+            Err(SteamError::MalformedFile(_))
+        ));
+    }
+
+    #[test]
+    fn builds_a_customer_from_every_file() {
+        let customer = customer_from_local_files(LocalLibraryUpload {
+            steam_name: "Alice".to_owned(),
+            steam_id: Some(76561197960287930),
+            library_folders: Some(LIBRARY_FOLDERS.to_owned()),
+            app_manifests: vec![APP_MANIFEST.to_owned(), UPDATING_MANIFEST.to_owned()],
+            local_config: Some(LOCAL_CONFIG.to_owned()),
+        })
+        .unwrap();
+        let game = |app_id| customer.games.iter().find(|g| g.app_id == app_id).unwrap();
+
+        assert_eq!(customer.games.len(), 4);
+        assert_eq!(game(620).name, "Portal 2");
+        assert_eq!(game(620).playtime_minutes, 754);
+        assert!(game(620).installed);
+        // Listed in a library folder, but its manifest says it's still updating
+        assert!(!game(570).installed);
+        assert_eq!(game(570).name, "Dota 2");
+        // Only played, never installed here
+        assert!(!game(730).installed);
+        assert_eq!(game(730).name, placeholder_name(730));
+        assert_eq!(game(228980).size_on_disk, Some(153560151));
+    }
+}
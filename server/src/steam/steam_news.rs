@@ -0,0 +1,74 @@
+//! This module deals with the news feed of an app (announcements, patch notes, press).
+
+use library::NewsHeadline;
+use serde::Deserialize;
+
+use crate::steam::steam_client::SteamClient;
+use crate::steam::steam_client::SteamError;
+use crate::steam::steam_endpoint::{QueryParams, SteamEndpoint};
+use crate::steam::steam_quota::CallPriority;
+
+/// Only the headline is shown, so the body is cut short by Steam
+const NEWS_MAX_LENGTH: u32 = 200;
+
+/// The Steam API "GetNewsForApp (v0002)" endpoint
+struct GetNewsForApp {
+    app_id: u64,
+    count: u32,
+}
+
+impl SteamEndpoint for GetNewsForApp {
+    const INTERFACE: &'static str = "ISteamNews";
+    const METHOD: &'static str = "GetNewsForApp";
+    const VERSION: u32 = 2;
+    const ENVELOPE: &'static str = "appnews";
+    const PRIORITY: CallPriority = CallPriority::Optional;
+    type Response = AppNews;
+
+    fn params(&self) -> QueryParams {
+        QueryParams::new()
+            .param("appid", self.app_id)
+            .param("count", self.count)
+            .param("maxlength", NEWS_MAX_LENGTH)
+    }
+}
+
+/// Helper struct used during deserializing the API response.
+#[derive(Debug, Default, Deserialize)]
+struct AppNews {
+    #[serde(default)]
+    newsitems: Vec<SteamNewsItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SteamNewsItem {
+    pub title: String,
+    pub url: String,
+    /// Unix timestamp
+    pub date: u64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl From<SteamNewsItem> for NewsHeadline {
+    fn from(item: SteamNewsItem) -> Self {
+        NewsHeadline {
+            is_patch_notes: item.tags.iter().any(|tag| tag == "patchnotes"),
+            title: item.title,
+            url: item.url,
+            date: item.date,
+        }
+    }
+}
+
+impl SteamClient {
+    /// Returns the `count` most recent news items of an app, newest first
+    pub async fn get_news_for_app(
+        &self,
+        app_id: u64,
+        count: u32,
+    ) -> Result<Vec<SteamNewsItem>, SteamError> {
+        let news = self.call(&GetNewsForApp { app_id, count }).await?;
+        Ok(news.newsitems)
+    }
+}
@@ -0,0 +1,385 @@
+//! "Sign in through Steam", which is plain OpenID 2.0 with Steam as the only provider.
+//!
+//! The user is sent to the provider with `login_url` and comes back to our `return_to` URL
+//! with a positive assertion in the query. `verify` checks that the assertion is for us, names
+//! a Steam account and is fresh, then asks the provider to confirm its signature
+//! (`check_authentication`), so no association or shared secret is needed.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Mutex;
+
+use thiserror::Error;
+
+use crate::unix_now;
+
+pub const STEAM_OPENID_PROVIDER: &str = "https://steamcommunity.com/openid/login";
+
+const OPENID_NS: &str = "http://specs.openid.net/auth/2.0";
+const IDENTIFIER_SELECT: &str = "http://specs.openid.net/auth/2.0/identifier_select";
+const CLAIMED_ID_PREFIX: &str = "https://steamcommunity.com/openid/id/";
+
+/// Fields an assertion must have signed, or they could be swapped for another account's
+const REQUIRED_SIGNED_FIELDS: [&str; 6] = [
+    "op_endpoint",
+    "claimed_id",
+    "identity",
+    "return_to",
+    "response_nonce",
+    "assoc_handle",
+];
+
+/// Assertions older than this are refused, newer ones are accepted only once
+const NONCE_MAX_AGE_SECS: u64 = 5 * 60;
+
+#[derive(Debug, Error)]
+pub enum OpenIdError {
+    #[error("Sign in was cancelled")]
+    Cancelled,
+    #[error("Not a positive assertion")]
+    NotAnAssertion,
+    #[error("Missing or invalid openid.{0}")]
+    Malformed(&'static str),
+    #[error("openid.{0} isn't signed")]
+    Unsigned(&'static str),
+    #[error("The assertion comes from another provider")]
+    WrongProvider,
+    #[error("The assertion was made for another site")]
+    WrongReturnTo,
+    #[error("The claimed identity isn't a Steam account")]
+    NotASteamAccount,
+    #[error("The assertion has expired")]
+    Expired,
+    #[error("The assertion was already used")]
+    Replayed,
+    #[error("The provider didn't confirm the assertion")]
+    Rejected,
+    #[error("Can't reach the provider: {0}")]
+    Unreachable(#[from] reqwest::Error),
+}
+
+pub struct SteamOpenId {
+    provider: String,
+    http: reqwest::Client,
+    /// Nonces of accepted assertions, with their time, until they expire
+    used_nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl SteamOpenId {
+    /// `provider` is Steam's OpenID endpoint, `STEAM_OPENID_PROVIDER`, outside of tests
+    pub fn new(provider: String) -> Self {
+        Self {
+            provider,
+            http: reqwest::Client::new(),
+            used_nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Where to send the user to sign in. The provider sends them back to `return_to`, which
+    /// must be under `realm`.
+    pub fn login_url(&self, return_to: &str, realm: &str) -> String {
+        let params = [
+            ("openid.ns", OPENID_NS),
+            ("openid.mode", "checkid_setup"),
+            ("openid.return_to", return_to),
+            ("openid.realm", realm),
+            ("openid.identity", IDENTIFIER_SELECT),
+            ("openid.claimed_id", IDENTIFIER_SELECT),
+        ];
+        let query: Vec<String> = params
+            .iter()
+            .map(|(name, value)| format!("{name}={}", urlencoding::encode(value)))
+            .collect();
+        format!("{}?{}", self.provider, query.join("&"))
+    }
+
+    /// Checks the assertion the provider sent back to `return_to`, and returns the SteamID64
+    /// it vouches for
+    pub async fn verify(
+        &self,
+        params: &HashMap<String, String>,
+        return_to: &str,
+    ) -> Result<u64, OpenIdError> {
+        let get = |name: &str| params.get(&format!("openid.{name}")).map(String::as_str);
+
+        match get("mode") {
+            Some("id_res") => {}
+            Some("cancel") => return Err(OpenIdError::Cancelled),
+            _ => return Err(OpenIdError::NotAnAssertion),
+        }
+        if get("ns") != Some(OPENID_NS) {
+            return Err(OpenIdError::Malformed("ns"));
+        }
+        if get("op_endpoint") != Some(self.provider.as_str()) {
+            return Err(OpenIdError::WrongProvider);
+        }
+        if get("return_to") != Some(return_to) {
+            return Err(OpenIdError::WrongReturnTo);
+        }
+
+        let signed: Vec<&str> = get("signed")
+            .ok_or(OpenIdError::Malformed("signed"))?
+            .split(',')
+            .collect();
+        if let Some(field) = REQUIRED_SIGNED_FIELDS
+            .into_iter()
+            .find(|field| !signed.contains(field))
+        {
+            return Err(OpenIdError::Unsigned(field));
+        }
+
+        let claimed_id = get("claimed_id").ok_or(OpenIdError::Malformed("claimed_id"))?;
+        if get("identity") != Some(claimed_id) {
+            return Err(OpenIdError::Malformed("identity"));
+        }
+        let steam_id = claimed_id
+            .strip_prefix(CLAIMED_ID_PREFIX)
+            .and_then(|id| id.parse().ok())
+            .ok_or(OpenIdError::NotASteamAccount)?;
+
+        let nonce = get("response_nonce").ok_or(OpenIdError::Malformed("response_nonce"))?;
+        let issued_at = nonce_time(nonce).ok_or(OpenIdError::Malformed("response_nonce"))?;
+        if unix_now().saturating_sub(issued_at) > NONCE_MAX_AGE_SECS {
+            return Err(OpenIdError::Expired);
+        }
+
+        self.check_authentication(params).await?;
+        self.use_nonce(nonce, issued_at)?;
+        Ok(steam_id)
+    }
+
+    /// Asks the provider whether it really signed the assertion
+    async fn check_authentication(
+        &self,
+        params: &HashMap<String, String>,
+    ) -> Result<(), OpenIdError> {
+        let form: Vec<(&str, &str)> = params
+            .iter()
+            .filter(|(name, _)| name.starts_with("openid.") && *name != "openid.mode")
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .chain([("openid.mode", "check_authentication")])
+            .collect();
+
+        let response = self.http.post(&self.provider).form(&form).send().await?;
+        let body = response.text().await?;
+        // Key-value form: one `key:value` per line
+        if body.lines().any(|line| line.trim() == "is_valid:true") {
+            Ok(())
+        } else {
+            Err(OpenIdError::Rejected)
+        }
+    }
+
+    fn use_nonce(&self, nonce: &str, issued_at: u64) -> Result<(), OpenIdError> {
+        let now = unix_now();
+        let mut used_nonces = self.used_nonces.lock().unwrap();
+        used_nonces.retain(|_, issued_at| now.saturating_sub(*issued_at) <= NONCE_MAX_AGE_SECS);
+        if used_nonces.insert(nonce.to_owned(), issued_at).is_some() {
+            return Err(OpenIdError::Replayed);
+        }
+        Ok(())
+    }
+}
+
+/// The Unix time a nonce was issued at. Nonces start with a UTC timestamp such as
+/// `2024-05-01T20:15:00Z`, followed by a unique suffix.
+fn nonce_time(nonce: &str) -> Option<u64> {
+    let number = |range: Range<usize>| nonce.get(range)?.parse::<i64>().ok();
+    if nonce.get(19..20) != Some("Z") {
+        return None;
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) = (number(11..13)?, number(14..16)?, number(17..19)?);
+
+    // Days since the epoch in the proleptic Gregorian calendar, years starting in March
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    u64::try_from(days * 86_400 + hour * 3_600 + minute * 60 + second).ok()
+}
+
+/// A nonce issued now, as a provider would make it
+#[cfg(test)]
+pub fn fresh_nonce(suffix: &str) -> String {
+    let now = unix_now();
+    let (days, secs) = (now / 86_400, now % 86_400);
+    // Inverse of the computation in `nonce_time`
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z{suffix}",
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+/// A stand-in for Steam's provider, confirming assertions whose signature is `valid`
+#[cfg(test)]
+pub mod test_provider {
+    use std::collections::HashMap;
+
+    use axum::{Form, Router, routing::post};
+
+    /// Serves the provider on a free local port and returns its endpoint
+    pub async fn start() -> String {
+        async fn check_authentication(Form(form): Form<HashMap<String, String>>) -> String {
+            let valid = form.get("openid.mode").map(String::as_str) == Some("check_authentication")
+                && form.get("openid.sig").map(String::as_str) == Some("valid");
+            format!("ns:{}\nis_valid:{valid}\n", super::OPENID_NS)
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/openid/login", listener.local_addr().unwrap());
+        let app = Router::new().route("/openid/login", post(check_authentication));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        endpoint
+    }
+
+    /// The query a provider sends back after the user signed in as `steam_id`
+    pub fn assertion(
+        provider: &str,
+        return_to: &str,
+        steam_id: u64,
+        nonce: &str,
+        sig: &str,
+    ) -> HashMap<String, String> {
+        let claimed_id = format!("{}{steam_id}", super::CLAIMED_ID_PREFIX);
+        [
+            ("openid.ns", super::OPENID_NS),
+            ("openid.mode", "id_res"),
+            ("openid.op_endpoint", provider),
+            ("openid.claimed_id", &claimed_id),
+            ("openid.identity", &claimed_id),
+            ("openid.return_to", return_to),
+            ("openid.response_nonce", nonce),
+            ("openid.assoc_handle", "1234567890"),
+            (
+                "openid.signed",
+                "signed,op_endpoint,claimed_id,identity,return_to,response_nonce,assoc_handle",
+            ),
+            ("openid.sig", sig),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_provider::{assertion, start};
+    use super::*;
+
+    const RETURN_TO: &str = "http://127.0.0.1:3000/api/auth/steam/callback";
+    const STEAM_ID: u64 = 76561197960287930;
+
+    #[test]
+    fn nonces_carry_their_issue_time() {
+        assert_eq!(nonce_time("1970-01-01T00:00:00Zabc"), Some(0));
+        assert_eq!(nonce_time("2024-02-29T12:30:15Z0x"), Some(1_709_209_815));
+        assert_eq!(nonce_time("yesterday"), None);
+        assert_eq!(
+            nonce_time(&fresh_nonce("x")).map(|t| t / 60),
+            Some(unix_now() / 60)
+        );
+    }
+
+    #[test]
+    fn login_url_asks_the_provider_to_pick_the_identity() {
+        let openid = SteamOpenId::new(STEAM_OPENID_PROVIDER.to_owned());
+        let url = openid.login_url(RETURN_TO, "http://127.0.0.1:3000/");
+
+        assert!(url.starts_with("https://steamcommunity.com/openid/login?"));
+        assert!(url.contains("openid.mode=checkid_setup"));
+        assert!(url.contains(&format!(
+            "openid.return_to={}",
+            urlencoding::encode(RETURN_TO)
+        )));
+    }
+
+    #[tokio::test]
+    async fn confirmed_assertions_are_accepted_once() {
+        let provider = start().await;
+        let openid = SteamOpenId::new(provider.clone());
+        let params = assertion(&provider, RETURN_TO, STEAM_ID, &fresh_nonce("a"), "valid");
+
+        assert_eq!(openid.verify(&params, RETURN_TO).await.unwrap(), STEAM_ID);
+        assert!(matches!(
+            openid.verify(&params, RETURN_TO).await,
+            Err(OpenIdError::Replayed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn forged_assertions_are_refused() {
+        let provider = start().await;
+        let openid = SteamOpenId::new(provider.clone());
+        let nonce = fresh_nonce("b");
+
+        let forged = assertion(&provider, RETURN_TO, STEAM_ID, &nonce, "forged");
+        assert!(matches!(
+            openid.verify(&forged, RETURN_TO).await,
+            Err(OpenIdError::Rejected)
+        ));
+
+        let elsewhere = assertion(
+            &provider,
+            "https://evil.example/",
+            STEAM_ID,
+            &nonce,
+            "valid",
+        );
+        assert!(matches!(
+            openid.verify(&elsewhere, RETURN_TO).await,
+            Err(OpenIdError::WrongReturnTo)
+        ));
+
+        let other_provider = assertion(
+            "https://evil.example/openid",
+            RETURN_TO,
+            STEAM_ID,
+            &nonce,
+            "valid",
+        );
+        assert!(matches!(
+            openid.verify(&other_provider, RETURN_TO).await,
+            Err(OpenIdError::WrongProvider)
+        ));
+
+        let old = assertion(
+            &provider,
+            RETURN_TO,
+            STEAM_ID,
+            "2020-01-01T00:00:00Zold",
+            "valid",
+        );
+        assert!(matches!(
+            openid.verify(&old, RETURN_TO).await,
+            Err(OpenIdError::Expired)
+        ));
+
+        let mut unsigned = assertion(&provider, RETURN_TO, STEAM_ID, &nonce, "valid");
+        unsigned.insert("openid.signed".to_owned(), "signed,op_endpoint".to_owned());
+        assert!(matches!(
+            openid.verify(&unsigned, RETURN_TO).await,
+            Err(OpenIdError::Unsigned("claimed_id"))
+        ));
+    }
+}
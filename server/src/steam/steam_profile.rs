@@ -0,0 +1,52 @@
+//! This module deals with a user's public Steam profile.
+
+use serde::Deserialize;
+
+use crate::steam::steam_client::SteamClient;
+use crate::steam::steam_client::SteamError;
+use crate::steam::steam_endpoint::{QueryParams, SteamEndpoint};
+
+/// The Steam API "GetPlayerSummaries (v0002)" endpoint
+struct GetPlayerSummaries<'a> {
+    steam_ids: &'a [&'a str],
+}
+
+impl SteamEndpoint for GetPlayerSummaries<'_> {
+    const INTERFACE: &'static str = "ISteamUser";
+    const METHOD: &'static str = "GetPlayerSummaries";
+    const VERSION: u32 = 2;
+    type Response = PlayerSummaries;
+
+    fn params(&self) -> QueryParams {
+        QueryParams::new().list_joined("steamids", self.steam_ids)
+    }
+}
+
+/// Helper struct used during deserializing the API response.
+#[derive(Debug, Deserialize)]
+struct PlayerSummaries {
+    players: Vec<PlayerSummary>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayerSummary {
+    #[serde(rename(deserialize = "personaname"))]
+    pub persona_name: String,
+}
+
+impl SteamClient {
+    pub async fn get_player_summary(&self, steam_id: &str) -> Result<PlayerSummary, SteamError> {
+        let steam_ids = [steam_id];
+        let summaries = self
+            .call(&GetPlayerSummaries {
+                steam_ids: &steam_ids,
+            })
+            .await?;
+
+        summaries
+            .players
+            .into_iter()
+            .next()
+            .ok_or(SteamError::NoData)
+    }
+}
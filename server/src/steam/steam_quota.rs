@@ -0,0 +1,253 @@
+//! Daily accounting of the calls made to Steam, so a busy evening can't burn the API key's
+//! budget.
+//!
+//! Every call is counted by endpoint and the totals are written to a small JSON file every
+//! few seconds and on shutdown, so a restart doesn't reset them. Past the soft budget the
+//! server keeps serving cached data even when it's out of date, and past the hard budget only
+//! essential calls (reading a user's library) still go through.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use library::{EndpointUsage, SteamUsageResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::steam::steam_client::{SteamClient, SteamError};
+
+/// Steam allows 100 000 Web API calls per key and day, leave some room for other tools
+pub const DEFAULT_SOFT_BUDGET: u64 = 50_000;
+pub const DEFAULT_HARD_BUDGET: u64 = 90_000;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How often counted calls are written to the usage file
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Whether a call can be refused once the hard budget is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallPriority {
+    /// Needed to answer the user at all, such as reading their library
+    Essential,
+    /// Metadata, news, reviews and catalogs, which have a cached or degraded fallback
+    Optional,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaBudget {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+/// What gets persisted: the calls of a single UTC day
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DailyUsage {
+    /// Days since the Unix epoch
+    day: u64,
+    calls: BTreeMap<String, u64>,
+}
+
+impl DailyUsage {
+    fn total(&self) -> u64 {
+        self.calls.values().sum()
+    }
+}
+
+#[derive(Debug)]
+pub struct SteamQuota {
+    budget: QuotaBudget,
+    path: Option<PathBuf>,
+    usage: Mutex<DailyUsage>,
+    /// Calls were counted since the usage file was last written
+    dirty: AtomicBool,
+}
+
+impl SteamQuota {
+    /// Loads today's totals from `path` if present
+    pub fn load(path: PathBuf, budget: QuotaBudget) -> Self {
+        let usage = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Self {
+            budget,
+            path: Some(path),
+            usage: Mutex::new(usage),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Totals that are lost on restart
+    pub fn in_memory(budget: QuotaBudget) -> Self {
+        Self {
+            budget,
+            path: None,
+            usage: Mutex::new(DailyUsage::default()),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Counts a call to `endpoint`, or refuses it when the hard budget is spent and the call
+    /// isn't essential. Refused calls aren't counted.
+    pub fn admit(&self, endpoint: &str, priority: CallPriority) -> Result<(), SteamError> {
+        let mut usage = self.usage.lock().unwrap();
+        roll_over(&mut usage);
+
+        let total = usage.total();
+        if priority == CallPriority::Optional && total >= self.budget.hard {
+            return Err(SteamError::QuotaExceeded);
+        }
+        if total + 1 == self.budget.soft {
+            log::warn!("Soft Steam API budget reached, preferring cached data for today");
+        }
+
+        *usage.calls.entry(endpoint.to_owned()).or_default() += 1;
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Past the soft budget, out of date cache entries are kept rather than refreshed
+    pub fn prefers_cache(&self) -> bool {
+        let mut usage = self.usage.lock().unwrap();
+        roll_over(&mut usage);
+        usage.total() >= self.budget.soft
+    }
+
+    pub fn usage(&self) -> SteamUsageResponse {
+        let mut usage = self.usage.lock().unwrap();
+        roll_over(&mut usage);
+        SteamUsageResponse {
+            day_start: usage.day * SECONDS_PER_DAY,
+            total_calls: usage.total(),
+            soft_budget: self.budget.soft,
+            hard_budget: self.budget.hard,
+            endpoints: usage
+                .calls
+                .iter()
+                .map(|(endpoint, calls)| EndpointUsage {
+                    endpoint: endpoint.clone(),
+                    calls: *calls,
+                })
+                .collect(),
+        }
+    }
+
+    /// Writes the totals to the usage file if calls were counted since the last time. This
+    /// blocks on the file system, so call it from `spawn_blocking` on the runtime.
+    pub fn flush(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let usage = self.usage.lock().unwrap().clone();
+        let written = serde_json::to_string(&usage)
+            .map_err(|error| error.to_string())
+            .and_then(|json| std::fs::write(path, json).map_err(|error| error.to_string()));
+        if let Err(error) = written {
+            log::warn!("Can't save Steam API usage to {}: {error}", path.display());
+        }
+    }
+}
+
+impl Default for SteamQuota {
+    /// Unlimited and in memory only
+    fn default() -> Self {
+        Self::in_memory(QuotaBudget {
+            soft: u64::MAX,
+            hard: u64::MAX,
+        })
+    }
+}
+
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs() / SECONDS_PER_DAY)
+}
+
+/// Writes the usage file every `FLUSH_INTERVAL` for as long as the server runs
+pub fn spawn_usage_flush(steam_client: Arc<SteamClient>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let steam_client = steam_client.clone();
+            if let Err(error) =
+                tokio::task::spawn_blocking(move || steam_client.quota().flush()).await
+            {
+                log::error!("Saving Steam API usage failed: {error}");
+            }
+        }
+    });
+}
+
+/// Starts a new count at UTC midnight
+fn roll_over(usage: &mut DailyUsage) {
+    let today = today();
+    if usage.day != today {
+        *usage = DailyUsage {
+            day: today,
+            calls: BTreeMap::new(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUDGET: QuotaBudget = QuotaBudget { soft: 2, hard: 3 };
+
+    #[test]
+    fn budgets_first_prefer_the_cache_then_refuse_optional_calls() {
+        let quota = SteamQuota::in_memory(BUDGET);
+
+        quota.admit("news", CallPriority::Optional).unwrap();
+        assert!(!quota.prefers_cache());
+        quota.admit("news", CallPriority::Optional).unwrap();
+        assert!(quota.prefers_cache());
+        quota.admit("reviews", CallPriority::Optional).unwrap();
+
+        assert!(matches!(
+            quota.admit("news", CallPriority::Optional),
+            Err(SteamError::QuotaExceeded)
+        ));
+        quota.admit("library", CallPriority::Essential).unwrap();
+
+        let usage = quota.usage();
+        assert_eq!(usage.total_calls, 4);
+        let calls: Vec<(&str, u64)> = usage
+            .endpoints
+            .iter()
+            .map(|e| (e.endpoint.as_str(), e.calls))
+            .collect();
+        assert_eq!(calls, [("library", 1), ("news", 2), ("reviews", 1)]);
+    }
+
+    #[test]
+    fn totals_survive_a_restart_once_flushed() {
+        let path = std::env::temp_dir().join(format!("steam-usage-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let quota = SteamQuota::load(path.clone(), BUDGET);
+        quota.admit("news", CallPriority::Optional).unwrap();
+        assert!(!path.exists());
+        quota.flush();
+        assert_eq!(
+            SteamQuota::load(path.clone(), BUDGET).usage().total_calls,
+            1
+        );
+
+        // Yesterday's count starts over
+        std::fs::write(&path, r#"{"day":1,"calls":{"news":5}}"#).unwrap();
+        let quota = SteamQuota::load(path.clone(), BUDGET);
+        assert_eq!(quota.usage().total_calls, 0);
+        assert!(!quota.prefers_cache());
+        let _ = std::fs::remove_file(&path);
+    }
+}
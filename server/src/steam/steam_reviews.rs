@@ -0,0 +1,65 @@
+//! This module deals with the user review summary of an app.
+
+use library::ReviewSummary;
+use serde::Deserialize;
+
+use crate::steam::steam_client::SteamClient;
+use crate::steam::steam_client::SteamError;
+
+/// The Steam store "appreviews" endpoint, the app id is part of the path
+const ENDPOINT_APP_REVIEWS: &str = "https://store.steampowered.com/appreviews";
+
+/// Helper structs used during deserializing the API response.
+#[derive(Debug, Deserialize)]
+struct AppReviewsResponse {
+    query_summary: Option<QuerySummary>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct QuerySummary {
+    #[serde(default)]
+    review_score: u8,
+    #[serde(default)]
+    review_score_desc: String,
+    #[serde(default)]
+    total_positive: u64,
+    #[serde(default)]
+    total_reviews: u64,
+}
+
+impl From<AppReviewsResponse> for ReviewSummary {
+    fn from(value: AppReviewsResponse) -> Self {
+        let summary = value.query_summary.unwrap_or_default();
+        let positive_ratio = if summary.total_reviews == 0 {
+            0.0
+        } else {
+            summary.total_positive as f32 / summary.total_reviews as f32
+        };
+
+        ReviewSummary {
+            total_reviews: summary.total_reviews,
+            positive_ratio,
+            review_score: summary.review_score,
+            description: summary.review_score_desc,
+        }
+    }
+}
+
+impl SteamClient {
+    pub async fn get_review_summary(&self, app_id: u64) -> Result<ReviewSummary, SteamError> {
+        let response = self
+            .get_store_request(
+                &format!("{ENDPOINT_APP_REVIEWS}/{app_id}"),
+                vec![
+                    ("json", "1"),
+                    ("language", "all"),
+                    ("purchase_type", "all"),
+                    // Only the summary is wanted, not the reviews themselves
+                    ("num_per_page", "0"),
+                ],
+            )
+            .await?;
+
+        self.parse_response::<AppReviewsResponse, ReviewSummary>(response)
+    }
+}
@@ -0,0 +1,239 @@
+//! How `SteamClient` reaches Steam: over HTTP with reqwest, or from a file of recorded
+//! responses so the server can run without network access.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::steam::steam_client::SteamError;
+
+/// How the server reaches Steam, see `open`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    Http,
+    Record,
+    Replay,
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "http" => Ok(Self::Http),
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            other => Err(format!(
+                "unknown transport {other}, expected http, record or replay"
+            )),
+        }
+    }
+}
+
+/// `Http` calls Steam, `Record` also saves every response to `recordings` and `Replay` only
+/// serves them back
+pub fn open(kind: TransportKind, recordings: &Path) -> Box<dyn SteamTransport> {
+    match kind {
+        TransportKind::Http => Box::new(ReqwestTransport::default()),
+        TransportKind::Record => Box::new(RecordReplayTransport::open(
+            recordings.to_owned(),
+            RecordReplayMode::Record,
+        )),
+        TransportKind::Replay => Box::new(RecordReplayTransport::open(
+            recordings.to_owned(),
+            RecordReplayMode::Replay,
+        )),
+    }
+}
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A raw response, before `SteamClient` checks its status and parses its body
+#[derive(Debug, Clone)]
+pub struct TransportResponse {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+/// Sends a GET request to a Steam endpoint. Errors are for requests that never got a
+/// response; HTTP error statuses come back as a `TransportResponse`.
+pub trait SteamTransport: Send + Sync {
+    fn get<'a>(
+        &'a self,
+        endpoint: &'a str,
+        query: &'a [(String, String)],
+    ) -> BoxFuture<'a, Result<TransportResponse, SteamError>>;
+}
+
+#[derive(Debug, Default)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl SteamTransport for ReqwestTransport {
+    fn get<'a>(
+        &'a self,
+        endpoint: &'a str,
+        query: &'a [(String, String)],
+    ) -> BoxFuture<'a, Result<TransportResponse, SteamError>> {
+        Box::pin(async move {
+            log::debug!("New request : {}", request_key(endpoint, query));
+            let request = self.client.get(endpoint).query(query).build()?;
+
+            let response = self.client.execute(request).await.map_err(|_| {
+                SteamError::FailedRequest("Something went wrong with your request".to_string())
+            })?;
+            Ok(TransportResponse {
+                status: response.status(),
+                body: response.text().await?,
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    body: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordReplayMode {
+    /// Calls Steam and saves every response
+    Record,
+    /// Only serves saved responses, never calls Steam
+    Replay,
+}
+
+/// Saves responses to a JSON file keyed by endpoint and query, or serves them back from it.
+/// The API key is never part of the key nor written to the file.
+pub struct RecordReplayTransport {
+    mode: RecordReplayMode,
+    path: PathBuf,
+    inner: ReqwestTransport,
+    responses: Mutex<BTreeMap<String, RecordedResponse>>,
+}
+
+impl RecordReplayTransport {
+    pub fn open(path: PathBuf, mode: RecordReplayMode) -> Self {
+        let responses = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Self {
+            mode,
+            path,
+            inner: ReqwestTransport::default(),
+            responses: Mutex::new(responses),
+        }
+    }
+
+    fn save(&self, responses: &BTreeMap<String, RecordedResponse>) {
+        let written = serde_json::to_string_pretty(responses)
+            .map_err(|error| error.to_string())
+            .and_then(|json| std::fs::write(&self.path, json).map_err(|error| error.to_string()));
+        if let Err(error) = written {
+            log::warn!(
+                "Can't save recorded responses to {}: {error}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+impl SteamTransport for RecordReplayTransport {
+    fn get<'a>(
+        &'a self,
+        endpoint: &'a str,
+        query: &'a [(String, String)],
+    ) -> BoxFuture<'a, Result<TransportResponse, SteamError>> {
+        Box::pin(async move {
+            let key = request_key(endpoint, query);
+
+            if self.mode == RecordReplayMode::Replay {
+                let responses = self.responses.lock().unwrap();
+                let recorded = responses.get(&key).ok_or_else(|| {
+                    SteamError::FailedRequest(format!("No recorded response for {key}"))
+                })?;
+                return Ok(TransportResponse {
+                    status: StatusCode::from_u16(recorded.status)
+                        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                    body: recorded.body.clone(),
+                });
+            }
+
+            let response = self.inner.get(endpoint, query).await?;
+            let mut responses = self.responses.lock().unwrap();
+            responses.insert(
+                key,
+                RecordedResponse {
+                    status: response.status.as_u16(),
+                    body: response.body.clone(),
+                },
+            );
+            self.save(&responses);
+            Ok(response)
+        })
+    }
+}
+
+/// Identifies a request by endpoint and query, leaving the API key out
+pub fn request_key(endpoint: &str, query: &[(String, String)]) -> String {
+    let params: Vec<String> = query
+        .iter()
+        .filter(|(name, _)| name != "key")
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    format!("{endpoint}?{}", params.join("&"))
+}
+
+/// Serves canned responses by endpoint and records the requests, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct FakeTransport {
+    responses: Mutex<std::collections::HashMap<String, TransportResponse>>,
+    /// Shared so tests can still look at it once the transport is handed to a `SteamClient`
+    pub requests: std::sync::Arc<Mutex<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl FakeTransport {
+    pub fn respond(&self, endpoint: &str, status: StatusCode, body: &str) {
+        self.responses.lock().unwrap().insert(
+            endpoint.to_owned(),
+            TransportResponse {
+                status,
+                body: body.to_owned(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+impl SteamTransport for FakeTransport {
+    fn get<'a>(
+        &'a self,
+        endpoint: &'a str,
+        query: &'a [(String, String)],
+    ) -> BoxFuture<'a, Result<TransportResponse, SteamError>> {
+        Box::pin(async move {
+            self.requests
+                .lock()
+                .unwrap()
+                .push(request_key(endpoint, query));
+            self.responses
+                .lock()
+                .unwrap()
+                .get(endpoint)
+                .cloned()
+                .ok_or_else(|| SteamError::FailedRequest("Connection refused".to_owned()))
+        })
+    }
+}
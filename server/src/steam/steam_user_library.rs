@@ -1,14 +1,39 @@
 //! This module deals with a user's games library.
 
-use library::Game;
+use library::{Game, PlatformPlaytime};
 use serde::Deserialize;
 use std::fmt::Formatter;
 
 use crate::steam::steam_client::SteamClient;
 use crate::steam::steam_client::SteamError;
+use crate::steam::steam_endpoint::{QueryParams, SteamEndpoint};
 
 /// The Steam API "GetOwnedGames (v0001)" endpoint
-const ENDPOINT_OWNED_GAMES: &str = "http://api.steampowered.com/IPlayerService/GetOwnedGames/v1";
+struct GetOwnedGames<'a> {
+    steam_id: String,
+    /// Only report these apps, or the whole library when empty
+    appids_filter: &'a [u64],
+}
+
+impl SteamEndpoint for GetOwnedGames<'_> {
+    const INTERFACE: &'static str = "IPlayerService";
+    const METHOD: &'static str = "GetOwnedGames";
+    const VERSION: u32 = 1;
+    type Response = SteamUserLibrary;
+
+    fn params(&self) -> QueryParams {
+        QueryParams::new()
+            .param("steamid", &self.steam_id)
+            .flag("include_appinfo", true)
+            .flag("include_played_free_games", true)
+            .list("appids_filter", self.appids_filter)
+    }
+
+    /// Ownership checks of a shortlist are made right after someone buys a game
+    fn cacheable(&self) -> bool {
+        self.appids_filter.is_empty()
+    }
+}
 
 // How to get icon:
 // https://media.steampowered.com/steamcommunity/public/images/apps/{appid}/{hash}.jpg
@@ -30,28 +55,13 @@ const ENDPOINT_OWNED_GAMES: &str = "http://api.steampowered.com/IPlayerService/G
 }
 */
 
-/// Helper struct used during deserializing the API response.
-#[derive(Debug, Deserialize)]
-struct OwnedGamesResponse {
-    response: Option<SteamUserLibrary>,
-}
-
 #[derive(Debug, Default, Deserialize)]
 pub struct SteamUserLibrary {
     pub game_count: u32,
+    #[serde(default)]
     pub games: Vec<SteamGame>,
 }
 
-impl From<OwnedGamesResponse> for SteamUserLibrary {
-    fn from(value: OwnedGamesResponse) -> Self {
-        let v = value.response.unwrap_or_default();
-        Self {
-            game_count: v.game_count,
-            games: v.games,
-        }
-    }
-}
-
 #[derive(Debug, Deserialize)]
 pub struct SteamGame {
     #[serde(rename(deserialize = "appid"))]
@@ -59,6 +69,15 @@ pub struct SteamGame {
     pub name: String,
     #[serde(rename(deserialize = "playtime_forever"))]
     pub total_playtime: u64,
+    #[serde(default)]
+    pub playtime_windows_forever: u64,
+    #[serde(default)]
+    pub playtime_mac_forever: u64,
+    #[serde(default)]
+    pub playtime_linux_forever: u64,
+    #[serde(default)]
+    pub playtime_deck_forever: u64,
+    #[serde(default)]
     pub img_icon_url: String,
 }
 
@@ -80,22 +99,41 @@ impl std::fmt::Display for SteamGame {
 
 impl SteamClient {
     pub async fn get_user_library(&self, steam_id: &str) -> Result<SteamUserLibrary, SteamError> {
-        // ?key=YOUR_API_KEY&steamid=USER_ID&include_appinfo=1&include_played_free_games=1
-
-        let response = self
-            .get_request(
-                ENDPOINT_OWNED_GAMES,
-                vec![
-                    ("steamid", steam_id),
-                    ("include_appInfo", "1"),
-                    ("include_played_free_games", "1"),
-                ],
-            )
-            .await?;
-
-        let games = self.parse_response::<OwnedGamesResponse, SteamUserLibrary>(response)?;
-
-        Ok(games)
+        self.call(&GetOwnedGames {
+            steam_id: steam_id.to_owned(),
+            appids_filter: &[],
+        })
+        .await
+    }
+}
+
+impl SteamClient {
+    /// Checks which of `app_ids` each of `steam_ids` owns, with one GetOwnedGames call per
+    /// user restricted through `appids_filter`. Much cheaper than pulling whole libraries
+    /// when a room only cares about a shortlist. These calls always reach Steam, bypassing the
+    /// response cache. Users whose library can't be read map to `None`.
+    pub async fn get_owned_games_filtered(
+        &self,
+        steam_ids: &[u64],
+        app_ids: &[u64],
+    ) -> Vec<(u64, Option<Vec<SteamGame>>)> {
+        let mut owned = Vec::with_capacity(steam_ids.len());
+        for &steam_id in steam_ids {
+            let request = GetOwnedGames {
+                steam_id: steam_id.to_string(),
+                appids_filter: app_ids,
+            };
+            let games = match self.call(&request).await {
+                Ok(library) => Some(library.games),
+                Err(error) => {
+                    log::warn!("Can't check owned games of {steam_id}: {error}");
+                    None
+                }
+            };
+            owned.push((steam_id, games));
+        }
+
+        owned
     }
 }
 
@@ -105,6 +143,17 @@ impl From<SteamGame> for Game {
             id: 0,
             app_id: game.app_id,
             name: game.name,
+            playtime_minutes: game.total_playtime,
+            installed: false,
+            size_on_disk: None,
+            platform_playtime: PlatformPlaytime {
+                windows: game.playtime_windows_forever,
+                mac: game.playtime_mac_forever,
+                linux: game.playtime_linux_forever,
+                deck: game.playtime_deck_forever,
+            },
+            app_type: Default::default(),
+            dlc: Vec::new(),
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,490 @@
+//! Keeps the `AppModel` in a SQLite file so a restart doesn't end an evening's rooms.
+//!
+//! Customers and their libraries, rooms, the decisions they reach and the counters are written
+//! as they change, in order, by a writer thread: whoever holds the model only queues the write
+//! and never waits on SQLite, save to read back a customer dropped from memory. On startup the
+//! schema is brought up to date by `MIGRATIONS`, then customers and the rooms still open are
+//! loaded back into memory.
+//!
+//! Rooms are stored without their members' libraries, which live once in the `libraries`
+//! table and are put back in the room on load.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, mpsc};
+
+use library::{Customer, Game, Room};
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::{is_same_customer, unix_now};
+
+/// Schema changes, applied in order. A database at `PRAGMA user_version` N has had the first N
+/// applied. Add new ones at the end, never edit one that has shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: customers, libraries, rooms and counters
+    "CREATE TABLE customers (
+        key TEXT PRIMARY KEY,
+        customer TEXT NOT NULL
+    );
+    CREATE TABLE libraries (
+        customer_key TEXT PRIMARY KEY REFERENCES customers (key),
+        games TEXT NOT NULL
+    );
+    CREATE TABLE rooms (
+        id INTEGER PRIMARY KEY,
+        room TEXT NOT NULL,
+        closed_at INTEGER
+    );
+    CREATE TABLE counters (
+        name TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );",
+    // 2: games rooms settled on
+    "CREATE TABLE session_results (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        room_id INTEGER NOT NULL REFERENCES rooms (id),
+        app_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        members TEXT NOT NULL,
+        decided_at INTEGER NOT NULL
+    );",
+    // 3: why rooms were closed
+    "ALTER TABLE rooms ADD COLUMN close_reason TEXT;",
+    // 4: join passwords, kept apart from the room sent to clients
+    "ALTER TABLE rooms ADD COLUMN password_hash TEXT;",
+];
+
+/// A write waiting for the writer thread
+type QueuedWrite = Box<dyn FnOnce(&mut Connection) + Send>;
+
+pub struct Storage {
+    connection: Arc<Mutex<Connection>>,
+    writes: mpsc::Sender<QueuedWrite>,
+}
+
+impl fmt::Debug for Storage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Storage").finish_non_exhaustive()
+    }
+}
+
+/// Why a room was closed, kept with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// By its host, or through `DELETE /api/rooms/{id}`
+    Closed,
+    /// Nothing happened in it for `rooms.idle_timeout_secs`
+    Idle,
+    /// It was open for `rooms.max_lifetime_secs`
+    MaxLifetime,
+}
+
+impl CloseReason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Idle => "idle",
+            Self::MaxLifetime => "max_lifetime",
+        }
+    }
+}
+
+/// Everything loaded back on startup
+#[derive(Debug, Default)]
+pub struct StoredModel {
+    pub customers: Vec<Customer>,
+    pub rooms: Vec<Room>,
+    /// Password hashes of the open rooms that have one, see `room_access::hash_password`
+    pub room_passwords: HashMap<u64, String>,
+    pub counter: u64,
+    pub next_room_id: u64,
+}
+
+impl Storage {
+    /// Opens the database at `path`. If it can't be opened the server still runs, but
+    /// forgets everything on restart.
+    pub fn open_or_in_memory(path: &Path) -> Self {
+        Self::open(path).unwrap_or_else(|error| {
+            log::error!(
+                "Can't open the database {}, nothing will be kept: {error}",
+                path.display()
+            );
+            Self::in_memory()
+        })
+    }
+
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        Self::migrated(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> Self {
+        Connection::open_in_memory()
+            .and_then(Self::migrated)
+            .expect("an in-memory database can always be created")
+    }
+
+    fn migrated(mut connection: Connection) -> rusqlite::Result<Self> {
+        migrate(&mut connection)?;
+        let connection = Arc::new(Mutex::new(connection));
+
+        let (writes, queued) = mpsc::channel::<QueuedWrite>();
+        let writer_connection = connection.clone();
+        std::thread::spawn(move || {
+            for write in queued {
+                write(&mut writer_connection.lock().unwrap());
+            }
+        });
+
+        Ok(Self { connection, writes })
+    }
+
+    pub fn load(&self) -> rusqlite::Result<StoredModel> {
+        self.flush();
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection.prepare(
+            "SELECT customers.customer, libraries.games
+             FROM customers LEFT JOIN libraries ON libraries.customer_key = customers.key",
+        )?;
+        let customers = statement
+            .query_map([], |row| {
+                let customer: String = row.get(0)?;
+                let games: Option<String> = row.get(1)?;
+                Ok((customer, games))
+            })?
+            .filter_map(|row| {
+                let (customer, games) = row.ok()?;
+                let mut customer: Customer = from_json(&customer)?;
+                customer.games = games.as_deref().and_then(from_json).unwrap_or_default();
+                Some(customer)
+            })
+            .collect::<Vec<_>>();
+
+        let mut room_passwords = HashMap::new();
+        let mut statement =
+            connection.prepare("SELECT room, password_hash FROM rooms WHERE closed_at IS NULL")?;
+        let rooms = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })?
+            .filter_map(|row| {
+                let (room, password_hash) = row.ok()?;
+                let room = from_json::<Room>(&room)?;
+                if let Some(password_hash) = password_hash {
+                    room_passwords.insert(room.id, password_hash);
+                }
+                Some(room)
+            })
+            .map(|mut room| {
+                for member in &mut room.customers {
+                    if let Some(customer) = customers
+                        .iter()
+                        .find(|c| is_same_customer(c, member.steam_id, &member.steam_name))
+                    {
+                        *member = Customer {
+                            verified: member.verified,
+                            ..customer.clone()
+                        };
+                    }
+                }
+                room
+            })
+            .collect();
+
+        let counter = |name: &str| -> rusqlite::Result<u64> {
+            let value: Option<i64> = connection
+                .query_row(
+                    "SELECT value FROM counters WHERE name = ?1",
+                    params![name],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(value.unwrap_or(0) as u64)
+        };
+
+        Ok(StoredModel {
+            customers,
+            rooms,
+            room_passwords,
+            counter: counter("counter")?,
+            next_room_id: counter("next_room_id")?,
+        })
+    }
+
+    /// Reads a customer and their library back, for those no longer kept in memory. Waits for
+    /// the queued writes first, see `flush`.
+    pub fn load_customer(&self, steam_id: Option<u64>, steam_name: &str) -> Option<Customer> {
+        self.flush();
+        let connection = self.connection.lock().unwrap();
+        let (customer, games) = connection
+            .query_row(
+                "SELECT customers.customer, libraries.games
+                 FROM customers LEFT JOIN libraries ON libraries.customer_key = customers.key
+                 WHERE customers.key = ?1",
+                params![customer_key(steam_id, steam_name)],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+            )
+            .optional()
+            .map_err(|error| log::warn!("Can't read the customer {steam_name}: {error}"))
+            .ok()??;
+        let mut customer: Customer = from_json(&customer)?;
+        customer.games = games.as_deref().and_then(from_json).unwrap_or_default();
+        Some(customer)
+    }
+
+    /// Writes the customer's profile and library, replacing the previous copy
+    pub fn save_customer(&self, customer: &Customer) {
+        let key = customer_key(customer.steam_id, &customer.steam_name);
+        let profile = Customer {
+            games: Vec::new(),
+            stale: None,
+            ..customer.clone()
+        };
+        let games = customer.games.clone();
+        self.write("customer", move |transaction| {
+            transaction.execute(
+                "INSERT OR REPLACE INTO customers (key, customer) VALUES (?1, ?2)",
+                params![key, to_json(&profile)],
+            )?;
+            transaction.execute(
+                "INSERT OR REPLACE INTO libraries (customer_key, games) VALUES (?1, ?2)",
+                params![key, to_json::<Vec<Game>>(&games)],
+            )?;
+            Ok(())
+        });
+    }
+
+    pub fn save_room(&self, room: &Room) {
+        let mut room = room.clone();
+        for member in room.customers.iter_mut().chain(&mut room.spectators) {
+            member.games = Vec::new();
+        }
+        self.write("room", move |transaction| {
+            transaction.execute(
+                "INSERT INTO rooms (id, room) VALUES (?1, ?2)
+                 ON CONFLICT (id) DO UPDATE SET room = excluded.room",
+                params![room.id as i64, to_json(&room)],
+            )?;
+            Ok(())
+        });
+    }
+
+    pub fn set_room_password(&self, room_id: u64, password_hash: Option<&str>) {
+        let password_hash = password_hash.map(str::to_owned);
+        self.write("room password", move |transaction| {
+            transaction.execute(
+                "UPDATE rooms SET password_hash = ?2 WHERE id = ?1",
+                params![room_id as i64, password_hash],
+            )?;
+            Ok(())
+        });
+    }
+
+    /// Archives the room as it was last: kept for its history but no longer loaded on startup
+    pub fn close_room(&self, room: &Room, reason: CloseReason) {
+        self.save_room(room);
+        let room_id = room.id;
+        self.write("room closing", move |transaction| {
+            transaction.execute(
+                "UPDATE rooms SET closed_at = ?2, close_reason = ?3 WHERE id = ?1",
+                params![room_id as i64, unix_now() as i64, reason.as_str()],
+            )?;
+            Ok(())
+        });
+    }
+
+    /// Records the game a room settled on, and who was there
+    pub fn save_session_result(&self, room: &Room, app_id: u64, name: &str) {
+        let members: Vec<String> = room
+            .customers
+            .iter()
+            .map(|c| c.steam_name.clone())
+            .collect();
+        let room_id = room.id;
+        let name = name.to_owned();
+        self.write("session result", move |transaction| {
+            transaction.execute(
+                "INSERT INTO session_results (room_id, app_id, name, members, decided_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    room_id as i64,
+                    app_id as i64,
+                    name,
+                    to_json(&members),
+                    unix_now() as i64
+                ],
+            )?;
+            Ok(())
+        });
+    }
+
+    pub fn save_counter(&self, name: &str, value: u64) {
+        let name = name.to_owned();
+        self.write("counter", move |transaction| {
+            transaction.execute(
+                "INSERT OR REPLACE INTO counters (name, value) VALUES (?1, ?2)",
+                params![name, value as i64],
+            )?;
+            Ok(())
+        });
+    }
+
+    /// Waits until every queued write is done. Blocks, so async code calls it from
+    /// `spawn_blocking`.
+    pub fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        let queued = self.writes.send(Box::new(move |_| {
+            let _ = done.send(());
+        }));
+        if queued.is_ok() {
+            let _ = flushed.recv();
+        }
+    }
+
+    /// Queues the writes, to be run in a transaction on the writer thread. A failed write is
+    /// logged rather than failing the request: the in-memory model stays authoritative until
+    /// the next restart.
+    fn write(
+        &self,
+        what: &'static str,
+        write: impl FnOnce(&Transaction) -> rusqlite::Result<()> + Send + 'static,
+    ) {
+        let queued = self.writes.send(Box::new(move |connection| {
+            let written = connection.transaction().and_then(|transaction| {
+                write(&transaction)?;
+                transaction.commit()
+            });
+            if let Err(error) = written {
+                log::warn!("Can't save the {what}: {error}");
+            }
+        }));
+        if queued.is_err() {
+            log::warn!("Can't save the {what}: the storage writer stopped");
+        }
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > MIGRATIONS.len() {
+        log::warn!(
+            "The database is at schema version {version}, newer than this server ({})",
+            MIGRATIONS.len()
+        );
+    }
+
+    for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", applied + 1)?;
+        transaction.commit()?;
+        log::info!("Database migrated to schema version {}", applied + 1);
+    }
+    Ok(())
+}
+
+/// Customers are known by their Steam ID, or by name for those who uploaded local files
+fn customer_key(steam_id: Option<u64>, steam_name: &str) -> String {
+    match steam_id {
+        Some(steam_id) => steam_id.to_string(),
+        None => format!("name:{steam_name}"),
+    }
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> String {
+    serde_json::to_string(value).expect("model types always serialize")
+}
+
+fn from_json<T: DeserializeOwned>(json: &str) -> Option<T> {
+    serde_json::from_str(json)
+        .map_err(|error| log::warn!("Skipping unreadable stored data: {error}"))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use library::ContentLimits;
+
+    fn customer(steam_id: u64, name: &str, app_ids: &[u64]) -> Customer {
+        Customer {
+            steam_name: name.to_owned(),
+            steam_id: Some(steam_id),
+            games: app_ids
+                .iter()
+                .map(|&app_id| {
+                    serde_json::from_value(serde_json::json!({
+                        "id": app_id,
+                        "app_id": app_id,
+                        "name": format!("Game {app_id}"),
+                    }))
+                    .unwrap()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rooms_come_back_with_their_members_libraries() {
+        let storage = Storage::in_memory();
+        let alice = customer(1, "Alice", &[10, 20]);
+        storage.save_customer(&alice);
+
+        let mut room = Room::new(7, ContentLimits::default());
+        room.customers.push(alice);
+        storage.save_room(&room);
+        storage.close_room(&Room::new(8, ContentLimits::default()), CloseReason::Idle);
+        storage.save_counter("next_room_id", 8);
+        storage.set_room_password(7, Some("hash"));
+
+        let stored = storage.load().unwrap();
+        assert_eq!(stored.next_room_id, 8);
+        assert_eq!(stored.rooms.len(), 1);
+        assert_eq!(stored.rooms[0].id, 7);
+        assert_eq!(stored.rooms[0].customers[0].games.len(), 2);
+        assert_eq!(stored.room_passwords[&7], "hash");
+    }
+
+    #[test]
+    fn customers_are_read_back_one_at_a_time() {
+        let storage = Storage::in_memory();
+        storage.save_customer(&customer(1, "Alice", &[10, 20]));
+
+        let alice = storage.load_customer(Some(1), "Renamed").unwrap();
+        assert_eq!(alice.steam_name, "Alice");
+        assert_eq!(alice.games.len(), 2);
+        assert!(storage.load_customer(Some(2), "Alice").is_none());
+        assert!(storage.load_customer(None, "Alice").is_none());
+    }
+
+    #[test]
+    fn migrations_are_applied_once() {
+        let path = std::env::temp_dir().join(format!("storage-test-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        Storage::open(&path)
+            .unwrap()
+            .save_customer(&customer(1, "Alice", &[10]));
+        let reopened = Storage::open(&path).unwrap();
+        let version: usize = reopened
+            .connection
+            .lock()
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+
+        assert_eq!(version, MIGRATIONS.len());
+        assert_eq!(reopened.load().unwrap().customers.len(), 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}